@@ -0,0 +1,161 @@
+//! Where the blueprints of a command come from and where the results go.
+
+use std::{
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+/// Input of a single blueprint.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Source {
+    /// Given directly on the command line.
+    Text(String),
+    Stdin,
+    File(PathBuf),
+}
+
+/// Blueprint (or json, yaml, ...) and where it came from.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Entry {
+    /// Shown in error messages, e.g. the file name or the line of a list.
+    pub name: String,
+    pub path: Option<PathBuf>,
+    pub content: String,
+}
+
+impl Source {
+    /// `-` stands for stdin.
+    pub fn from_argument(argument: &str) -> Self {
+        match argument {
+            "-" => Source::Stdin,
+            _ => Source::Text(argument.into()),
+        }
+    }
+
+    /// Like [`Source::from_argument`], but an argument which names an existing file is read
+    /// from that file.
+    pub fn detect(argument: &str) -> Self {
+        match Path::new(argument).is_file() {
+            true => Source::File(argument.into()),
+            false => Source::from_argument(argument),
+        }
+    }
+
+    pub fn read(&self) -> io::Result<Entry> {
+        match self {
+            Source::Text(text) => Ok(Entry {
+                name: "argument".into(),
+                path: None,
+                content: text.clone(),
+            }),
+            Source::Stdin => Ok(Entry {
+                name: "stdin".into(),
+                path: None,
+                content: read_stdin()?,
+            }),
+            Source::File(path) => Ok(Entry {
+                name: path.display().to_string(),
+                path: Some(path.clone()),
+                content: fs::read_to_string(path)
+                    .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?,
+            }),
+        }
+    }
+}
+
+/// Entries of a batch: all files of a directory (sorted by name) or the non-empty lines of a
+/// file or of stdin (`-`). Files of a directory which cannot be read fail on their own.
+pub fn batch(path: &str) -> io::Result<Vec<io::Result<Entry>>> {
+    if path == "-" {
+        return Ok(lines(&read_stdin()?).into_iter().map(Ok).collect());
+    }
+
+    let path = Path::new(path);
+    if !path.is_dir() {
+        return Ok(lines(&fs::read_to_string(path)?)
+            .into_iter()
+            .map(Ok)
+            .collect());
+    }
+
+    let mut files = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
+        if path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files
+        .into_iter()
+        .map(|file| Source::File(file).read())
+        .collect())
+}
+
+fn lines(list: &str) -> Vec<Entry> {
+    list.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| Entry {
+            name: format!("line {}", i + 1),
+            path: None,
+            content: line.trim().into(),
+        })
+        .collect()
+}
+
+fn read_stdin() -> io::Result<String> {
+    let mut input = String::new();
+    io::stdin().read_to_string(&mut input)?;
+    Ok(input)
+}
+
+/// File of the result of an entry inside of the output directory of a batch. The extension is
+/// appended to the whole file name, so files which only differ in their extension stay apart.
+pub fn output_file(directory: &Path, entry: &Entry, index: usize, extension: &str) -> PathBuf {
+    let name = match entry.path.as_ref().and_then(|p| p.file_name()) {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => format!("{:04}", index + 1),
+    };
+    directory.join(format!("{}.{}", name, extension))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_and_directories_are_split_into_entries() {
+        let entries = lines("0abc\n\n  0def  \n");
+        let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(vec!["line 1", "line 3"], names);
+        assert_eq!("0def", entries[1].content);
+
+        let directory = std::env::temp_dir().join(format!("batch-{}", std::process::id()));
+        fs::create_dir_all(directory.join("nested")).unwrap();
+        fs::write(directory.join("b.txt"), "0b").unwrap();
+        fs::write(directory.join("a.json"), "{}").unwrap();
+        fs::write(directory.join("a.txt"), "0a").unwrap();
+        fs::write(directory.join("c.txt"), [0xff, 0xfe]).unwrap();
+        let entries = batch(directory.to_str().unwrap()).unwrap();
+        assert_eq!(4, entries.len());
+        assert!(entries[3].is_err());
+        let entries: Vec<Entry> = entries.into_iter().take(3).map(Result::unwrap).collect();
+        assert_eq!("{}", entries[0].content);
+        let out = directory.join("out");
+        assert_eq!(
+            out.join("a.json.txt"),
+            output_file(&out, &entries[0], 0, "txt")
+        );
+        assert_eq!(
+            out.join("a.txt.txt"),
+            output_file(&out, &entries[1], 1, "txt")
+        );
+        assert_eq!(
+            Path::new("out/0003.json"),
+            output_file(Path::new("out"), &lines("x")[0], 2, "json")
+        );
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
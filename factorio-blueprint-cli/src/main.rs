@@ -1,63 +1,889 @@
-use factorio_blueprint::{blueprint_string_to_model, model_to_blueprint_string, blueprint_string_to_pretty_json};
-use clap::{App, Arg};
+mod entries;
 
-enum Command {
-    Decode(String),
-    ReEncode(String),
+use std::{error::Error, fmt, fs, io, path::{Path, PathBuf}, process::ExitCode};
+
+use factorio_blueprint::{
+    abstract_model::{replace::ReplaceError, validation::Severity, Blueprint}, blueprint_string_to_pretty_json, blueprint_string_to_raw_model,
+    check_raw_model, diff, merge::{self, MergeConflicts}, model::{BlueprintContainer, Signal},
+    model_to_blueprint_string, query::{self, Assignment, QueryError, Selector},
+    raw_model_to_blueprint_string, raw_model_to_pretty_json,
+    render::{self, RenderOptions}, stats, BlueprintError,
+};
+use clap::{App, AppSettings, Arg, ArgMatches};
+
+use entries::{Entry, Source};
+
+#[derive(Debug)]
+enum CliError {
+    Blueprint(BlueprintError),
+    Io(io::Error),
+    Parse(String),
+    Argument(String),
+    Batch { failed: usize, total: usize },
+    Conflicts(MergeConflicts),
+
+    /// Report of a validation with errors.
+    Invalid { errors: usize, report: String },
+    Query(QueryError),
+    Replace(ReplaceError),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Blueprint(cause) => write!(f, "{}", cause),
+            Self::Io(cause) => write!(f, "{}", cause),
+            Self::Parse(cause) => write!(f, "invalid blueprint: {}", cause),
+            Self::Argument(cause) => write!(f, "{}", cause),
+            Self::Batch { failed, total } => write!(f, "{} of {} entries failed", failed, total),
+            Self::Query(cause) => write!(f, "{}", cause),
+            Self::Replace(cause) => write!(f, "{}", cause),
+            Self::Invalid { errors, report } => write!(f, "{} validation errors\n{}", errors, report),
+            Self::Conflicts(report) => write!(f, "{} merge conflicts\n{}", report.conflicts.len(), report),
+        }
+    }
+}
+
+impl Error for CliError {}
+
+impl From<BlueprintError> for CliError {
+    fn from(e: BlueprintError) -> Self {
+        CliError::Blueprint(e)
+    }
+}
+
+impl From<QueryError> for CliError {
+    fn from(e: QueryError) -> Self {
+        CliError::Query(e)
+    }
+}
+
+impl From<ReplaceError> for CliError {
+    fn from(e: ReplaceError) -> Self {
+        CliError::Replace(e)
+    }
+}
+
+impl From<io::Error> for CliError {
+    fn from(e: io::Error) -> Self {
+        CliError::Io(e)
+    }
+}
+
+type Result<T> = core::result::Result<T, CliError>;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum Format {
+    Json,
+    Yaml,
+}
+
+enum Kind {
+    Decode,
+    ReEncode,
+    Encode(Option<Format>),
+    /// Compares `old` with the input.
+    Diff { old: Source, json: bool },
+
+    /// Merges the input as theirs. The merged blueprint is written as a blueprint string
+    /// without a format.
+    Merge { base: Source, ours: Source, format: Option<Format> },
+    Render(RenderOptions),
+
+    /// Text preview, coloured with ANSI escape codes if set.
+    Show(bool),
+
+    /// Statistics as json if set, otherwise as a table.
+    Stats(bool),
+
+    /// Diagnostics as json if set, otherwise one per line.
+    Validate(bool),
+
+    /// Lists the matching entities, as json if set.
+    Query(Selector, bool),
+
+    /// The result is written as a blueprint string without a format.
+    Set(Selector, Vec<Assignment>, Option<Format>),
+
+    /// Replaces signals or entity names, limited to the ids if given. The result is written
+    /// like by `Set`.
+    Replace { target: Replace, ids: Option<Vec<usize>>, format: Option<Format> },
+}
+
+enum Replace {
+    Signal(Signal, Signal),
+    Entity(String, String),
+}
+
+#[derive(Debug)]
+enum Input {
+    Single(Source),
+
+    /// Directory, list file or `-` for a list on stdin.
+    Batch(String),
+}
+
+/// Where the input of a command comes from and where its result goes.
+#[derive(Debug)]
+struct Io {
+    input: Input,
+
+    /// File, or directory in batch mode. Results are printed without one.
+    output: Option<PathBuf>,
+}
+
+struct Command {
+    kind: Kind,
+    io: Io,
+}
+
+/// Adds the options for reading the input (`input` is the last positional argument) and
+/// writing the result.
+fn with_io<'a>(app: App<'a>, input: Arg<'a>) -> App<'a> {
+    let name = input.get_name();
+    with_files(app.arg(input), &[name])
 }
 
-fn parse_arguments() -> Option<Command> {
+/// Adds the options for reading the input from files and writing the result. `positional`
+/// are the arguments which give the input directly.
+fn with_files<'a>(app: App<'a>, positional: &[&'a str]) -> App<'a> {
+    let mut batch_conflicts = positional.to_vec();
+    batch_conflicts.push("input");
+    app.arg(Arg::new("input")
+            .long("input")
+            .short('i')
+            .takes_value(true)
+            .conflicts_with_all(positional)
+            .help("Reads the input from a file, - reads it from stdin"))
+        .arg(Arg::new("output")
+            .long("output")
+            .short('o')
+            .takes_value(true)
+            .help("Writes the result to a file, or into a directory in batch mode"))
+        .arg(Arg::new("batch")
+            .long("batch")
+            .takes_value(true)
+            .conflicts_with_all(&batch_conflicts)
+            .help("Processes every file of a directory or every line of a file (- for stdin) and reports errors per entry"))
+}
+
+fn blueprint_string() -> Arg<'static> {
+    Arg::new("blueprint-string")
+        .help("Factorio blueprint string as exported from the game, - reads it from stdin (the default)")
+}
+
+fn parse_arguments() -> Command {
     let matches = App::new("JP Factorio Blueprint CLI")
         .version("0.1.0")
         .about("Factorio Blueprint Toolbox")
-        .subcommand(App::new("reencode")
-            .about("Decodes a blueprint string and then reencodes it")
-            .arg(Arg::new("blueprint-string")
-                .help("Factorio blueprint string as exported from the game")
-                .required(true)
-                .index(1)))
-        .subcommand(App::new("decode")
-            .about("Decodes a blueprint string and outputs the json")
-            .arg(Arg::new("blueprint-string")
-                .help("Factorio blueprint string as exported from the game")
-                .required(true)
-                .index(1)))
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(with_io(App::new("reencode")
+            .about("Decodes a blueprint string and then reencodes it"),
+            blueprint_string()))
+        .subcommand(with_io(App::new("decode")
+            .about("Decodes a blueprint string and outputs the json"),
+            blueprint_string()))
+        .subcommand(with_io(App::new("encode")
+            .about("Encodes json or yaml, e.g. an edited output of decode, as a blueprint string")
+            .arg(Arg::new("format")
+                .long("format")
+                .possible_values(["json", "yaml"])
+                .help("Format of the input, by default detected from the file extension or the content")),
+            Arg::new("file")
+                .help("File with the blueprint, - reads it from stdin (the default)")))
+        .subcommand(with_io(App::new("diff")
+            .about("Compares two blueprints, matching entities by name and position")
+            .after_help("In batch mode, old is compared with every entry.")
+            .arg(Arg::new("old")
+                .help("Blueprint string, json or yaml file, or - for stdin")
+                .required(true))
+            .arg(Arg::new("format")
+                .long("format")
+                .possible_values(["text", "json"])
+                .default_value("text")
+                .help("Output format")),
+            Arg::new("new")
+                .help("Blueprint string, json or yaml file, - reads it from stdin (the default)")))
+        .subcommand(with_io(App::new("render")
+            .about("Draws a blueprint string, json or yaml as SVG")
+            .arg(Arg::new("tile-size")
+                .long("tile-size")
+                .default_value("32")
+                .help("Pixels per tile"))
+            .arg(Arg::new("no-tiles")
+                .long("no-tiles")
+                .help("Leaves out floor tiles like concrete")),
+            Arg::new("blueprint")
+                .help("Blueprint string, json or yaml file, - reads it from stdin (the default)")))
+        .subcommand(with_io(App::new("show")
+            .about("Prints a blueprint string, json or yaml as a grid of characters")
+            .arg(Arg::new("colour")
+                .long("colour")
+                .help("Colours entities on red and green wire networks")),
+            Arg::new("blueprint")
+                .help("Blueprint string, json or yaml file, - reads it from stdin (the default)")))
+        .subcommand(with_io(App::new("stats")
+            .about("Counts entities, materials, wires, circuit networks and combinator operations")
+            .arg(Arg::new("format")
+                .long("format")
+                .possible_values(["text", "json"])
+                .default_value("text")
+                .help("Output format")),
+            Arg::new("blueprint")
+                .help("Blueprint string, json or yaml file, - reads it from stdin (the default)")))
+        .subcommand(with_io(App::new("validate")
+            .about("Checks a blueprint for overlaps, wires out of reach, invalid filters and broken connections")
+            .after_help("Fails if there are errors, warnings are only reported.")
+            .arg(Arg::new("format")
+                .long("format")
+                .possible_values(["text", "json"])
+                .default_value("text")
+                .help("Output format")),
+            Arg::new("blueprint")
+                .help("Blueprint string, json or yaml file, - reads it from stdin (the default)")))
+        .subcommand(with_io(App::new("query")
+            .about("Lists the entities matching a selector, e.g. 'name=decider-combinator and condition.first_signal=signal-R'")
+            .after_help(SELECTOR_HELP)
+            .arg(Arg::new("selector")
+                .help("Conditions joined with 'and' or 'or'")
+                .required(true))
+            .arg(Arg::new("format")
+                .long("format")
+                .possible_values(["text", "json"])
+                .default_value("text")
+                .help("Output format, json shows all fields")),
+            Arg::new("blueprint")
+                .help("Blueprint string, json or yaml file, - reads it from stdin (the default)")))
+        .subcommand(with_files(App::new("set")
+            .about("Changes fields of the entities matching a selector and reencodes the blueprint")
+            .after_help(SELECTOR_HELP)
+            .arg(Arg::new("selector")
+                .help("Conditions joined with 'and' or 'or'")
+                .required(true))
+            .arg(Arg::new("assignments")
+                .help("Fields and their new values, e.g. condition.constant=7. Use replace to change the name")
+                .multiple_values(true)
+                .required(true))
+            .arg(Arg::new("format")
+                .long("format")
+                .possible_values(["string", "json", "yaml"])
+                .default_value("string")
+                .help("Output format of the changed blueprint")),
+            &[]))
+        .subcommand(with_files(App::new("replace")
+            .about("Replaces every use of a signal or every entity of a name and reencodes the blueprint")
+            .after_help("Signals are written as virtual:name, item:name or fluid:name, names starting with signal- are virtual without a type. \
+Entities keep their top left tile, the replacement fails if they would overlap another entity or the size of an entity is unknown.")
+            .arg(Arg::new("what")
+                .possible_values(["signal", "entity"])
+                .help("Whether signals or entities are replaced")
+                .required(true))
+            .arg(Arg::new("from")
+                .help("Signal or entity name to replace, e.g. medium-electric-pole")
+                .required(true))
+            .arg(Arg::new("to")
+                .help("Signal or entity name to replace it with, e.g. substation")
+                .required(true))
+            .arg(Arg::new("ids")
+                .long("ids")
+                .takes_value(true)
+                .use_delimiter(true)
+                .help("Comma separated entity numbers to limit the replacement to, signal icons are kept then"))
+            .arg(Arg::new("format")
+                .long("format")
+                .possible_values(["string", "json", "yaml"])
+                .default_value("string")
+                .help("Output format of the changed blueprint")),
+            &[]))
+        .subcommand(with_io(App::new("merge")
+            .about("Merges the changes of two blueprints to a common base or reports their conflicts")
+            .after_help("As a git merge driver: merge %O %A %B -o %A\n\
+The output is left unchanged on conflicts. In batch mode, every entry is merged as theirs.")
+            .arg(Arg::new("base")
+                .help("Blueprint string, json or yaml file, or - for stdin")
+                .required(true))
+            .arg(Arg::new("ours")
+                .help("Blueprint string, json or yaml file, or - for stdin")
+                .required(true))
+            .arg(Arg::new("format")
+                .long("format")
+                .possible_values(["string", "json", "yaml"])
+                .default_value("string")
+                .help("Output format of the merged blueprint")),
+            Arg::new("theirs")
+                .help("Blueprint string, json or yaml file, - reads it from stdin (the default)")))
         .get_matches();
 
     match matches.subcommand() {
-        Some(("decode", args)) => args
-            .value_of("blueprint-string")
-            .map(|b| Command::Decode(b.into())),
-        Some(("reencode", args)) => args
-            .value_of("blueprint-string")
-            .map(|b| Command::ReEncode(b.into())),
+        Some(("decode", args)) => Command { kind: Kind::Decode, io: io_of(args, "blueprint-string") },
+        Some(("reencode", args)) => Command { kind: Kind::ReEncode, io: io_of(args, "blueprint-string") },
+        Some(("encode", args)) => {
+            let format = match args.value_of("format") {
+                Some("json") => Some(Format::Json),
+                Some("yaml") => Some(Format::Yaml),
+                _ => None,
+            };
+            Command { kind: Kind::Encode(format), io: io_of(args, "file") }
+        },
+        Some(("diff", args)) => {
+            let old = Source::detect(args.value_of("old").unwrap_or("-"));
+            let kind = Kind::Diff { old, json: args.value_of("format") == Some("json") };
+            Command { kind, io: io_of(args, "new") }
+        },
+        Some(("render", args)) => {
+            let tile_size = args.value_of_t("tile-size").unwrap_or_else(|e| e.exit());
+            let options = RenderOptions { tile_size, tiles: !args.is_present("no-tiles") };
+            Command { kind: Kind::Render(options), io: io_of(args, "blueprint") }
+        },
+        Some(("show", args)) => {
+            Command { kind: Kind::Show(args.is_present("colour")), io: io_of(args, "blueprint") }
+        },
+        Some(("stats", args)) => {
+            let json = args.value_of("format") == Some("json");
+            Command { kind: Kind::Stats(json), io: io_of(args, "blueprint") }
+        },
+        Some(("validate", args)) => {
+            let json = args.value_of("format") == Some("json");
+            Command { kind: Kind::Validate(json), io: io_of(args, "blueprint") }
+        },
+        Some(("query", args)) => {
+            let kind = Kind::Query(selector(args), args.value_of("format") == Some("json"));
+            Command { kind, io: io_of(args, "blueprint") }
+        },
+        Some(("set", args)) => {
+            let assignments = args.values_of("assignments").into_iter().flatten()
+                .map(|a| a.parse().unwrap_or_else(exit_with))
+                .collect();
+            let kind = Kind::Set(selector(args), assignments, output_format(args));
+            Command { kind, io: io_of(args, "") }
+        },
+        Some(("replace", args)) => {
+            let (from, to) = (args.value_of("from").unwrap_or_default(), args.value_of("to").unwrap_or_default());
+            let target = match args.value_of("what") {
+                Some("signal") => Replace::Signal(
+                    from.parse().map_err(CliError::Argument).unwrap_or_else(exit_with),
+                    to.parse().map_err(CliError::Argument).unwrap_or_else(exit_with),
+                ),
+                _ => Replace::Entity(from.into(), to.into()),
+            };
+            let ids = args.values_of("ids").map(|ids| ids.map(entity_id).collect());
+            let kind = Kind::Replace { target, ids, format: output_format(args) };
+            Command { kind, io: io_of(args, "") }
+        },
+        Some(("merge", args)) => {
+            let source = |name| Source::detect(args.value_of(name).unwrap_or("-"));
+            let kind = Kind::Merge { base: source("base"), ours: source("ours"), format: output_format(args) };
+            Command { kind, io: io_of(args, "theirs") }
+        },
+        _ => unreachable!("a subcommand is required"),
+    }
+}
+
+const SELECTOR_HELP: &str = "Selectors compare fields of the blueprint json with =, !=, <, <=, > or >=. \
+The fields of the control behaviour are moved up and conditions are called 'condition', \
+e.g. condition.constant or filters.signal. Signals are compared by name.";
+
+fn selector(args: &ArgMatches) -> Selector {
+    args.value_of("selector").unwrap_or_default().parse().unwrap_or_else(exit_with)
+}
+
+/// `None` for blueprint strings.
+fn output_format(args: &ArgMatches) -> Option<Format> {
+    match args.value_of("format") {
+        Some("json") => Some(Format::Json),
+        Some("yaml") => Some(Format::Yaml),
         _ => None,
     }
 }
 
-fn reencode(blueprint: &str) -> factorio_blueprint::Result<()> {
-    let model = blueprint_string_to_model(blueprint)?;
-    let reencoded = model_to_blueprint_string(model)?;
+/// Id of an entity number as shown in the blueprint json.
+fn entity_id(number: &str) -> usize {
+    match number.trim().parse::<usize>() {
+        Ok(number) if number > 0 => number - 1,
+        _ => exit_with(CliError::Argument(format!("invalid entity number '{}'", number))),
+    }
+}
 
-    println!("{}", reencoded);
+fn exit_with<E: Into<CliError>, T>(e: E) -> T {
+    eprintln!("error: {}", e.into());
+    std::process::exit(2)
+}
 
-    Ok(())
+/// `positional` is the argument which gives the input directly, empty if there is none.
+fn io_of(args: &ArgMatches, positional: &str) -> Io {
+    let value = match positional {
+        "" => None,
+        _ => args.value_of(positional),
+    };
+    let input = match (args.value_of("batch"), args.value_of("input"), value) {
+        (Some(batch), _, _) => Input::Batch(batch.into()),
+        (_, Some("-"), _) | (_, None, Some("-")) | (_, None, None) => Input::Single(Source::Stdin),
+        (_, Some(file), _) => Input::Single(Source::File(file.into())),
+        (_, _, Some(value)) if positional == "file" => Input::Single(Source::File(value.into())),
+        (_, _, Some(value)) if positional == "blueprint-string" => Input::Single(Source::from_argument(value)),
+        (_, _, Some(value)) => Input::Single(Source::detect(value)),
+    };
+    Io { input, output: args.value_of("output").map(PathBuf::from) }
 }
 
-fn decode(blueprint: &str) -> factorio_blueprint::Result<()> {
-    let json = blueprint_string_to_pretty_json(blueprint)?;
-    println!("{}", json);
+/// Applies `command` to the input and writes the result, in batch mode to every entry.
+/// `extension` is the file extension of the results in the output directory of a batch.
+fn run<F>(io: &Io, extension: &str, command: F) -> Result<()>
+where
+    F: Fn(&Entry) -> Result<String>,
+{
+    let path = match &io.input {
+        Input::Single(source) => {
+            let entry = source.read()?;
+            return write(io.output.as_deref(), &command(&entry)?);
+        }
+        Input::Batch(path) => path,
+    };
+
+    let entries = entries::batch(path)?;
+    if let Some(directory) = &io.output {
+        fs::create_dir_all(directory)?;
+    }
+    let mut failed = 0;
+    for (i, entry) in entries.iter().enumerate() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                eprintln!("{}", e);
+                failed += 1;
+                continue;
+            },
+        };
+        let output = io.output.as_ref()
+            .map(|directory| entries::output_file(directory, entry, i, extension));
+        if let Err(e) = command(entry).and_then(|result| write(output.as_deref(), &result)) {
+            eprintln!("{}: {}", entry.name, e);
+            failed += 1;
+        }
+    }
 
+    match failed {
+        0 => Ok(()),
+        _ => Err(CliError::Batch { failed, total: entries.len() }),
+    }
+}
+
+fn write(path: Option<&Path>, result: &str) -> Result<()> {
+    match path {
+        Some(path) => fs::write(path, format!("{}\n", result))?,
+        None => println!("{}", result),
+    }
     Ok(())
 }
 
-fn main() -> factorio_blueprint::Result<()> {
-    match parse_arguments() {
-        Some(Command::ReEncode(b)) => reencode(&b),
-        Some(Command::Decode(b)) => decode(&b),
-        _ => {
-            eprintln!("Unknown command");
-            Ok(())
+fn reencode(blueprint: &str) -> Result<String> {
+    let raw_model = blueprint_string_to_raw_model(blueprint)?;
+    check_raw_model(&raw_model)?;
+    Ok(model_to_blueprint_string(Blueprint::from(raw_model))?)
+}
+
+fn decode(blueprint: &str) -> Result<String> {
+    Ok(blueprint_string_to_pretty_json(blueprint)?)
+}
+
+fn encode(entry: &Entry, format: Option<Format>) -> Result<String> {
+    let format = format
+        .or_else(|| entry.path.as_deref().and_then(format_of_path))
+        .unwrap_or_else(|| format_of_content(&entry.content));
+    encode_str(&entry.content, format)
+}
+
+/// Parses the blueprint and checks it before encoding it.
+fn encode_str(input: &str, format: Format) -> Result<String> {
+    Ok(raw_model_to_blueprint_string(&parse(input, format)?)?)
+}
+
+fn parse(input: &str, format: Format) -> Result<BlueprintContainer> {
+    let raw_model: BlueprintContainer = match format {
+        Format::Json => serde_json::from_str(input).map_err(|e| CliError::Parse(e.to_string()))?,
+        Format::Yaml => serde_yaml::from_str(input).map_err(|e| CliError::Parse(e.to_string()))?,
+    };
+    check_raw_model(&raw_model)?;
+    Ok(raw_model)
+}
+
+/// Loads a blueprint string or, like `encode`, json or yaml.
+fn load(entry: &Entry) -> Result<Blueprint> {
+    Ok(Blueprint::try_from_raw(load_raw(entry)?)?)
+}
+
+fn load_raw(entry: &Entry) -> Result<BlueprintContainer> {
+    let content = entry.content.trim();
+    match content.starts_with('0') && !content.contains(char::is_whitespace) {
+        true => {
+            let raw_model = blueprint_string_to_raw_model(content)?;
+            check_raw_model(&raw_model)?;
+            Ok(raw_model)
         },
+        false => {
+            let format = entry.path.as_deref().and_then(format_of_path)
+                .unwrap_or_else(|| format_of_content(content));
+            parse(content, format)
+        },
+    }
+}
+
+/// Writes a blueprint string, json or yaml.
+fn serialise(raw_model: &BlueprintContainer, format: Option<Format>) -> Result<String> {
+    match format {
+        None => Ok(raw_model_to_blueprint_string(raw_model)?),
+        Some(Format::Json) => Ok(raw_model_to_pretty_json(raw_model)?),
+        Some(Format::Yaml) => serde_yaml::to_string(raw_model)
+            .map(|yaml| yaml.trim_end().into())
+            .map_err(|e| CliError::Parse(e.to_string())),
+    }
+}
+
+/// Loads the blueprints which are given besides the input of a command.
+fn load_sources(sources: &[&Source], io: &Io) -> Result<Vec<Blueprint>> {
+    let input = match &io.input {
+        Input::Single(source) => source == &Source::Stdin,
+        Input::Batch(path) => path == "-",
+    };
+    if sources.iter().filter(|&&s| s == &Source::Stdin).count() + input as usize > 1 {
+        return Err(CliError::Parse("only one blueprint can be read from stdin".into()));
+    }
+
+    sources.iter().map(|s| load(&s.read()?)).collect()
+}
+
+fn diff(old: &Blueprint, new: &Entry, json: bool) -> Result<String> {
+    let diff = diff::diff(old, &load(new)?);
+    match json {
+        true => serde_json::to_string_pretty(&diff).map_err(|e| CliError::Parse(e.to_string())),
+        false => Ok(diff.to_string().trim_end().into()),
+    }
+}
+
+fn merge(base: &Blueprint, ours: &Blueprint, theirs: &Entry, format: Option<Format>) -> Result<String> {
+    let merged = merge::merge(base, ours, &load(theirs)?).map_err(CliError::Conflicts)?;
+    serialise(&merged.into(), format)
+}
+
+fn query(entry: &Entry, selector: &Selector, json: bool) -> Result<String> {
+    let raw_model = load_raw(entry)?;
+    let entities = raw_model.blueprint.entities.iter().filter(|e| selector.matches(e));
+    match json {
+        true => serde_json::to_string_pretty(&entities.map(query::view).collect::<Vec<_>>())
+            .map_err(|e| CliError::Parse(e.to_string())),
+        false => Ok(entities
+            .map(|e| format!("{} {} at ({}, {})", e.entity_number, e.name, e.position.x, e.position.y))
+            .collect::<Vec<_>>()
+            .join("\n")),
+    }
+}
+
+fn set(entry: &Entry, selector: &Selector, assignments: &[Assignment], format: Option<Format>) -> Result<String> {
+    let mut raw_model = load_raw(entry)?;
+    let changed = query::set(&mut raw_model.blueprint, selector, assignments)?;
+    check_raw_model(&raw_model)?;
+    eprintln!("changed {} entities", changed.len());
+    serialise(&raw_model, format)
+}
+
+fn replace(entry: &Entry, target: &Replace, ids: Option<&[usize]>, format: Option<Format>) -> Result<String> {
+    let mut blueprint = load(entry)?;
+    match target {
+        Replace::Signal(from, to) => {
+            let replaced = blueprint.replace_signal(from, to, ids)?;
+            eprintln!("replaced {} uses of {}", replaced, from.name);
+        },
+        Replace::Entity(from, to) => {
+            let replaced = blueprint.replace_entity(from, to, ids)?;
+            eprintln!("replaced {} entities", replaced.len());
+        },
+    }
+    serialise(&blueprint.into(), format)
+}
+
+fn statistics(entry: &Entry, json: bool) -> Result<String> {
+    let stats = stats::stats(&load(entry)?);
+    match json {
+        true => serde_json::to_string_pretty(&stats).map_err(|e| CliError::Parse(e.to_string())),
+        false => Ok(stats.to_string().trim_end().into()),
+    }
+}
+
+fn validate(entry: &Entry, json: bool) -> Result<String> {
+    let diagnostics = load(entry)?.validate();
+    let report = match json {
+        true => serde_json::to_string_pretty(&diagnostics).map_err(|e| CliError::Parse(e.to_string()))?,
+        false => diagnostics.iter().map(|d| d.to_string()).collect::<Vec<_>>().join("\n"),
+    };
+
+    match diagnostics.iter().filter(|d| d.severity == Severity::Error).count() {
+        0 => Ok(report),
+        errors => Err(CliError::Invalid { errors, report }),
+    }
+}
+
+/// File extension of results in the given format, blueprint strings are text.
+fn extension(format: Option<Format>) -> &'static str {
+    match format {
+        Some(Format::Json) => "json",
+        Some(Format::Yaml) => "yaml",
+        None => "txt",
+    }
+}
+
+fn format_of_path(path: &Path) -> Option<Format> {
+    match path.extension()?.to_str()? {
+        "json" => Some(Format::Json),
+        "yaml" | "yml" => Some(Format::Yaml),
+        _ => None,
+    }
+}
+
+/// JSON starts with a brace, everything else is treated as YAML.
+fn format_of_content(input: &str) -> Format {
+    match input.trim_start().starts_with('{') {
+        true => Format::Json,
+        false => Format::Yaml,
+    }
+}
+
+fn execute(command: &Command) -> Result<()> {
+    match command.kind {
+        Kind::Decode => run(&command.io, "json", |e| decode(e.content.trim())),
+        Kind::ReEncode => run(&command.io, "txt", |e| reencode(e.content.trim())),
+        Kind::Encode(format) => run(&command.io, "txt", |e| encode(e, format)),
+        Kind::Diff { ref old, json } => {
+            let old = load_sources(&[old], &command.io)?.remove(0);
+            run(&command.io, if json { "json" } else { "txt" }, |e| diff(&old, e, json))
+        },
+        Kind::Render(ref options) => run(&command.io, "svg", |e| Ok(render::render(&load(e)?, options).trim_end().into())),
+        Kind::Show(colour) => run(&command.io, "txt", |e| Ok(render::text(&load(e)?, colour).trim_end().into())),
+        Kind::Stats(json) => run(&command.io, if json { "json" } else { "txt" }, |e| statistics(e, json)),
+        Kind::Validate(json) => run(&command.io, if json { "json" } else { "txt" }, |e| validate(e, json)),
+        Kind::Query(ref selector, json) => run(&command.io, "txt", |e| query(e, selector, json)),
+        Kind::Set(ref selector, ref assignments, format) =>
+            run(&command.io, extension(format), |e| set(e, selector, assignments, format)),
+        Kind::Replace { ref target, ref ids, format } =>
+            run(&command.io, extension(format), |e| replace(e, target, ids.as_deref(), format)),
+        Kind::Merge { ref base, ref ours, format } => {
+            let blueprints = load_sources(&[base, ours], &command.io)?;
+            run(&command.io, extension(format), |e| merge(&blueprints[0], &blueprints[1], e, format))
+        },
+    }
+}
+
+fn main() -> ExitCode {
+    match execute(&parse_arguments()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use factorio_blueprint::{abstract_model::Entity, model::Direction};
+
+    const YAML: &str = "
+blueprint:
+  item: blueprint
+  version: 281479275151360
+  icons: []
+  entities:
+    - entity_number: 1
+      name: medium-electric-pole
+      position: { x: 0.5, y: 0.5 }
+      neighbours: [2]
+    - entity_number: 2
+      name: medium-electric-pole
+      position: { x: 5.5, y: 0.5 }
+      neighbours: [1]
+";
+
+    #[test]
+    fn json_and_yaml_are_encoded() {
+        assert_eq!(Format::Yaml, format_of_content(YAML));
+        let string = encode_str(YAML, Format::Yaml).unwrap();
+        let raw_model = blueprint_string_to_raw_model(&string).unwrap();
+        assert_eq!(2, raw_model.blueprint.entities.len());
+
+        let json = serde_json::to_string(&raw_model).unwrap();
+        assert_eq!(Format::Json, format_of_content(&json));
+        assert_eq!(string, encode_str(&json, Format::Json).unwrap());
+        assert_eq!(Some(Format::Yaml), format_of_path(Path::new("board.yml")));
+    }
+
+    #[test]
+    fn batches_report_errors_per_entry() {
+        let string = encode_str(YAML, Format::Yaml).unwrap();
+        let directory = std::env::temp_dir().join(format!("cli-batch-{}", std::process::id()));
+        let list = directory.join("list.txt");
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(&list, format!("{}\nnot a blueprint\n\n{}\n", string, string)).unwrap();
+
+        let io = Io {
+            input: Input::Batch(list.display().to_string()),
+            output: Some(directory.join("out")),
+        };
+        let result = run(&io, "txt", |e| reencode(&e.content));
+        assert!(matches!(result, Err(CliError::Batch { failed: 1, total: 3 })));
+        let written = fs::read_to_string(directory.join("out").join("0003.txt")).unwrap();
+        assert_eq!(format!("{}\n", string), written);
+        assert!(!directory.join("out").join("0002.txt").exists());
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn diffs_compare_strings_and_files() {
+        let old = Source::Text(encode_str(YAML, Format::Yaml).unwrap());
+        let moved = YAML.replace("x: 5.5", "x: 6.5");
+        let new = Entry { name: "new".into(), path: None, content: moved.clone() };
+        assert_eq!(2, load(&new).unwrap().entities.len());
+
+        let stdin = Io { input: Input::Single(Source::Stdin), output: None };
+        assert!(matches!(load_sources(&[&Source::Stdin], &stdin), Err(CliError::Parse(_))));
+        let old = load_sources(&[&old], &stdin).unwrap().remove(0);
+
+        let file = std::env::temp_dir().join(format!("cli-diff-{}.yaml", std::process::id()));
+        fs::write(&file, moved).unwrap();
+        let text = diff(&old, &Source::detect(file.to_str().unwrap()).read().unwrap(), false).unwrap();
+        assert_eq!(
+            "- medium-electric-pole at (5.5, 0.5)\n\
+             + medium-electric-pole at (6.5, 0.5)\n\
+             - copper wire medium-electric-pole at (0.5, 0.5) -- medium-electric-pole at (5.5, 0.5)\n\
+             + copper wire medium-electric-pole at (0.5, 0.5) -- medium-electric-pole at (6.5, 0.5)",
+            text
+        );
+        let unchanged = Entry { name: "old".into(), path: None, content: YAML.into() };
+        let json = diff(&old, &unchanged, true).unwrap();
+        assert_eq!(serde_json::json!({ "changes": [] }), serde_json::from_str::<serde_json::Value>(&json).unwrap());
+        fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn merges_report_conflicts() {
+        let entry = |yaml: String| Entry { name: "yaml".into(), path: None, content: yaml };
+        let base = load(&entry(YAML.into())).unwrap();
+        let moved = entry(YAML.replace("x: 5.5", "x: 6.5"));
+
+        let merged = merge(&base, &base, &moved, Some(Format::Yaml)).unwrap();
+        assert!(merged.contains("x: 6.5"), "{}", merged);
+        assert!(merge(&base, &load(&moved).unwrap(), &moved, None).unwrap().starts_with('0'));
+
+        let tiled = |tile: &str| {
+            entry(format!("{}  tiles:\n    - {{ name: {}, position: {{ x: 0, y: 0 }} }}\n", YAML, tile))
+        };
+        match merge(&base, &load(&tiled("concrete")).unwrap(), &tiled("landfill"), None) {
+            Err(CliError::Conflicts(report)) => assert_eq!("tile at (0, 0): added in ours, added in theirs\n", report.to_string()),
+            result => panic!("unexpected {:?}", result),
+        }
+    }
+
+    #[test]
+    fn blueprints_are_rendered() {
+        let entry = Entry { name: "yaml".into(), path: None, content: YAML.into() };
+        let svg = render::render(&load(&entry).unwrap(), &RenderOptions::default());
+        assert_eq!(2, svg.matches("medium-electric-pole at").count());
+        assert_eq!(1, svg.matches("<line").count());
+    }
+
+    #[test]
+    fn statistics_are_tables_or_json() {
+        let entry = Entry { name: "yaml".into(), path: None, content: YAML.into() };
+        let json: serde_json::Value = serde_json::from_str(&statistics(&entry, true).unwrap()).unwrap();
+        assert_eq!(2, json["entities"]["medium-electric-pole"]);
+        assert_eq!(1, json["wires"]["copper"]);
+        assert_eq!(6, json["width"]);
+        assert!(statistics(&entry, false).unwrap().starts_with("size"));
+    }
+
+    #[test]
+    fn validation_fails_on_errors() {
+        let entry = |yaml: String| Entry { name: "yaml".into(), path: None, content: yaml };
+        assert_eq!("", validate(&entry(YAML.into()), false).unwrap());
+
+        let far = YAML.replace("x: 5.5", "x: 15.5");
+        match validate(&entry(far.clone()), false) {
+            Err(CliError::Invalid { errors: 1, report }) => assert!(report.starts_with("error[wire-reach] entity 1:"), "{}", report),
+            result => panic!("unexpected {:?}", result),
+        }
+        match validate(&entry(far), true) {
+            Err(CliError::Invalid { report, .. }) => assert!(report.contains("\"check\": \"wire-reach\"")),
+            result => panic!("unexpected {:?}", result),
+        }
+    }
+
+    #[test]
+    fn north_facing_combinators_have_no_direction() {
+        let yaml = format!("{}{}", YAML, "    - entity_number: 3
+      name: decider-combinator
+      position: { x: 2.5, y: 1 }
+      control_behavior:
+        decider_conditions: { first_signal: { type: virtual, name: signal-A }, constant: 0, comparator: \">\", output_signal: { type: virtual, name: signal-A }, copy_count_from_input: true }
+");
+        let entry = Entry { name: "yaml".into(), path: None, content: yaml };
+        assert!(matches!(load(&entry).unwrap().entities[2], Entity::DeciderCombinator { direction: Direction::North, .. }));
+    }
+
+    #[test]
+    fn combinators_without_control_behaviour_do_not_panic() {
+        let entry = |name: &str| {
+            let yaml = format!("{}    - {{ entity_number: 3, name: {}, position: {{ x: 2.5, y: 0.5 }} }}\n", YAML, name);
+            Entry { name: "yaml".into(), path: None, content: yaml }
+        };
+        assert_eq!("", validate(&entry("constant-combinator"), false).unwrap());
+        assert!(statistics(&entry("constant-combinator"), false).is_ok());
+        match load(&entry("decider-combinator")) {
+            Err(CliError::Blueprint(BlueprintError::MissingCondition { entity: 3 })) => {},
+            result => panic!("unexpected {:?}", result),
+        }
+    }
+
+    #[test]
+    fn entities_are_queried_and_set() {
+        let entry = Entry { name: "yaml".into(), path: None, content: YAML.into() };
+        let right: Selector = "position.x>1".parse().unwrap();
+        assert_eq!("2 medium-electric-pole at (5.5, 0.5)", query(&entry, &right, false).unwrap());
+
+        let moved = set(&entry, &right, &["position.x=6.5".parse().unwrap()], Some(Format::Yaml)).unwrap();
+        let entry = Entry { content: moved, ..entry };
+        let json: serde_json::Value = serde_json::from_str(&query(&entry, &right, true).unwrap()).unwrap();
+        assert_eq!(6.5, json[0]["position"]["x"]);
+        assert_eq!(1, json.as_array().unwrap().len());
+    }
+
+    #[test]
+    fn entities_are_replaced() {
+        let entry = Entry { name: "yaml".into(), path: None, content: YAML.into() };
+        let target = Replace::Entity("medium-electric-pole".into(), "substation".into());
+        let replaced = replace(&entry, &target, Some(&[1]), Some(Format::Yaml)).unwrap();
+        let names: Vec<_> = load(&Entry { content: replaced, ..entry }).unwrap()
+            .entities.iter().map(|e| e.name().to_string()).collect();
+        assert_eq!(vec!["medium-electric-pole", "substation"], names);
+
+        let entry = Entry { name: "yaml".into(), path: None, content: YAML.into() };
+        assert!(matches!(
+            replace(&entry, &target, Some(&[5]), None),
+            Err(CliError::Replace(ReplaceError::InvalidId(5)))
+        ));
+    }
+
+    #[test]
+    fn invalid_blueprints_are_rejected() {
+        assert!(matches!(encode_str("{\"blueprint\": {}}", Format::Json), Err(CliError::Parse(_))));
+
+        let unknown = YAML.replace("neighbours: [1]", "neighbours: [3]");
+        assert!(matches!(
+            encode_str(&unknown, Format::Yaml),
+            Err(CliError::Blueprint(BlueprintError::UnknownEntity { from: 2, to: 3 }))
+        ));
+        let numbers = YAML.replace("entity_number: 2", "entity_number: 4");
+        assert!(matches!(
+            encode_str(&numbers, Format::Yaml),
+            Err(CliError::Blueprint(BlueprintError::InvalidEntityNumber { expected: 2, found: 4 }))
+        ));
     }
 }
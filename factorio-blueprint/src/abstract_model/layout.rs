@@ -0,0 +1,413 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeSet, BinaryHeap},
+};
+
+use crate::model::Position;
+
+use super::{
+    network::{CircuitNetwork, NetworkId},
+    prototype::{distance, position_on_tile, Tile},
+    utility::Result,
+    Blueprint, Connector, Entity, PoleType, Side,
+};
+
+// A wire that has to be relayed over a pole costs as much as this many tiles of wire.
+const RELAY_PENALTY: f32 = 100.0;
+
+/// Connection points of the entities placed so far, per circuit network.
+type PlacedPoints = Vec<Vec<(Position, f32)>>;
+
+impl Blueprint {
+    /// Places all entities on a compact grid, ignoring their current positions.
+    /// The blueprint is treated as a netlist: directions of the entities are kept, and
+    /// entities which share a circuit network are placed close to each other.
+    ///
+    /// Afterwards every circuit network is rewired as a spanning tree over its connectors,
+    /// relaying wires over poles of `pole_type` where they would be out of reach, and
+    /// power poles are placed. Pole connections of the netlist are replaced in the process.
+    ///
+    /// Returns the ids of the newly placed poles.
+    pub fn layout(&mut self, pole_type: PoleType) -> Result<Vec<usize>> {
+        let networks = self.circuit_networks();
+        let mut nets: Vec<Vec<(Side, NetworkId)>> = vec![Vec::new(); self.entities.len()];
+        for network in &networks.networks {
+            for c in &network.connectors {
+                nets[c.id].push((c.side, network.id));
+            }
+        }
+
+        let grid = Grid::new(self, pole_type);
+        let mut occupied = BTreeSet::new();
+        let mut points: PlacedPoints = vec![Vec::new(); networks.networks.len()];
+        let mut placed: Vec<usize> = Vec::new();
+
+        for id in self.placement_order(&nets, &networks.networks) {
+            let size = self.entities[id].footprint().unwrap_or((1, 1));
+
+            // Partners in the smallest networks come first, a bus can be joined from anywhere
+            let mut own = nets[id].clone();
+            own.sort_by_key(|&(_, n)| (networks.networks[n].connectors.len(), n));
+            let mut mates: Vec<usize> = Vec::new();
+            for (_, network) in own {
+                let partner = placed
+                    .iter()
+                    .rev()
+                    .find(|&&other| other != id && nets[other].iter().any(|&(_, n)| n == network));
+                if let Some(&partner) = partner {
+                    if !mates.contains(&partner) {
+                        mates.push(partner);
+                    }
+                }
+            }
+            mates.truncate(4);
+
+            // Only if there is no space within reach of the partners, wires are relayed
+            let mut best: Option<(f32, Tile)> = None;
+            for scale in 1..=2 {
+                for tile in grid.candidates(self, &mates, size, scale) {
+                    if !grid.is_free(tile, size, &occupied) {
+                        continue;
+                    }
+
+                    *self.entities[id].position_mut() = position_on_tile(tile, size);
+                    let cost = self.placement_cost(id, &nets[id], &points);
+                    let better = best.is_none_or(|(c, t)| {
+                        cost < c || (cost == c && (tile.1, tile.0) < (t.1, t.0))
+                    });
+                    if better {
+                        best = Some((cost, tile));
+                    }
+                }
+                if best.is_some() {
+                    break;
+                }
+            }
+
+            let tile = match best {
+                Some((_, tile)) => tile,
+                None => grid.first_free(size, &occupied),
+            };
+
+            let entity = &mut self.entities[id];
+            *entity.position_mut() = position_on_tile(tile, size);
+            occupied.extend(entity.tiles());
+            let reach = entity.wire_reach().unwrap_or(0.0);
+            for &(side, network) in &nets[id] {
+                points[network].push((entity.connection_point(side), reach));
+            }
+            placed.push(id);
+        }
+
+        for entity in &mut self.entities {
+            entity.set_wires(Vec::new());
+            if let Entity::ElectricPole { neighbours, .. } = entity {
+                neighbours.clear();
+            }
+        }
+
+        let mut poles = Vec::new();
+        for network in &networks.networks {
+            for (c1, c2) in self.spanning_tree(&network.connectors) {
+                if self.within_reach(&c1, &c2) {
+                    self.connect_wire_with_side(c1, c2, network.wire)?;
+                } else {
+                    poles.extend(self.route_wire(c1, c2, network.wire, pole_type)?);
+                }
+            }
+        }
+
+        let powered: Vec<usize> = self
+            .entities
+            .iter()
+            .filter(|e| !matches!(e, Entity::ElectricPole { .. } | Entity::Unknown(_)))
+            .map(Entity::id)
+            .collect();
+        poles.extend(self.place_power_poles(&powered, pole_type)?);
+
+        Ok(poles)
+    }
+
+    /// Grows groups of connected entities from the lowest id, always continuing with an
+    /// entity that shares the smallest network with the ones placed before. This keeps the
+    /// entities of small networks together, busses are extended as they come along.
+    fn placement_order(
+        &self,
+        nets: &[Vec<(Side, NetworkId)>],
+        networks: &[CircuitNetwork],
+    ) -> Vec<usize> {
+        let mut visited = vec![false; self.entities.len()];
+        let mut order = Vec::with_capacity(self.entities.len());
+        let mut heap = BinaryHeap::new();
+        let mut discovered = 0;
+
+        for start in 0..self.entities.len() {
+            heap.push(Reverse((0, discovered, start)));
+            while let Some(Reverse((_, _, id))) = heap.pop() {
+                if visited[id] {
+                    continue;
+                }
+
+                visited[id] = true;
+                order.push(id);
+                for &(_, network) in &nets[id] {
+                    let connectors = &networks[network].connectors;
+                    for c in connectors.iter().filter(|c| !visited[c.id]) {
+                        discovered += 1;
+                        heap.push(Reverse((connectors.len(), discovered, c.id)));
+                    }
+                }
+            }
+        }
+
+        order
+    }
+
+    /// Length of the wires from the entity at its current position to the closest placed
+    /// connector of each of its networks. Wires that are out of reach are penalised.
+    fn placement_cost(&self, id: usize, nets: &[(Side, NetworkId)], points: &PlacedPoints) -> f32 {
+        let entity = &self.entities[id];
+        let reach = entity.wire_reach().unwrap_or(0.0);
+
+        let mut cost = 0.0;
+        for &(side, network) in nets {
+            let point = entity.connection_point(side);
+            let closest = points[network]
+                .iter()
+                .map(|(p, r)| (distance(&point, p), r.min(reach)))
+                .min_by(|a, b| a.0.total_cmp(&b.0));
+            if let Some((length, max_length)) = closest {
+                cost += length;
+                if length > max_length {
+                    cost += RELAY_PENALTY;
+                }
+            }
+        }
+
+        cost
+    }
+
+    fn within_reach(&self, c1: &Connector, c2: &Connector) -> bool {
+        let (e1, e2) = (&self.entities[c1.id], &self.entities[c2.id]);
+        let reach = e1
+            .wire_reach()
+            .unwrap_or(0.0)
+            .min(e2.wire_reach().unwrap_or(0.0));
+        distance(&e1.connection_point(c1.side), &e2.connection_point(c2.side)) <= reach
+    }
+
+    /// Minimum spanning tree over the connection points of the connectors (Prim's algorithm).
+    /// Every returned pair connects a new connector to the tree.
+    fn spanning_tree(&self, connectors: &[Connector]) -> Vec<(Connector, Connector)> {
+        let point = |c: &Connector| self.entities[c.id].connection_point(c.side);
+        let points: Vec<Position> = connectors.iter().map(point).collect();
+
+        let mut edges = Vec::new();
+        if points.is_empty() {
+            return edges;
+        }
+
+        let mut closest: Vec<Option<(f32, usize)>> = vec![None; points.len()];
+        let mut in_tree = vec![false; points.len()];
+        let mut next = 0;
+        loop {
+            in_tree[next] = true;
+            for (i, p) in points.iter().enumerate() {
+                let length = distance(&points[next], p);
+                if !in_tree[i] && closest[i].is_none_or(|(l, _)| length < l) {
+                    closest[i] = Some((length, next));
+                }
+            }
+
+            let candidate = (0..points.len())
+                .filter(|&i| !in_tree[i])
+                .filter_map(|i| closest[i].map(|(l, from)| (l, i, from)))
+                .min_by(|a, b| a.0.total_cmp(&b.0));
+            match candidate {
+                Some((_, i, from)) => {
+                    edges.push((connectors[from].clone(), connectors[i].clone()));
+                    next = i;
+                }
+                None => return edges,
+            }
+        }
+    }
+}
+
+/// Area in which the entities are placed. Tiles on a regular lattice are kept free for
+/// power poles, such that every tile of the grid can be supplied.
+struct Grid {
+    width: i32,
+    spacing: i32,
+    pole_offset: i32,
+    pole_size: i32,
+}
+
+impl Grid {
+    fn new(blueprint: &Blueprint, pole_type: PoleType) -> Self {
+        let footprints = blueprint
+            .entities
+            .iter()
+            .map(|e| e.footprint().unwrap_or((1, 1)));
+        let area: u32 = footprints.clone().map(|(w, h)| w * h).sum();
+        let widest = footprints.map(|(w, _)| w).max().unwrap_or(1) as i32;
+
+        // Twice the area leaves room for the poles and for gaps between groups of entities
+        let width = ((2 * area) as f32).sqrt().ceil() as i32;
+
+        let pole_size = pole_type.prototype().size.0 as i32;
+        let spacing = (2.0 * pole_type.supply_area_distance()).floor() as i32;
+        Grid {
+            width: width.max(widest),
+            spacing,
+            pole_offset: (spacing - pole_size) / 2,
+            pole_size,
+        }
+    }
+
+    fn is_reserved(&self, tile: Tile) -> bool {
+        let on_lattice = |v: i32| {
+            let v = v.rem_euclid(self.spacing) - self.pole_offset;
+            (0..self.pole_size).contains(&v)
+        };
+        on_lattice(tile.0) && on_lattice(tile.1)
+    }
+
+    fn is_free(&self, tile: Tile, size: (u32, u32), occupied: &BTreeSet<Tile>) -> bool {
+        if tile.0 < 0 || tile.1 < 0 || tile.0 + size.0 as i32 > self.width {
+            return false;
+        }
+
+        (0..size.0 as i32).all(|dx| {
+            (0..size.1 as i32).all(|dy| {
+                let t = (tile.0 + dx, tile.1 + dy);
+                !occupied.contains(&t) && !self.is_reserved(t)
+            })
+        })
+    }
+
+    /// Top left tiles around the given entities up to `scale` times their wire reach.
+    fn candidates(
+        &self,
+        blueprint: &Blueprint,
+        ids: &[usize],
+        size: (u32, u32),
+        scale: i32,
+    ) -> BTreeSet<Tile> {
+        let mut tiles = BTreeSet::new();
+        for &id in ids {
+            let entity = &blueprint.entities[id];
+            let r = scale * entity.wire_reach().unwrap_or(0.0).ceil() as i32;
+            let (left, top) = entity.top_left_tile();
+            for x in left - r - size.0 as i32..=left + r {
+                for y in top - r - size.1 as i32..=top + r {
+                    tiles.insert((x, y));
+                }
+            }
+        }
+        tiles
+    }
+
+    fn first_free(&self, size: (u32, u32), occupied: &BTreeSet<Tile>) -> Tile {
+        (0..)
+            .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .find(|&tile| self.is_free(tile, size, occupied))
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::{
+        abstract_model::Wire,
+        model,
+        ram::{ram, RamOptions},
+    };
+
+    /// Circuit networks as sets of connectors, ignoring everything that was added later.
+    fn networks(blueprint: &Blueprint, ids: usize) -> HashSet<(Wire, Vec<Connector>)> {
+        blueprint
+            .circuit_networks()
+            .networks
+            .into_iter()
+            .map(|n| {
+                let connectors = n.connectors.into_iter().filter(|c| c.id < ids).collect();
+                (n.wire, connectors)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn layout_keeps_networks_and_reach() {
+        let options = RamOptions {
+            width: 3,
+            height: 4,
+            ..RamOptions::default()
+        };
+        let mut blueprint = ram(&options).unwrap().blueprint;
+        let ids = blueprint.entities.len();
+        let before = networks(&blueprint, ids);
+        for entity in &mut blueprint.entities {
+            *entity.position_mut() = Position { x: 0.0, y: 0.0 };
+        }
+
+        blueprint.layout(PoleType::Medium).unwrap();
+
+        let tiles: usize = blueprint.entities.iter().map(|e| e.tiles().len()).sum();
+        assert_eq!(tiles, blueprint.occupied_tiles().len());
+        assert!(blueprint.check_wire_reach().is_empty());
+        assert_eq!(before, networks(&blueprint, ids));
+
+        // Everything is powered already
+        let combinators: Vec<usize> = (0..ids)
+            .filter(|&id| !matches!(blueprint.entities[id], Entity::ElectricPole { .. }))
+            .collect();
+        let poles = blueprint
+            .place_power_poles(&combinators, PoleType::Medium)
+            .unwrap();
+        assert!(poles.is_empty());
+    }
+
+    #[test]
+    fn layout_rewires_unknown_entities() {
+        let unknown = |id: usize, name: &str, x: f32| {
+            Entity::Unknown(model::Entity {
+                entity_number: id as u32 + 1,
+                name: name.into(),
+                position: Position { x, y: 0.5 },
+                direction: None,
+                connections: None,
+                control_behavior: None,
+                neighbours: None,
+                tags: None,
+            })
+        };
+        let constant = Entity::ConstantCombinator {
+            id: 0,
+            position: Position { x: 0.5, y: 0.5 },
+            direction: model::Direction::North,
+            is_on: true,
+            connections: Vec::new(),
+            condition: Vec::new(),
+            tags: None,
+        };
+        let mut blueprint = Blueprint::new(vec![
+            constant,
+            unknown(1, "inserter", 4.5),
+            unknown(2, "iron-chest", 8.5),
+        ]);
+        blueprint.connect_wire(0, 1, Wire::Red).unwrap();
+        blueprint.connect_wire(1, 2, Wire::Green).unwrap();
+        let before = networks(&blueprint, 3);
+        assert_eq!(2, before.len());
+
+        blueprint.layout(PoleType::Medium).unwrap();
+
+        assert_eq!(before, networks(&blueprint, 3));
+        assert_eq!(2, blueprint.entities[1].wires().len());
+        assert!(blueprint.check_wire_reach().is_empty());
+    }
+}
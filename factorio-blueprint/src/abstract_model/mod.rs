@@ -1,14 +1,33 @@
+pub mod layout;
+pub mod network;
+pub mod prototype;
+pub mod reach;
+pub mod replace;
+pub mod routing;
 pub mod utility;
+pub mod validation;
 
 use crate::model::{self, CircuitId, ConnectionPoint};
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Blueprint {
     pub entities: Vec<Entity>,
+    pub tiles: Vec<model::Tile>,
     pub version: u64,
     pub icons: Vec<model::Icon>,
 }
 
+impl Blueprint {
+    /// Blueprint with the given entities, without tiles and icons.
+    pub fn new(entities: Vec<Entity>) -> Self {
+        Blueprint {
+            entities,
+            ..Blueprint::default()
+        }
+    }
+}
+
+#[allow(clippy::large_enum_variant)]
 #[derive(Clone, Debug)]
 pub enum Entity {
     DeciderCombinator {
@@ -19,6 +38,8 @@ pub enum Entity {
         connections: Vec<Connection>,
 
         condition: model::DeciderCondition,
+
+        tags: Option<model::Tags>,
     },
     ArithmeticCombinator {
         id: usize,
@@ -28,6 +49,8 @@ pub enum Entity {
         connections: Vec<Connection>,
 
         condition: model::ArithmeticCondition,
+
+        tags: Option<model::Tags>,
     },
     ConstantCombinator {
         id: usize,
@@ -38,6 +61,8 @@ pub enum Entity {
         connections: Vec<Connection>,
 
         condition: Vec<model::ConstantCondition>,
+
+        tags: Option<model::Tags>,
     },
     ElectricPole {
         id: usize,
@@ -45,6 +70,22 @@ pub enum Entity {
         position: model::Position,
         neighbours: Vec<usize>,
         connections: Vec<Connection>,
+
+        tags: Option<model::Tags>,
+    },
+    Lamp {
+        id: usize,
+        position: model::Position,
+
+        connections: Vec<Connection>,
+
+        /// Lamps without a condition are always on.
+        condition: Option<model::CircuitCondition>,
+
+        /// Takes the colour from the colour signals on the network.
+        use_colors: bool,
+
+        tags: Option<model::Tags>,
     },
     Unknown(model::Entity),
 }
@@ -56,19 +97,19 @@ pub struct Connection {
     pub wire: Wire,
 }
 
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
 pub struct Connector {
     pub id: usize,
     pub side: Side,
 }
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
 pub enum Side {
     One,
     Two,
 }
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
 pub enum Wire {
     Red,
     Green,
@@ -76,7 +117,10 @@ pub enum Wire {
 
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum PoleType {
+    Small,
     Medium,
+    Big,
+    Substation,
 }
 
 impl From<model::BlueprintContainer> for Blueprint {
@@ -88,28 +132,35 @@ impl From<model::BlueprintContainer> for Blueprint {
             version: b.version,
             icons: b.icons,
             entities: b.entities.into_iter().map(Entity::from).collect(),
+            tiles: b.tiles,
         }
     }
 }
 
 impl Blueprint {
+    /// Like [`Blueprint::from`], but returns an error instead of panicking if the blueprint is
+    /// not accepted by [`check_raw_model`](crate::check_raw_model).
+    pub fn try_from_raw(bc: model::BlueprintContainer) -> crate::Result<Self> {
+        crate::check_raw_model(&bc)?;
+        Ok(Blueprint::from(bc))
+    }
+
     fn assert_compact_ascending_ids(b: &model::Blueprint) {
-        let mut expected_id = 1u32;
-        for entity in &b.entities {
+        for (expected_id, entity) in (1u32..).zip(&b.entities) {
             assert_eq!(expected_id, entity.entity_number);
-            expected_id += 1;
         }
     }
 }
 
-impl Into<model::BlueprintContainer> for Blueprint {
-    fn into(self) -> model::BlueprintContainer {
+impl From<Blueprint> for model::BlueprintContainer {
+    fn from(b: Blueprint) -> Self {
         model::BlueprintContainer {
             blueprint: model::Blueprint {
-                version: self.version,
-                icons: self.icons,
+                version: b.version,
+                icons: b.icons,
                 item: model::Item::Blueprint,
-                entities: self.entities.into_iter().map(Entity::into).collect(),
+                entities: b.entities.into_iter().map(Entity::into).collect(),
+                tiles: b.tiles,
             },
         }
     }
@@ -122,69 +173,83 @@ impl From<model::Entity> for Entity {
             "decider-combinator" => Self::decider_combinator(id, e),
             "arithmetic-combinator" => Self::arithmetic_combinator(id, e),
             "constant-combinator" => Self::constant_combinator(id, e),
+            "small-electric-pole" => Self::electric_pole(id, PoleType::Small, e),
             "medium-electric-pole" => Self::electric_pole(id, PoleType::Medium, e),
+            "big-electric-pole" => Self::electric_pole(id, PoleType::Big, e),
+            "substation" => Self::electric_pole(id, PoleType::Substation, e),
+            "small-lamp" => Self::lamp(id, e),
             _ => Entity::Unknown(e),
         }
     }
 }
 
-impl Into<model::Entity> for Entity {
-    fn into(self) -> model::Entity {
-        match self {
-            Self::Unknown(e) => e,
-            Self::DeciderCombinator {
+impl From<Entity> for model::Entity {
+    fn from(e: Entity) -> Self {
+        match e {
+            Entity::Unknown(e) => e,
+            Entity::DeciderCombinator {
                 id,
                 position,
                 direction,
                 connections,
                 condition,
+                tags,
             } => model::Entity {
                 entity_number: (id + 1) as u32,
                 name: "decider-combinator".into(),
                 position,
                 direction: Some(direction),
                 neighbours: None,
+                tags,
                 control_behavior: Some(model::ControlBehavior {
                     arithmetic_conditions: None,
                     decider_conditions: Some(condition),
                     filters: None,
                     is_on: None,
+                    circuit_condition: None,
+                    use_colors: None,
                 }),
                 connections: Connection::to_model(connections),
             },
-            Self::ArithmeticCombinator {
+            Entity::ArithmeticCombinator {
                 id,
                 position,
                 direction,
                 connections,
                 condition,
+                tags,
             } => model::Entity {
                 entity_number: (id + 1) as u32,
                 name: "arithmetic-combinator".into(),
                 position,
                 direction: Some(direction),
                 neighbours: None,
+                tags,
                 control_behavior: Some(model::ControlBehavior {
                     arithmetic_conditions: Some(condition),
                     decider_conditions: None,
                     filters: None,
                     is_on: None,
+                    circuit_condition: None,
+                    use_colors: None,
                 }),
                 connections: Connection::to_model(connections),
             },
-            Self::ConstantCombinator {
+            Entity::ConstantCombinator {
                 id,
                 position,
                 direction,
                 is_on,
                 connections,
                 condition,
+                tags,
             } => model::Entity {
                 entity_number: (id + 1) as u32,
                 name: "constant-combinator".into(),
                 position,
                 direction: Some(direction),
                 neighbours: None,
+                tags,
                 control_behavior: Some(model::ControlBehavior {
                     arithmetic_conditions: None,
                     decider_conditions: None,
@@ -193,24 +258,55 @@ impl Into<model::Entity> for Entity {
                         else { Some(condition) },
                     // Leaving away the is_on attribute for constant combinators is interpreted as: "is_on": true
                     is_on: if is_on { None } else { Some(false) },
+                    circuit_condition: None,
+                    use_colors: None,
                 }),
                 connections: Connection::to_model(connections),
             },
-            Self::ElectricPole {
+            Entity::ElectricPole {
                 id,
                 pole_type,
                 position,
                 neighbours,
                 connections,
+                tags,
             } => model::Entity {
                 entity_number: (id + 1) as u32,
                 name: pole_type.name().into(),
                 position,
                 direction: None,
                 neighbours: Some(neighbours.into_iter().map(|id| (id + 1) as u32).collect()),
+                tags,
                 control_behavior: None,
                 connections: Connection::to_model(connections),
             },
+            Entity::Lamp {
+                id,
+                position,
+                connections,
+                condition,
+                use_colors,
+                tags,
+            } => model::Entity {
+                entity_number: (id + 1) as u32,
+                name: "small-lamp".into(),
+                position,
+                direction: None,
+                neighbours: None,
+                tags,
+                control_behavior: match (&condition, use_colors) {
+                    (None, false) => None,
+                    _ => Some(model::ControlBehavior {
+                        arithmetic_conditions: None,
+                        decider_conditions: None,
+                        filters: None,
+                        is_on: None,
+                        circuit_condition: condition,
+                        use_colors: if use_colors { Some(true) } else { None },
+                    }),
+                },
+                connections: Connection::to_model(connections),
+            },
         }
     }
 }
@@ -220,11 +316,13 @@ impl Entity {
         Entity::DeciderCombinator {
             id,
             position: e.position,
-            direction: e.direction.unwrap(),
+            direction: e.direction.unwrap_or(model::Direction::North),
 
             connections: Connection::from_model(e.connections),
 
             condition: e.control_behavior.unwrap().decider_conditions.unwrap(),
+
+            tags: e.tags,
         }
     }
 
@@ -232,32 +330,37 @@ impl Entity {
         Entity::ArithmeticCombinator {
             id,
             position: e.position,
-            direction: e.direction.unwrap(),
+            direction: e.direction.unwrap_or(model::Direction::North),
 
             connections: Connection::from_model(e.connections),
 
             condition: e.control_behavior.unwrap().arithmetic_conditions.unwrap(),
+
+            tags: e.tags,
         }
     }
 
+    /// The game leaves out the control behaviour and the filters of empty constant combinators.
     fn constant_combinator(id: usize, e: model::Entity) -> Self {
-        let control_behavior = e.control_behavior.unwrap();
+        let control_behavior = e.control_behavior.unwrap_or_default();
         Entity::ConstantCombinator {
             id,
             position: e.position,
-            direction: e.direction.unwrap(),
+            direction: e.direction.unwrap_or(model::Direction::North),
             is_on: control_behavior.is_on.unwrap_or(true),
 
             connections: Connection::from_model(e.connections),
 
-            condition: control_behavior.filters.unwrap(),
+            condition: control_behavior.filters.unwrap_or_default(),
+
+            tags: e.tags,
         }
     }
 
     fn electric_pole(id: usize, pole_type: PoleType, e: model::Entity) -> Self {
         let neighbours = e
             .neighbours
-            .unwrap_or(Vec::new())
+            .unwrap_or_default()
             .into_iter()
             .map(|n| (n - 1) as usize)
             .collect();
@@ -268,6 +371,27 @@ impl Entity {
             position: e.position,
 
             connections: Connection::from_model(e.connections),
+
+            tags: e.tags,
+        }
+    }
+
+    fn lamp(id: usize, e: model::Entity) -> Self {
+        let control_behavior = e.control_behavior;
+        Entity::Lamp {
+            id,
+            position: e.position,
+
+            connections: Connection::from_model(e.connections),
+
+            condition: control_behavior
+                .as_ref()
+                .and_then(|c| c.circuit_condition.clone()),
+            use_colors: control_behavior
+                .and_then(|c| c.use_colors)
+                .unwrap_or(false),
+
+            tags: e.tags,
         }
     }
 }
@@ -381,11 +505,11 @@ impl From<Option<CircuitId>> for Side {
     }
 }
 
-impl Into<Option<CircuitId>> for Side {
-    fn into(self) -> Option<CircuitId> {
-        match self {
-            Self::One => Some(CircuitId::One), // TODO: Is Some(One) instead of None a problem?
-            Self::Two => Some(CircuitId::Two),
+impl From<Side> for Option<CircuitId> {
+    fn from(side: Side) -> Self {
+        match side {
+            Side::One => Some(CircuitId::One), // TODO: Is Some(One) instead of None a problem?
+            Side::Two => Some(CircuitId::Two),
         }
     }
 }
@@ -393,7 +517,10 @@ impl Into<Option<CircuitId>> for Side {
 impl PoleType {
     pub fn name(&self) -> &'static str {
         match &self {
+            Self::Small => "small-electric-pole",
             Self::Medium => "medium-electric-pole",
+            Self::Big => "big-electric-pole",
+            Self::Substation => "substation",
         }
     }
 }
@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+
+use super::{Blueprint, Connector, Wire};
+
+pub type NetworkId = usize;
+
+type Key = (Connector, Wire);
+
+/// Connectors which are joined by wires of the same colour and therefore share their signals.
+#[derive(Clone, PartialEq, Debug)]
+pub struct CircuitNetwork {
+    pub id: NetworkId,
+    pub wire: Wire,
+
+    /// Sorted by entity id and side.
+    pub connectors: Vec<Connector>,
+}
+
+/// All circuit networks of a blueprint.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct CircuitNetworks {
+    /// Indexed by network id.
+    pub networks: Vec<CircuitNetwork>,
+    lookup: HashMap<Key, NetworkId>,
+}
+
+impl CircuitNetworks {
+    pub fn id_of(&self, connector: &Connector, wire: Wire) -> Option<NetworkId> {
+        self.lookup.get(&(connector.clone(), wire)).copied()
+    }
+
+    pub fn network_of(&self, connector: &Connector, wire: Wire) -> Option<&CircuitNetwork> {
+        self.id_of(connector, wire).map(|id| &self.networks[id])
+    }
+
+    pub fn get(&self, id: NetworkId) -> Option<&CircuitNetwork> {
+        self.networks.get(id)
+    }
+
+    pub fn of_colour(&self, wire: Wire) -> impl Iterator<Item = &CircuitNetwork> {
+        self.networks.iter().filter(move |n| n.wire == wire)
+    }
+}
+
+impl Blueprint {
+    /// Splits the wires of the blueprint into connected components per colour.
+    /// Only connectors with at least one wire of a colour are part of a network of that colour.
+    ///
+    /// Network ids are stable for a given blueprint: they are assigned in order of the first
+    /// connector of each network, ordered by entity id, side and then colour (red before green).
+    pub fn circuit_networks(&self) -> CircuitNetworks {
+        let mut parent: HashMap<Key, Key> = HashMap::new();
+
+        for entity in &self.entities {
+            let id = entity.id();
+            for c in entity.wires() {
+                // Wires to entities that do not exist cannot carry signals
+                if c.to.id >= self.entities.len() {
+                    continue;
+                }
+
+                let from = (Connector { id, side: c.from_side }, c.wire);
+                let to = (c.to.clone(), c.wire);
+                let (a, b) = (find(&mut parent, from), find(&mut parent, to));
+                if a != b {
+                    // The smaller key becomes the root, which keeps the result deterministic
+                    let (root, child) = if a < b { (a, b) } else { (b, a) };
+                    parent.insert(child, root);
+                }
+            }
+        }
+
+        let mut keys: Vec<Key> = parent.keys().cloned().collect();
+        keys.sort();
+
+        let mut networks = CircuitNetworks::default();
+        let mut root_ids = HashMap::new();
+        for key in keys {
+            let root = find(&mut parent, key.clone());
+            let id = *root_ids.entry(root).or_insert_with(|| {
+                networks.networks.push(CircuitNetwork {
+                    id: networks.networks.len(),
+                    wire: key.1,
+                    connectors: Vec::new(),
+                });
+                networks.networks.len() - 1
+            });
+
+            networks.networks[id].connectors.push(key.0.clone());
+            networks.lookup.insert(key, id);
+        }
+
+        networks
+    }
+}
+
+/// Union find lookup, creating a new set for unknown keys.
+fn find(parent: &mut HashMap<Key, Key>, key: Key) -> Key {
+    let p = parent.entry(key.clone()).or_insert_with(|| key.clone()).clone();
+    if p == key {
+        return key;
+    }
+
+    let root = find(parent, p);
+    parent.insert(key, root.clone());
+    root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        abstract_model::{Connection, Entity, Side},
+        model::{ArithmeticCondition, Direction, Operation, Position},
+    };
+
+    fn arithmetic_combinator(id: usize) -> Entity {
+        Entity::ArithmeticCombinator {
+            id,
+            position: Position { x: id as f32 * 2.0, y: 0.5 },
+            direction: Direction::East,
+            connections: Vec::new(),
+            condition: ArithmeticCondition {
+                operation: Operation::Add,
+                first_constant: None,
+                second_constant: Some(0),
+                first_signal: None,
+                second_signal: None,
+                output_signal: None,
+            },
+            tags: None,
+        }
+    }
+
+    fn blueprint(count: usize) -> Blueprint {
+        Blueprint::new((0..count).map(arithmetic_combinator).collect())
+    }
+
+    fn connector(id: usize, side: Side) -> Connector {
+        Connector { id, side }
+    }
+
+    #[test]
+    fn colours_and_sides_form_separate_networks() {
+        let mut b = blueprint(3);
+        b.connect_wire(0, 1, Wire::Red).unwrap();
+        b.connect_wire(0, 1, Wire::Green).unwrap();
+        b.connect_wire_with_side(connector(1, Side::Two), connector(2, Side::One), Wire::Red)
+            .unwrap();
+
+        let networks = b.circuit_networks();
+        assert_eq!(3, networks.networks.len());
+        assert_eq!(2, networks.of_colour(Wire::Red).count());
+        assert_eq!(1, networks.of_colour(Wire::Green).count());
+
+        let red = networks.network_of(&connector(0, Side::One), Wire::Red).unwrap();
+        assert_eq!(vec![connector(0, Side::One), connector(1, Side::One)], red.connectors);
+        let green = networks.network_of(&connector(0, Side::One), Wire::Green).unwrap();
+        assert_ne!(red.id, green.id);
+        assert_eq!(red.connectors, green.connectors);
+
+        // Input and output of a combinator are not joined
+        let output = networks.network_of(&connector(1, Side::Two), Wire::Red).unwrap();
+        assert_ne!(red.id, output.id);
+        assert_eq!(vec![connector(1, Side::Two), connector(2, Side::One)], output.connectors);
+
+        // Connectors without a wire of a colour have no network of it
+        assert!(networks.network_of(&connector(1, Side::Two), Wire::Green).is_none());
+        assert!(networks.network_of(&connector(2, Side::Two), Wire::Red).is_none());
+    }
+
+    #[test]
+    fn network_ids_are_stable() {
+        let mut b = blueprint(4);
+        b.connect_wire(2, 3, Wire::Green).unwrap();
+        b.connect_wire(2, 3, Wire::Red).unwrap();
+        b.connect_wire(0, 1, Wire::Green).unwrap();
+
+        let networks = b.circuit_networks();
+        assert_eq!(networks, b.circuit_networks());
+
+        // Ordered by the first connector, red before green
+        let first: Vec<(Connector, Wire)> =
+            networks.networks.iter().map(|n| (n.connectors[0].clone(), n.wire)).collect();
+        assert_eq!(
+            vec![
+                (connector(0, Side::One), Wire::Green),
+                (connector(2, Side::One), Wire::Red),
+                (connector(2, Side::One), Wire::Green),
+            ],
+            first
+        );
+        for (i, network) in networks.networks.iter().enumerate() {
+            assert_eq!(i, network.id);
+            assert_eq!(Some(network), networks.get(i));
+        }
+    }
+
+    #[test]
+    fn wires_to_missing_entities_are_ignored() {
+        let mut b = blueprint(2);
+        b.connect_wire(0, 1, Wire::Red).unwrap();
+        if let Entity::ArithmeticCombinator { connections, .. } = &mut b.entities[0] {
+            connections.push(Connection {
+                from_side: Side::Two,
+                to: connector(7, Side::One),
+                wire: Wire::Red,
+            });
+        }
+
+        let networks = b.circuit_networks();
+        assert_eq!(1, networks.networks.len());
+        assert!(networks.network_of(&connector(0, Side::Two), Wire::Red).is_none());
+        assert!(networks.network_of(&connector(7, Side::One), Wire::Red).is_none());
+    }
+}
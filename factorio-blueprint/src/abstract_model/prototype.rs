@@ -0,0 +1,167 @@
+use std::collections::HashSet;
+
+use crate::model::{Direction, Position};
+
+use super::{Blueprint, Entity, PoleType, Side};
+
+/// Static data of an entity type as defined by the game.
+#[derive(Debug, PartialEq)]
+pub struct Prototype {
+    pub name: &'static str,
+
+    /// Size in tiles (width, height) when the entity faces north.
+    pub size: (u32, u32),
+
+    /// Maximal length of circuit wires (and copper cables for poles). Copper cables of power
+    /// switches reach 10 tiles, but only the ones of poles are modelled.
+    pub wire_reach: Option<f32>,
+
+    /// Distance from the centre of a pole in which entities are supplied with power.
+    pub supply_area_distance: Option<f32>,
+}
+
+const PROTOTYPES: &[Prototype] = &[
+    Prototype { name: "decider-combinator", size: (1, 2), wire_reach: Some(9.0), supply_area_distance: None },
+    Prototype { name: "arithmetic-combinator", size: (1, 2), wire_reach: Some(9.0), supply_area_distance: None },
+    Prototype { name: "constant-combinator", size: (1, 1), wire_reach: Some(9.0), supply_area_distance: None },
+    Prototype { name: "small-electric-pole", size: (1, 1), wire_reach: Some(7.5), supply_area_distance: Some(2.5) },
+    Prototype { name: "medium-electric-pole", size: (1, 1), wire_reach: Some(9.0), supply_area_distance: Some(3.5) },
+    Prototype { name: "big-electric-pole", size: (2, 2), wire_reach: Some(30.0), supply_area_distance: Some(2.0) },
+    Prototype { name: "substation", size: (2, 2), wire_reach: Some(18.0), supply_area_distance: Some(9.0) },
+    Prototype { name: "small-lamp", size: (1, 1), wire_reach: Some(9.0), supply_area_distance: None },
+    Prototype { name: "power-switch", size: (2, 2), wire_reach: Some(9.0), supply_area_distance: None },
+    Prototype { name: "programmable-speaker", size: (1, 1), wire_reach: Some(9.0), supply_area_distance: None },
+    Prototype { name: "wooden-chest", size: (1, 1), wire_reach: Some(9.0), supply_area_distance: None },
+    Prototype { name: "iron-chest", size: (1, 1), wire_reach: Some(9.0), supply_area_distance: None },
+    Prototype { name: "steel-chest", size: (1, 1), wire_reach: Some(9.0), supply_area_distance: None },
+    Prototype { name: "inserter", size: (1, 1), wire_reach: Some(9.0), supply_area_distance: None },
+    Prototype { name: "fast-inserter", size: (1, 1), wire_reach: Some(9.0), supply_area_distance: None },
+    Prototype { name: "stack-inserter", size: (1, 1), wire_reach: Some(9.0), supply_area_distance: None },
+    Prototype { name: "transport-belt", size: (1, 1), wire_reach: Some(9.0), supply_area_distance: None },
+];
+
+/// Looks up the prototype of an entity by its name.
+pub fn prototype(name: &str) -> Option<&'static Prototype> {
+    PROTOTYPES.iter().find(|p| p.name == name)
+}
+
+impl PoleType {
+    pub fn prototype(&self) -> &'static Prototype {
+        prototype(self.name()).unwrap()
+    }
+
+    pub fn wire_reach(&self) -> f32 {
+        self.prototype().wire_reach.unwrap()
+    }
+
+    pub fn supply_area_distance(&self) -> f32 {
+        self.prototype().supply_area_distance.unwrap()
+    }
+}
+
+impl Entity {
+    pub fn name(&self) -> &str {
+        match self {
+            Entity::DeciderCombinator { .. } => "decider-combinator",
+            Entity::ArithmeticCombinator { .. } => "arithmetic-combinator",
+            Entity::ConstantCombinator { .. } => "constant-combinator",
+            Entity::ElectricPole { pole_type, .. } => pole_type.name(),
+            Entity::Lamp { .. } => "small-lamp",
+            Entity::Unknown(e) => &e.name,
+        }
+    }
+
+    pub fn direction(&self) -> Option<&Direction> {
+        match self {
+            Entity::DeciderCombinator { direction, .. }
+            | Entity::ArithmeticCombinator { direction, .. }
+            | Entity::ConstantCombinator { direction, .. } => Some(direction),
+            Entity::ElectricPole { .. } | Entity::Lamp { .. } => None,
+            Entity::Unknown(e) => e.direction.as_ref(),
+        }
+    }
+
+    pub fn prototype(&self) -> Option<&'static Prototype> {
+        prototype(self.name())
+    }
+
+    /// Size in tiles (width, height) taking the direction of the entity into account.
+    pub fn footprint(&self) -> Option<(u32, u32)> {
+        let (width, height) = self.prototype()?.size;
+        match self.direction() {
+            Some(Direction::East) | Some(Direction::West) => Some((height, width)),
+            _ => Some((width, height)),
+        }
+    }
+
+    pub fn wire_reach(&self) -> Option<f32> {
+        self.prototype()?.wire_reach
+    }
+
+    /// Position where wires that connect to the given side are attached.
+    /// Combinators take their input on side one (back) and output on side two (front).
+    pub fn connection_point(&self, side: Side) -> Position {
+        let position = self.position().clone();
+        let offset = match (self, side) {
+            (Entity::DeciderCombinator { .. }, Side::One)
+            | (Entity::ArithmeticCombinator { .. }, Side::One) => -0.5,
+            (Entity::DeciderCombinator { .. }, Side::Two)
+            | (Entity::ArithmeticCombinator { .. }, Side::Two) => 0.5,
+            _ => return position,
+        };
+
+        let (dx, dy) = match self.direction() {
+            Some(Direction::East) => (1.0, 0.0),
+            Some(Direction::South) => (0.0, 1.0),
+            Some(Direction::West) => (-1.0, 0.0),
+            _ => (0.0, -1.0),
+        };
+
+        Position {
+            x: position.x + dx * offset,
+            y: position.y + dy * offset,
+        }
+    }
+}
+
+pub fn distance(a: &Position, b: &Position) -> f32 {
+    ((a.x - b.x).powi(2) + (a.y - b.y).powi(2)).sqrt()
+}
+
+/// Position of a tile, the tile covers the area from (x, y) to (x + 1, y + 1).
+pub type Tile = (i32, i32);
+
+impl Entity {
+    /// Top left tile of the entity. Entities without a known prototype are treated as 1x1.
+    pub fn top_left_tile(&self) -> Tile {
+        let (width, height) = self.footprint().unwrap_or((1, 1));
+        let position = self.position();
+        (
+            (position.x - width as f32 / 2.0).round() as i32,
+            (position.y - height as f32 / 2.0).round() as i32,
+        )
+    }
+
+    /// All tiles covered by the entity.
+    pub fn tiles(&self) -> Vec<Tile> {
+        let (width, height) = self.footprint().unwrap_or((1, 1));
+        let (left, top) = self.top_left_tile();
+        (0..width as i32)
+            .flat_map(|dx| (0..height as i32).map(move |dy| (left + dx, top + dy)))
+            .collect()
+    }
+}
+
+impl Blueprint {
+    pub fn occupied_tiles(&self) -> HashSet<Tile> {
+        self.entities.iter().flat_map(Entity::tiles).collect()
+    }
+}
+
+/// Centre position of an entity of the given size with its top left corner on `tile`.
+pub fn position_on_tile(tile: Tile, size: (u32, u32)) -> Position {
+    Position {
+        x: tile.0 as f32 + size.0 as f32 / 2.0,
+        y: tile.1 as f32 + size.1 as f32 / 2.0,
+    }
+}
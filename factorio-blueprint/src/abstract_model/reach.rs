@@ -0,0 +1,201 @@
+use std::collections::HashSet;
+
+use super::{prototype::distance, Blueprint, Connector, Side, Wire};
+
+/// Kind of a link between two entities.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Link {
+    Circuit(Wire),
+    Copper,
+}
+
+/// A wire or copper cable which is longer than the game allows.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ReachViolation {
+    pub from: Connector,
+    pub to: Connector,
+    pub link: Link,
+    pub length: f32,
+    pub max_length: f32,
+}
+
+impl Blueprint {
+    /// Measures every circuit wire and copper cable between the wire connection points
+    /// of the entities and returns all links that exceed the reach of one of their ends.
+    /// Every link is reported only once, even though it is stored on both entities.
+    pub fn check_wire_reach(&self) -> Vec<ReachViolation> {
+        let mut seen = HashSet::new();
+        let mut violations = Vec::new();
+
+        for entity in &self.entities {
+            let id = entity.id();
+
+            for c in entity.wires() {
+                let from = Connector {
+                    id,
+                    side: c.from_side,
+                };
+                if let Some(v) = self.measure(from, c.to.clone(), Link::Circuit(c.wire), &mut seen) {
+                    violations.push(v);
+                }
+            }
+
+            for n in entity.neighbours() {
+                let from = Connector { id, side: Side::One };
+                let to = Connector { id: n, side: Side::One };
+                if let Some(v) = self.measure(from, to, Link::Copper, &mut seen) {
+                    violations.push(v);
+                }
+            }
+        }
+
+        violations
+    }
+
+    fn measure(
+        &self,
+        from: Connector,
+        to: Connector,
+        link: Link,
+        seen: &mut HashSet<(usize, u8, usize, u8, u8)>,
+    ) -> Option<ReachViolation> {
+        let (a, b) = match (self.entities.get(from.id), self.entities.get(to.id)) {
+            (Some(a), Some(b)) => (a, b),
+            // Dangling links cannot be measured
+            _ => return None,
+        };
+
+        let key = |c: &Connector| (c.id, c.side as u8);
+        let link_key = match link {
+            Link::Circuit(wire) => wire as u8,
+            Link::Copper => 2,
+        };
+        let (k1, k2) = (key(&from), key(&to));
+        let (k1, k2) = if k1 <= k2 { (k1, k2) } else { (k2, k1) };
+        if !seen.insert((k1.0, k1.1, k2.0, k2.1, link_key)) {
+            return None;
+        }
+
+        let max_length = match (a.wire_reach(), b.wire_reach()) {
+            (Some(r1), Some(r2)) => r1.min(r2),
+            (Some(r), None) | (None, Some(r)) => r,
+            (None, None) => return None,
+        };
+
+        let length = match link {
+            Link::Circuit(_) => distance(&a.connection_point(from.side), &b.connection_point(to.side)),
+            Link::Copper => distance(a.position(), b.position()),
+        };
+
+        if length > max_length {
+            Some(ReachViolation {
+                from,
+                to,
+                link,
+                length,
+                max_length,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        abstract_model::{Entity, PoleType},
+        model::{self, Position},
+    };
+
+    fn pole(id: usize, pole_type: PoleType, x: f32) -> Entity {
+        Entity::ElectricPole {
+            id,
+            pole_type,
+            position: Position { x, y: 0.5 },
+            neighbours: Vec::new(),
+            connections: Vec::new(),
+            tags: None,
+        }
+    }
+
+    fn blueprint(entities: Vec<Entity>) -> Blueprint {
+        Blueprint::new(entities)
+    }
+
+    #[test]
+    fn links_up_to_the_reach_are_allowed() {
+        let mut b = blueprint(vec![
+            pole(0, PoleType::Medium, 0.5),
+            pole(1, PoleType::Medium, 9.5),
+        ]);
+        b.connect_electric_poles(0, 1).unwrap();
+        b.connect_wire(0, 1, Wire::Red).unwrap();
+        assert_eq!(Vec::<ReachViolation>::new(), b.check_wire_reach());
+    }
+
+    #[test]
+    fn too_long_links_are_reported_once() {
+        let mut b = blueprint(vec![
+            pole(0, PoleType::Medium, 0.5),
+            pole(1, PoleType::Medium, 10.5),
+        ]);
+        b.connect_electric_poles(0, 1).unwrap();
+        b.connect_wire(0, 1, Wire::Red).unwrap();
+
+        let violations = b.check_wire_reach();
+        assert_eq!(2, violations.len());
+        let red = violations
+            .iter()
+            .find(|v| v.link == Link::Circuit(Wire::Red))
+            .unwrap();
+        assert_eq!((0, 1), (red.from.id, red.to.id));
+        assert_eq!((10.0, 9.0), (red.length, red.max_length));
+        assert!(violations.iter().any(|v| v.link == Link::Copper));
+    }
+
+    #[test]
+    fn the_shorter_reach_of_both_ends_counts() {
+        let mut b = blueprint(vec![
+            pole(0, PoleType::Small, 0.5),
+            pole(1, PoleType::Medium, 8.5),
+        ]);
+        b.connect_electric_poles(0, 1).unwrap();
+
+        let violations = b.check_wire_reach();
+        assert_eq!(1, violations.len());
+        assert_eq!(Link::Copper, violations[0].link);
+        assert_eq!(7.5, violations[0].max_length);
+    }
+
+    #[test]
+    fn links_of_unknown_entities_are_measured() {
+        let unknown = |id: usize, name: &str, x: f32, neighbours: Option<Vec<u32>>| {
+            Entity::Unknown(model::Entity {
+                entity_number: id as u32 + 1,
+                name: name.into(),
+                position: Position { x, y: 0.5 },
+                direction: None,
+                connections: None,
+                control_behavior: None,
+                neighbours,
+                tags: None,
+            })
+        };
+        let mut b = blueprint(vec![
+            unknown(0, "inserter", 0.5, None),
+            unknown(1, "inserter", 10.5, None),
+            unknown(2, "power-switch", 1.0, Some(vec![4])),
+            unknown(3, "power-switch", 11.0, Some(vec![3])),
+        ]);
+        b.connect_wire(0, 1, Wire::Red).unwrap();
+
+        let violations = b.check_wire_reach();
+        assert_eq!(2, violations.len(), "{:?}", violations);
+        assert_eq!(Link::Circuit(Wire::Red), violations[0].link);
+        assert_eq!((0, 1), (violations[0].from.id, violations[0].to.id));
+        assert_eq!(Link::Copper, violations[1].link);
+        assert_eq!((2, 3), (violations[1].from.id, violations[1].to.id));
+    }
+}
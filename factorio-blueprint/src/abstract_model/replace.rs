@@ -0,0 +1,411 @@
+use std::{collections::HashMap, error::Error, fmt};
+
+use crate::model::{self, ControlBehavior, Signal};
+
+use super::{
+    prototype::{position_on_tile, Tile},
+    Blueprint, Entity, PoleType,
+};
+
+/// Errors refer to entities by id, their messages by entity number.
+#[derive(Debug, PartialEq)]
+pub enum ReplaceError {
+    InvalidId(usize),
+
+    /// The entity cannot become an entity of the other name, e.g. a pole cannot become a lamp.
+    Incompatible {
+        id: usize,
+        name: String,
+    },
+
+    /// The replacement of the entity would cover a tile of the other entity.
+    Overlap {
+        id: usize,
+        other: usize,
+        tile: Tile,
+    },
+
+    /// The size of the entity or of its replacement with this name is not known, so the tiles
+    /// it covers cannot be checked.
+    UnknownSize {
+        id: usize,
+        name: String,
+    },
+}
+
+impl fmt::Display for ReplaceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidId(id) => write!(f, "there is no entity {}", id + 1),
+            Self::Incompatible { id, name } => {
+                write!(f, "entity {} cannot be replaced by {}", id + 1, name)
+            }
+            Self::Overlap { id, other, tile } => write!(
+                f,
+                "replacement of entity {} overlaps entity {} at tile ({}, {})",
+                id + 1,
+                other + 1,
+                tile.0,
+                tile.1
+            ),
+            Self::UnknownSize { id, name } => write!(
+                f,
+                "entity {} cannot be replaced, the size of {} is unknown",
+                id + 1,
+                name
+            ),
+        }
+    }
+}
+
+impl Error for ReplaceError {}
+
+pub type Result<T> = core::result::Result<T, ReplaceError>;
+
+/// Entities which can be replaced by each other. Entities without their own variant can be
+/// replaced by any other entity without a variant.
+#[derive(PartialEq, Debug)]
+enum Kind<'a> {
+    Pole,
+    Unknown,
+    Other(&'a str),
+}
+
+fn kind(name: &str) -> Kind<'_> {
+    let poles = [
+        PoleType::Small,
+        PoleType::Medium,
+        PoleType::Big,
+        PoleType::Substation,
+    ];
+    if poles.iter().any(|p| p.name() == name) {
+        return Kind::Pole;
+    }
+    match name {
+        "decider-combinator" | "arithmetic-combinator" | "constant-combinator" | "small-lamp" => {
+            Kind::Other(name)
+        }
+        _ => Kind::Unknown,
+    }
+}
+
+impl Blueprint {
+    /// Replaces every use of the signal `from` in conditions, outputs and filters of the
+    /// entities in `ids`, or of all entities and the icons if `ids` is `None`.
+    /// Returns how many uses were replaced.
+    pub fn replace_signal(
+        &mut self,
+        from: &Signal,
+        to: &Signal,
+        ids: Option<&[usize]>,
+    ) -> Result<usize> {
+        self.check_ids(ids)?;
+
+        let mut replaced = 0;
+        let mut replace = |signal: &mut Signal| {
+            if signal == from {
+                *signal = to.clone();
+                replaced += 1;
+            }
+        };
+        for entity in &mut self.entities {
+            if ids.is_none_or(|ids| ids.contains(&entity.id())) {
+                signals_mut(entity).into_iter().for_each(&mut replace);
+            }
+        }
+        if ids.is_none() {
+            self.icons
+                .iter_mut()
+                .for_each(|icon| replace(&mut icon.signal));
+        }
+
+        Ok(replaced)
+    }
+
+    /// Replaces all entities named `from`, limited to `ids` if given, by entities named `to`.
+    /// The top left tile of each entity stays the same, nothing is changed if a replacement
+    /// would overlap another entity. Returns the ids of the replaced entities.
+    pub fn replace_entity(
+        &mut self,
+        from: &str,
+        to: &str,
+        ids: Option<&[usize]>,
+    ) -> Result<Vec<usize>> {
+        self.check_ids(ids)?;
+
+        let mut replacements = Vec::new();
+        for entity in &self.entities {
+            if entity.name() != from || ids.is_some_and(|ids| !ids.contains(&entity.id())) {
+                continue;
+            }
+            if kind(from) != kind(to) {
+                return Err(ReplaceError::Incompatible {
+                    id: entity.id(),
+                    name: to.into(),
+                });
+            }
+
+            let unknown_size = |name: &str| ReplaceError::UnknownSize {
+                id: entity.id(),
+                name: name.into(),
+            };
+            if entity.footprint().is_none() {
+                return Err(unknown_size(from));
+            }
+
+            let mut model = model::Entity::from(entity.clone());
+            model.name = to.into();
+            let mut replacement = Entity::from(model);
+            let size = replacement.footprint().ok_or_else(|| unknown_size(to))?;
+            *replacement.position_mut() = position_on_tile(entity.top_left_tile(), size);
+            replacements.push(replacement);
+        }
+
+        // Tiles of the entities that stay and of the replacements
+        let mut occupied: HashMap<Tile, usize> = HashMap::new();
+        for entity in &self.entities {
+            if !replacements.iter().any(|r| r.id() == entity.id()) {
+                occupied.extend(entity.tiles().into_iter().map(|t| (t, entity.id())));
+            }
+        }
+        for replacement in &replacements {
+            for tile in replacement.tiles() {
+                if let Some(other) = occupied.insert(tile, replacement.id()) {
+                    return Err(ReplaceError::Overlap {
+                        id: replacement.id(),
+                        other,
+                        tile,
+                    });
+                }
+            }
+        }
+
+        let replaced = replacements.iter().map(Entity::id).collect();
+        for replacement in replacements {
+            let id = replacement.id();
+            self.entities[id] = replacement;
+        }
+        Ok(replaced)
+    }
+
+    fn check_ids(&self, ids: Option<&[usize]>) -> Result<()> {
+        match ids
+            .into_iter()
+            .flatten()
+            .find(|&&id| id >= self.entities.len())
+        {
+            Some(&id) => Err(ReplaceError::InvalidId(id)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// All signals used by the entity.
+fn signals_mut(entity: &mut Entity) -> Vec<&mut Signal> {
+    let mut signals = Vec::new();
+    match entity {
+        Entity::DeciderCombinator { condition: c, .. } => {
+            signals.extend([
+                &mut c.first_signal,
+                &mut c.second_signal,
+                &mut c.output_signal,
+            ]);
+        }
+        Entity::ArithmeticCombinator { condition: c, .. } => {
+            signals.extend([
+                &mut c.first_signal,
+                &mut c.second_signal,
+                &mut c.output_signal,
+            ]);
+        }
+        Entity::ConstantCombinator { condition, .. } => {
+            return condition.iter_mut().map(|f| &mut f.signal).collect();
+        }
+        Entity::Lamp {
+            condition: Some(c), ..
+        } => signals.extend([&mut c.first_signal, &mut c.second_signal]),
+        Entity::Unknown(e) => {
+            return e
+                .control_behavior
+                .iter_mut()
+                .flat_map(control_signals_mut)
+                .collect()
+        }
+        Entity::Lamp { .. } | Entity::ElectricPole { .. } => {}
+    }
+    signals.into_iter().flatten().collect()
+}
+
+fn control_signals_mut(c: &mut ControlBehavior) -> Vec<&mut Signal> {
+    let mut signals = Vec::new();
+    if let Some(d) = &mut c.decider_conditions {
+        signals.extend([
+            &mut d.first_signal,
+            &mut d.second_signal,
+            &mut d.output_signal,
+        ]);
+    }
+    if let Some(a) = &mut c.arithmetic_conditions {
+        signals.extend([
+            &mut a.first_signal,
+            &mut a.second_signal,
+            &mut a.output_signal,
+        ]);
+    }
+    if let Some(l) = &mut c.circuit_condition {
+        signals.extend([&mut l.first_signal, &mut l.second_signal]);
+    }
+    let filters = c.filters.iter_mut().flatten().map(|f| &mut f.signal);
+    signals.into_iter().flatten().chain(filters).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        abstract_model::Wire,
+        model::{Icon, Position},
+        primitives,
+    };
+
+    fn pole(id: usize, x: f32) -> Entity {
+        Entity::ElectricPole {
+            id,
+            pole_type: PoleType::Medium,
+            position: Position { x, y: 0.5 },
+            neighbours: Vec::new(),
+            connections: Vec::new(),
+            tags: None,
+        }
+    }
+
+    #[test]
+    fn signals_are_replaced() {
+        let template = primitives::memory_cell();
+        let mut blueprint = Blueprint {
+            icons: vec![Icon {
+                index: 1,
+                signal: Signal::virtual_signal("signal-green"),
+            }],
+            ..Blueprint::new(template.blueprint().entities.clone())
+        };
+        let (green, iron) = (
+            Signal::virtual_signal("signal-green"),
+            Signal::item("iron-plate"),
+        );
+        assert_eq!(Ok(green.clone()), "signal-green".parse());
+        assert_eq!(Ok(iron.clone()), "item:iron-plate".parse());
+        assert_eq!(Ok(Signal::fluid("water")), "fluid:water".parse());
+        let uses = |blueprint: &mut Blueprint, signal: &Signal| {
+            blueprint
+                .entities
+                .iter_mut()
+                .flat_map(signals_mut)
+                .filter(|s| **s == *signal)
+                .count()
+        };
+        let before = uses(&mut blueprint, &green);
+        let first = (0..blueprint.entities.len())
+            .find(|&id| {
+                signals_mut(&mut blueprint.entities[id])
+                    .iter()
+                    .any(|s| **s == green)
+            })
+            .unwrap();
+
+        let replaced = blueprint
+            .replace_signal(&green, &iron, Some(&[first]))
+            .unwrap();
+        assert!(replaced > 0);
+        assert_eq!(before - replaced, uses(&mut blueprint, &green));
+        assert_eq!(green, blueprint.icons[0].signal);
+        let rest = blueprint.replace_signal(&green, &iron, None).unwrap();
+        assert_eq!(before, replaced + rest - 1);
+        assert_eq!(0, uses(&mut blueprint, &green));
+        assert_eq!(iron, blueprint.icons[0].signal);
+        assert_eq!(
+            Err(ReplaceError::InvalidId(99)),
+            blueprint.replace_signal(&iron, &green, Some(&[99]))
+        );
+    }
+
+    #[test]
+    fn entities_are_replaced_if_they_fit() {
+        let mut blueprint = Blueprint::new(vec![pole(0, 0.5), pole(1, 4.5), pole(2, 5.5)]);
+        blueprint.connect_electric_poles(0, 1).unwrap();
+        blueprint.connect_wire(0, 1, Wire::Red).unwrap();
+
+        assert!(matches!(
+            blueprint.replace_entity("medium-electric-pole", "substation", None),
+            Err(ReplaceError::Overlap {
+                id: 2,
+                other: 1,
+                ..
+            })
+        ));
+        assert_eq!("medium-electric-pole", blueprint.entities[0].name());
+        assert!(matches!(
+            blueprint.replace_entity("medium-electric-pole", "small-lamp", None),
+            Err(ReplaceError::Incompatible { id: 0, .. })
+        ));
+
+        let replaced = blueprint
+            .replace_entity("medium-electric-pole", "substation", Some(&[0, 2]))
+            .unwrap();
+        assert_eq!(vec![0, 2], replaced);
+        match &blueprint.entities[0] {
+            Entity::ElectricPole {
+                pole_type,
+                position,
+                neighbours,
+                connections,
+                ..
+            } => {
+                assert_eq!(PoleType::Substation, *pole_type);
+                assert_eq!(Position { x: 1.0, y: 1.0 }, *position);
+                assert_eq!(vec![1], *neighbours);
+                assert_eq!(1, connections.len());
+            }
+            e => panic!("unexpected {:?}", e),
+        }
+    }
+
+    #[test]
+    fn entities_of_unknown_size_are_not_replaced() {
+        let chest = |id: usize, name: &str| {
+            Entity::Unknown(model::Entity {
+                entity_number: id as u32 + 1,
+                name: name.into(),
+                position: Position {
+                    x: id as f32 + 0.5,
+                    y: 0.5,
+                },
+                direction: None,
+                connections: None,
+                control_behavior: None,
+                neighbours: None,
+                tags: None,
+            })
+        };
+        let mut blueprint = Blueprint::new(vec![chest(0, "iron-chest"), chest(1, "modded-chest")]);
+
+        assert_eq!(
+            Err(ReplaceError::UnknownSize {
+                id: 0,
+                name: "modded-chest".into()
+            }),
+            blueprint.replace_entity("iron-chest", "modded-chest", None)
+        );
+        assert_eq!(
+            Err(ReplaceError::UnknownSize {
+                id: 1,
+                name: "modded-chest".into()
+            }),
+            blueprint.replace_entity("modded-chest", "iron-chest", None)
+        );
+        assert_eq!(
+            Ok(vec![0]),
+            blueprint.replace_entity("iron-chest", "steel-chest", None)
+        );
+    }
+}
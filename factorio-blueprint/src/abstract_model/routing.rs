@@ -0,0 +1,277 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+use crate::model::Position;
+
+use super::{
+    prototype::{distance, position_on_tile, Tile},
+    utility::{Result, UtilityError},
+    Blueprint, Connector, Entity, PoleType, Side, Wire,
+};
+
+// Placing a pole is always more expensive than any number of additional wires.
+const POLE_COST: u64 = 1_000_000;
+const WIRE_COST: u64 = 1_000;
+
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+enum Node {
+    Existing(Connector),
+    NewPole(Tile),
+}
+
+impl Blueprint {
+    /// Connects two connectors with a wire of the given colour. If they are out of reach,
+    /// the wire is relayed over existing poles which do not carry a wire of this colour yet
+    /// and over newly placed poles of `pole_type`. The chain with the fewest new poles
+    /// (and then the fewest wires) is chosen. Connectors that already share a network of
+    /// this colour are left untouched, and wires which already exist are reused.
+    ///
+    /// Returns the ids of the newly placed poles.
+    pub fn route_wire(
+        &mut self,
+        c1: Connector,
+        c2: Connector,
+        wire: Wire,
+        pole_type: PoleType,
+    ) -> Result<Vec<usize>> {
+        for c in [&c1, &c2] {
+            if !self.connectable(c) {
+                return Err(UtilityError::InvalidId(c.id));
+            }
+        }
+
+        let networks = self.circuit_networks();
+        let network = |c: &Connector| -> HashSet<Connector> {
+            match networks.network_of(c, wire) {
+                Some(n) => n.connectors.iter().cloned().collect(),
+                None => HashSet::from([c.clone()]),
+            }
+        };
+        let sources = network(&c1);
+        if sources.contains(&c2) {
+            return Ok(Vec::new());
+        }
+        let targets = network(&c2);
+
+        let path = self.shortest_relay_chain(&sources, &targets, &c1, &c2, wire, pole_type)?;
+
+        // Materialise the chain
+        let size = pole_type.prototype().size;
+        let mut new_poles = Vec::new();
+        let mut connectors = Vec::new();
+        for node in path {
+            match node {
+                Node::Existing(c) => connectors.push(c),
+                Node::NewPole(tile) => {
+                    let id = self.entities.len();
+                    self.entities.push(Entity::ElectricPole {
+                        id,
+                        pole_type,
+                        position: position_on_tile(tile, size),
+                        neighbours: Vec::new(),
+                        connections: Vec::new(),
+                        tags: None,
+                    });
+                    new_poles.push(id);
+                    connectors.push(Connector { id, side: Side::One });
+                }
+            }
+        }
+
+        for pair in connectors.windows(2) {
+            self.connect_wire_with_side(pair[0].clone(), pair[1].clone(), wire)?;
+
+            // Relay poles are also joined by copper, so they do not end up as isolated poles
+            let is_pole = |id: usize| matches!(self.entities[id], Entity::ElectricPole { .. });
+            if is_pole(pair[0].id) && is_pole(pair[1].id) {
+                self.connect_electric_poles(pair[0].id, pair[1].id)?;
+            }
+        }
+
+        Ok(new_poles)
+    }
+
+    /// Dijkstra over the connectors of both networks, free existing poles and free tiles.
+    fn shortest_relay_chain(
+        &self,
+        sources: &HashSet<Connector>,
+        targets: &HashSet<Connector>,
+        c1: &Connector,
+        c2: &Connector,
+        wire: Wire,
+        pole_type: PoleType,
+    ) -> Result<Vec<Node>> {
+        let node = |c: &Connector| Node::Existing(c.clone());
+        let target_nodes: HashSet<Node> = targets.iter().map(node).collect();
+
+        let mut candidates: Vec<Node> = sources.iter().chain(targets).map(node).collect();
+        candidates.extend(
+            self.entities
+                .iter()
+                .filter(|e| matches!(e, Entity::ElectricPole { .. }))
+                .filter(|e| e.connections().into_iter().flatten().all(|c| c.wire != wire))
+                .map(|e| Node::Existing(Connector { id: e.id(), side: Side::One })),
+        );
+
+        let size = pole_type.prototype().size;
+        let reach = pole_type.wire_reach();
+        let occupied = self.occupied_tiles();
+        let (a, b) = (self.node_position(&node(c1), size), self.node_position(&node(c2), size));
+        let margin = reach.ceil() as i32;
+        for x in a.x.min(b.x).floor() as i32 - margin..=a.x.max(b.x).ceil() as i32 + margin {
+            for y in a.y.min(b.y).floor() as i32 - margin..=a.y.max(b.y).ceil() as i32 + margin {
+                if pole_tiles((x, y), size).all(|t| !occupied.contains(&t)) {
+                    candidates.push(Node::NewPole((x, y)));
+                }
+            }
+        }
+        candidates.sort();
+        candidates.dedup();
+
+        let positions: Vec<Position> =
+            candidates.iter().map(|n| self.node_position(n, size)).collect();
+        let reaches: Vec<f32> = candidates.iter().map(|n| self.node_reach(n, pole_type)).collect();
+
+        // Bucket the candidates into cells as large as the longest reach, so only the
+        // neighbouring cells have to be scanned for the nodes in reach of a node
+        let cell_size = reaches.iter().copied().fold(reach, f32::max).max(1.0);
+        let cell =
+            |p: &Position| ((p.x / cell_size).floor() as i32, (p.y / cell_size).floor() as i32);
+        let mut cells: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+        for (i, p) in positions.iter().enumerate() {
+            cells.entry(cell(p)).or_default().push(i);
+        }
+
+        let mut cost = vec![u64::MAX; candidates.len()];
+        let mut previous: Vec<Option<usize>> = vec![None; candidates.len()];
+        let mut heap = BinaryHeap::new();
+        for (i, n) in candidates.iter().enumerate() {
+            if matches!(n, Node::Existing(c) if sources.contains(c)) {
+                cost[i] = 0;
+                heap.push(Reverse((0, i)));
+            }
+        }
+
+        while let Some(Reverse((current_cost, current))) = heap.pop() {
+            if cost[current] < current_cost {
+                continue;
+            }
+
+            let mut path = vec![current];
+            while let Some(p) = previous[*path.last().unwrap()] {
+                path.push(p);
+            }
+            if target_nodes.contains(&candidates[current]) {
+                return Ok(path.into_iter().rev().map(|i| candidates[i].clone()).collect());
+            }
+
+            // New poles of the chain so far must not overlap each other
+            let taken: Vec<Tile> = path
+                .iter()
+                .filter_map(|&i| match candidates[i] {
+                    Node::NewPole(tile) => Some(tile),
+                    Node::Existing(_) => None,
+                })
+                .flat_map(|tile| pole_tiles(tile, size))
+                .collect();
+
+            let position = &positions[current];
+            let (cx, cy) = cell(position);
+            let nearby = (cx - 1..=cx + 1)
+                .flat_map(|x| (cy - 1..=cy + 1).map(move |y| (x, y)))
+                .filter_map(|c| cells.get(&c))
+                .flatten();
+            for &next in nearby {
+                let length = distance(position, &positions[next]);
+                if length > reaches[current].min(reaches[next]) || next == current {
+                    continue;
+                }
+
+                let step = match candidates[next] {
+                    Node::NewPole(tile) if pole_tiles(tile, size).any(|t| taken.contains(&t)) => {
+                        continue
+                    }
+                    Node::NewPole(_) => WIRE_COST + POLE_COST,
+                    Node::Existing(_) => WIRE_COST,
+                };
+                let next_cost = current_cost + step + (length * 10.0).round() as u64;
+                if next_cost < cost[next] {
+                    cost[next] = next_cost;
+                    previous[next] = Some(current);
+                    heap.push(Reverse((next_cost, next)));
+                }
+            }
+        }
+
+        Err(UtilityError::NoFreeTile)
+    }
+
+    fn node_position(&self, node: &Node, pole_size: (u32, u32)) -> Position {
+        match node {
+            Node::Existing(c) => self.entities[c.id].connection_point(c.side),
+            Node::NewPole(tile) => position_on_tile(*tile, pole_size),
+        }
+    }
+
+    fn node_reach(&self, node: &Node, pole_type: PoleType) -> f32 {
+        match node {
+            Node::Existing(c) => self.entities[c.id].wire_reach().unwrap_or(0.0),
+            Node::NewPole(_) => pole_type.wire_reach(),
+        }
+    }
+}
+
+/// Tiles covered by a new pole of the given size with its top left corner on `tile`.
+fn pole_tiles(tile: Tile, size: (u32, u32)) -> impl Iterator<Item = Tile> {
+    (0..size.0 as i32)
+        .flat_map(move |dx| (0..size.1 as i32).map(move |dy| (tile.0 + dx, tile.1 + dy)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Direction;
+
+    fn constant_combinator(id: usize, x: f32) -> Entity {
+        Entity::ConstantCombinator {
+            id,
+            position: Position { x, y: 0.5 },
+            direction: Direction::East,
+            is_on: true,
+            connections: Vec::new(),
+            condition: Vec::new(),
+            tags: None,
+        }
+    }
+
+    #[test]
+    fn route_wire_relays_over_new_poles() {
+        let mut b = Blueprint::new(vec![constant_combinator(0, 0.5), constant_combinator(1, 30.5)]);
+        let (c1, c2) = (Connector { id: 0, side: Side::One }, Connector { id: 1, side: Side::One });
+
+        let poles = b.route_wire(c1.clone(), c2.clone(), Wire::Red, PoleType::Medium).unwrap();
+        assert_eq!(3, poles.len());
+        let networks = b.circuit_networks();
+        assert!(networks.network_of(&c1, Wire::Red).unwrap().connectors.contains(&c2));
+        assert!(networks.network_of(&c1, Wire::Green).is_none());
+        assert!(b.check_wire_reach().is_empty());
+
+        // Already connected, nothing changes
+        let poles = b.route_wire(c2, c1, Wire::Red, PoleType::Medium).unwrap();
+        assert!(poles.is_empty());
+    }
+
+    #[test]
+    fn relay_poles_do_not_overlap() {
+        let mut b = Blueprint::new(vec![constant_combinator(0, 0.5), constant_combinator(1, 80.5)]);
+        let (c1, c2) = (Connector { id: 0, side: Side::One }, Connector { id: 1, side: Side::One });
+
+        let poles = b.route_wire(c1, c2, Wire::Green, PoleType::Substation).unwrap();
+        assert!(!poles.is_empty());
+        let tiles: Vec<Tile> = b.entities.iter().flat_map(Entity::tiles).collect();
+        assert_eq!(tiles.len(), b.occupied_tiles().len());
+        assert!(b.check_wire_reach().is_empty());
+    }
+}
@@ -1,19 +1,23 @@
 use std::{
-    cmp::{max, min},
-    collections::HashMap,
+    cmp::{max, min, Reverse},
+    collections::{BinaryHeap, HashMap, HashSet},
     error::Error,
     fmt,
 };
 
-use crate::model::Position;
+use crate::model::{self, Position};
 
-use super::{Blueprint, Connection, Connector, Entity, Side, Wire};
+use super::{
+    prototype::{distance, position_on_tile, Tile},
+    Blueprint, Connection, Connector, Entity, PoleType, Side, Wire,
+};
 
 #[derive(Debug, PartialEq)]
 pub enum UtilityError {
     InvalidId(usize),
     DuplicateIds,
     InvalidOperation,
+    NoFreeTile,
 }
 
 impl fmt::Display for UtilityError {
@@ -25,6 +29,7 @@ impl fmt::Display for UtilityError {
                 "received duplicate ids which is not allowed for this function"
             ),
             Self::InvalidOperation => write!(f, "tried to perform an invalid operation"),
+            Self::NoFreeTile => write!(f, "could not find a free tile to place an entity"),
         }
     }
 }
@@ -40,48 +45,288 @@ pub enum SideCount {
     Two,
 }
 
+/// What happens to wires and pole connections that lead from cloned entities
+/// to entities which were not cloned.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ExternalLinks {
+    /// Copy the links as they are, the outside entities do not link back to the clones.
+    OneWay,
+
+    /// Remove the links from the clones.
+    Drop,
+
+    /// Keep the links and connect the outside entities back to the clones.
+    Keep,
+}
+
+/// Maps the ids of cloned entities to the ids of their clones.
+/// The pairs are in the same order as the ids that were cloned.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct IdMap {
+    pairs: Vec<(usize, usize)>,
+}
+
+impl IdMap {
+    pub fn get(&self, old_id: usize) -> Option<usize> {
+        self.pairs
+            .iter()
+            .find(|(old, _)| *old == old_id)
+            .map(|(_, new)| *new)
+    }
+
+    pub fn pairs(&self) -> &[(usize, usize)] {
+        &self.pairs
+    }
+
+    pub fn old_ids(&self) -> Vec<usize> {
+        self.pairs.iter().map(|(old, _)| *old).collect()
+    }
+
+    pub fn new_ids(&self) -> Vec<usize> {
+        self.pairs.iter().map(|(_, new)| *new).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+}
+
+/// Direction in which two neighbouring copies of a replicated grid touch.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Axis {
+    Horizontal,
+    Vertical,
+}
+
+/// A single copy inside of a grid created by [`Blueprint::replicate`].
+#[derive(Copy, Clone, Debug)]
+pub struct GridCell<'a> {
+    pub col: usize,
+    pub row: usize,
+
+    /// Ids of this copy in the same order as the ids that were replicated.
+    pub ids: &'a [usize],
+}
+
+/// Called once for every pair of neighbouring copies.
+/// The first cell is always the one to the left of or above the second one.
+pub type LinkFn<'a> = &'a mut dyn FnMut(&mut Blueprint, Axis, GridCell, GridCell) -> Result<()>;
+
+/// Called once for every copy (including the original) to customise it.
+pub type CustomiseFn<'a> = &'a mut dyn FnMut(&mut Blueprint, GridCell) -> Result<()>;
+
 impl Blueprint {
-    pub fn clone_entities(&mut self, ids: &Vec<usize>) -> Result<Vec<usize>> {
-        if let Some(id) = self.contains_invalid_id(&ids) {
+    /// Clones the given entities and appends the clones to the blueprint.
+    /// Wires and pole connections between the cloned entities are copied,
+    /// links to entities outside of the cloned set are copied one way.
+    pub fn clone_entities(&mut self, ids: &[usize]) -> Result<IdMap> {
+        self.clone_entities_with(ids, ExternalLinks::OneWay)
+    }
+
+    /// Clones the given entities and appends the clones to the blueprint.
+    /// The clones get consecutive ids in the order of `ids`.
+    pub fn clone_entities_with(&mut self, ids: &[usize], external: ExternalLinks) -> Result<IdMap> {
+        if let Some(id) = ids.iter().copied().find(|&id| id >= self.entities.len()) {
             return Err(UtilityError::InvalidId(id));
         }
 
-        let mut next_id = self.entities.len();
         let mut id_map = HashMap::new();
-        for &id in ids {
+        for (next_id, &id) in (self.entities.len()..).zip(ids) {
             let had_value = id_map.insert(id, next_id);
-            next_id += 1;
 
-            if let Some(_) = had_value {
+            if had_value.is_some() {
                 return Err(UtilityError::DuplicateIds);
             }
         }
 
+        let pairs: Vec<(usize, usize)> = ids.iter().map(|id| (*id, id_map[id])).collect();
+
         let mut new_entities = Vec::new();
-        for (&old_id, &new_id) in &id_map {
+        for &(old_id, new_id) in &pairs {
             let mut entity = self.entities[old_id].clone();
             entity.update_id(new_id);
             entity.update_connections(&id_map);
             new_entities.push(entity);
         }
 
-        new_entities.sort_by(|a, b| a.id().cmp(&b.id()));
         self.entities.append(&mut new_entities);
 
-        Ok(id_map.into_values().collect())
+        let new_ids: HashSet<usize> = id_map.into_values().collect();
+        for &(_, new_id) in &pairs {
+            match external {
+                ExternalLinks::OneWay => {}
+                ExternalLinks::Drop => self.entities[new_id].retain_links(|id| new_ids.contains(&id)),
+                ExternalLinks::Keep => self.add_reverse_links(new_id, &new_ids),
+            }
+        }
+
+        Ok(IdMap { pairs })
     }
 
-    fn contains_invalid_id(&self, ids: &Vec<usize>) -> Option<usize> {
-        for &id in ids {
-            if self.id_invalid(id) {
-                return Some(id);
+    /// Adds the missing back references for links of `id` that leave the given set.
+    fn add_reverse_links(&mut self, id: usize, inside: &HashSet<usize>) {
+        let entity = &self.entities[id];
+
+        let mut wires = entity.wires();
+        wires.retain(|c| !inside.contains(&c.to.id));
+        let mut poles = entity.neighbours();
+        poles.retain(|n| !inside.contains(n));
+
+        for c in wires {
+            if let Some(target) = self.entities.get_mut(c.to.id) {
+                target.push_wire(Connection {
+                    from_side: c.to.side,
+                    to: Connector {
+                        id,
+                        side: c.from_side,
+                    },
+                    wire: c.wire,
+                });
             }
         }
 
-        None
+        for pole in poles {
+            match self.entities.get_mut(pole) {
+                Some(Entity::ElectricPole { neighbours, .. }) => neighbours.push(id),
+                Some(Entity::Unknown(e)) => e
+                    .neighbours
+                    .get_or_insert_with(Vec::new)
+                    .push(id as u32 + 1),
+                _ => {}
+            }
+        }
+    }
+
+    /// Replicates the given entities into a grid of `cols` x `rows` copies.
+    /// The original entities become the copy at column 0, row 0 and
+    /// the copy at (col, row) is moved by (col * dx, row * dy).
+    ///
+    /// `customise` is called for every copy in column major order
+    /// and afterwards `link` is called for every pair of neighbouring copies.
+    ///
+    /// Links of the copies to entities outside of `ids` are dropped.
+    ///
+    /// Returns the ids of the copies indexed as `grid[col][row][i]`,
+    /// where `i` is the position of the original id in `ids`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn replicate(
+        &mut self,
+        ids: &[usize],
+        cols: usize,
+        rows: usize,
+        dx: f32,
+        dy: f32,
+        link: Option<LinkFn>,
+        customise: Option<CustomiseFn>,
+    ) -> Result<Vec<Vec<Vec<usize>>>> {
+        if cols == 0 || rows == 0 {
+            return Err(UtilityError::InvalidOperation);
+        }
+
+        if let Some(id) = ids.iter().copied().find(|&id| id >= self.entities.len()) {
+            return Err(UtilityError::InvalidId(id));
+        }
+
+        let mut grid = Vec::with_capacity(cols);
+        for col in 0..cols {
+            let mut column = Vec::with_capacity(rows);
+            for row in 0..rows {
+                if col == 0 && row == 0 {
+                    column.push(ids.to_vec());
+                    continue;
+                }
+
+                let copy = self
+                    .clone_entities_with(ids, ExternalLinks::Drop)?
+                    .new_ids();
+
+                self.translate_entities(&copy, (col as f32) * dx, (row as f32) * dy)?;
+                column.push(copy);
+            }
+            grid.push(column);
+        }
+
+        let cell = |col: usize, row: usize| GridCell {
+            col,
+            row,
+            ids: &grid[col][row],
+        };
+
+        if let Some(customise) = customise {
+            for col in 0..cols {
+                for row in 0..rows {
+                    customise(self, cell(col, row))?;
+                }
+            }
+        }
+
+        if let Some(link) = link {
+            for col in 0..cols {
+                for row in 0..rows {
+                    if row > 0 {
+                        link(self, Axis::Vertical, cell(col, row - 1), cell(col, row))?;
+                    }
+
+                    if col > 0 {
+                        link(self, Axis::Horizontal, cell(col - 1, row), cell(col, row))?;
+                    }
+                }
+            }
+        }
+
+        Ok(grid)
+    }
+
+    pub fn translate_entities(&mut self, ids: &[usize], dx: f32, dy: f32) -> Result<()> {
+        if let Some(id) = ids.iter().copied().find(|&id| id >= self.entities.len()) {
+            return Err(UtilityError::InvalidId(id));
+        }
+
+        for &id in ids {
+            let position = self.entities[id].position_mut();
+            position.x += dx;
+            position.y += dy;
+        }
+
+        Ok(())
+    }
+
+    /// Moves all entities and tiles of `other` by (dx, dy) and appends them to the blueprint.
+    /// Wires and pole connections among them are kept.
+    pub fn append(&mut self, other: Blueprint, dx: f32, dy: f32) -> IdMap {
+        let offset = self.entities.len();
+        let id_map: HashMap<usize, usize> =
+            (0..other.entities.len()).map(|id| (id, id + offset)).collect();
+
+        for mut entity in other.entities {
+            let new_id = entity.id() + offset;
+            entity.update_id(new_id);
+            entity.update_connections(&id_map);
+            let position = entity.position_mut();
+            position.x += dx;
+            position.y += dy;
+            self.entities.push(entity);
+        }
+        for mut tile in other.tiles {
+            tile.position.x += dx;
+            tile.position.y += dy;
+            self.tiles.push(tile);
+        }
+
+        let mut pairs: Vec<(usize, usize)> = id_map.into_iter().collect();
+        pairs.sort_unstable();
+        IdMap { pairs }
     }
 
-    fn id_invalid(&self, id: usize) -> bool {
+    fn contains_invalid_id(&self, ids: &[usize]) -> Option<usize> {
+        ids.iter().copied().find(|&id| self.id_invalid(id))
+    }
+
+    pub(super) fn id_invalid(&self, id: usize) -> bool {
         if id >= self.entities.len() {
             return true;
         }
@@ -93,6 +338,14 @@ impl Blueprint {
         false
     }
 
+    /// Whether the entity of the connector exists and takes wires on its side.
+    pub(super) fn connectable(&self, c: &Connector) -> bool {
+        match self.entities.get(c.id) {
+            Some(e) => e.can_connect(c.side),
+            None => false,
+        }
+    }
+
     pub fn connect_electric_poles(&mut self, id1: usize, id2: usize) -> Result<()> {
         if id1 == id2 {
             return Err(UtilityError::DuplicateIds);
@@ -160,35 +413,357 @@ impl Blueprint {
             return Err(UtilityError::DuplicateIds);
         }
 
-        if self.id_invalid(c1.id) || !self.entities[c1.id].can_connect(c1.side) {
+        if !self.connectable(&c1) {
             return Err(UtilityError::InvalidId(c1.id));
         }
 
-        if self.id_invalid(c2.id) || !self.entities[c2.id].can_connect(c2.side) {
+        if !self.connectable(&c2) {
             return Err(UtilityError::InvalidId(c2.id));
         }
 
-        let result1 = self.entities[c1.id].connections_mut().map(|cs| {
-            cs.push(Connection {
-                from_side: c1.side,
-                to: c2.clone(),
-                wire,
-            })
+        self.entities[c1.id].push_wire(Connection {
+            from_side: c1.side,
+            to: c2.clone(),
+            wire,
+        });
+        self.entities[c2.id].push_wire(Connection {
+            from_side: c2.side,
+            to: c1,
+            wire,
         });
 
-        let result2 = self.entities[c2.id].connections_mut().map(|cs| {
-            cs.push(Connection {
-                from_side: c2.side,
-                to: c1.clone(),
-                wire,
+        Ok(())
+    }
+}
+
+impl Blueprint {
+    /// Places poles of the given type on free tiles, such that every entity in `ids`
+    /// is inside of the supply area of a pole. Entities that are already supplied by
+    /// an existing pole are skipped. Afterwards the new poles and the existing poles that
+    /// supply the entities are joined into one network, placing additional relay poles where
+    /// the wire reach is too short. Other poles of the blueprint are left alone.
+    ///
+    /// Returns the ids of the newly placed poles.
+    pub fn place_power_poles(&mut self, ids: &[usize], pole_type: PoleType) -> Result<Vec<usize>> {
+        self.place_power_poles_avoiding(ids, pole_type, &HashSet::new())
+    }
+
+    /// Like [`Blueprint::place_power_poles`], but no pole is placed on the `reserved` tiles.
+    pub fn place_power_poles_avoiding(
+        &mut self,
+        ids: &[usize],
+        pole_type: PoleType,
+        reserved: &HashSet<Tile>,
+    ) -> Result<Vec<usize>> {
+        if let Some(id) = self.contains_invalid_id(ids) {
+            return Err(UtilityError::InvalidId(id));
+        }
+
+        let mut occupied = self.occupied_tiles();
+        occupied.extend(reserved);
+        let mut placed = Vec::new();
+
+        let poles: Vec<(usize, Position, f32)> = self
+            .entities
+            .iter()
+            .filter_map(|e| match e {
+                Entity::ElectricPole { id, pole_type, position, .. } => {
+                    Some((*id, position.clone(), pole_type.supply_area_distance()))
+                }
+                _ => None,
             })
+            .collect();
+        let supplied =
+            |id: usize, (_, p, d): &(usize, Position, f32)| supplies(p, *d, &self.entities[id]);
+
+        let supplying: Vec<usize> = poles
+            .iter()
+            .filter(|pole| ids.iter().any(|&id| supplied(id, pole)))
+            .map(|pole| pole.0)
+            .collect();
+        let mut uncovered: Vec<usize> = ids
+            .iter()
+            .copied()
+            .filter(|&id| !poles.iter().any(|pole| supplied(id, pole)))
+            .collect();
+        uncovered.sort_unstable();
+        uncovered.dedup();
+        for tile in self.plan_pole_tiles(&uncovered, pole_type, &occupied)? {
+            placed.push(self.add_pole(tile, pole_type, &mut occupied));
+        }
+
+        let mut members = supplying;
+        members.extend(&placed);
+        self.join_pole_networks(members, pole_type, &mut occupied, &mut placed)?;
+
+        Ok(placed)
+    }
+
+    /// Greedy set cover: repeatedly picks the free tile whose pole would supply the most
+    /// entities that are not supplied yet. Ties are broken by tile order to stay deterministic.
+    fn plan_pole_tiles(
+        &self,
+        ids: &[usize],
+        pole_type: PoleType,
+        occupied: &HashSet<Tile>,
+    ) -> Result<Vec<Tile>> {
+        let size = pole_type.prototype().size;
+        let supply = pole_type.supply_area_distance();
+        let reach = supply.ceil() as i32 + size.0.max(size.1) as i32;
+
+        let mut candidates: HashMap<Tile, Vec<usize>> = HashMap::new();
+        for &id in ids {
+            let entity = &self.entities[id];
+            let (left, top) = entity.top_left_tile();
+            for x in left - reach..=left + reach {
+                for y in top - reach..=top + reach {
+                    let tile = (x, y);
+                    if is_free(tile, size, occupied)
+                        && supplies(&position_on_tile(tile, size), supply, entity)
+                    {
+                        candidates.entry(tile).or_default().push(id);
+                    }
+                }
+            }
+        }
+
+        let coverable: HashSet<usize> = candidates.values().flatten().copied().collect();
+        if ids.iter().any(|id| !coverable.contains(id)) {
+            return Err(UtilityError::NoFreeTile);
+        }
+
+        let mut heap: BinaryHeap<(usize, Reverse<Tile>)> = candidates
+            .iter()
+            .map(|(&tile, covered)| (covered.len(), Reverse(tile)))
+            .collect();
+        let mut covered = HashSet::new();
+        let mut blocked: HashSet<Tile> = HashSet::new();
+        let mut tiles = Vec::new();
+
+        while covered.len() < ids.len() {
+            let (count, Reverse(tile)) = heap.pop().ok_or(UtilityError::NoFreeTile)?;
+            if blocked.contains(&tile) {
+                continue;
+            }
+
+            // Lazy evaluation: re-queue the candidate if its count is outdated
+            let actual = candidates[&tile].iter().filter(|id| !covered.contains(*id)).count();
+            if actual == 0 {
+                continue;
+            }
+            if actual < count {
+                heap.push((actual, Reverse(tile)));
+                continue;
+            }
+
+            covered.extend(candidates[&tile].iter().copied());
+            for dx in 1 - size.0 as i32..size.0 as i32 {
+                for dy in 1 - size.1 as i32..size.1 as i32 {
+                    blocked.insert((tile.0 + dx, tile.1 + dy));
+                }
+            }
+            tiles.push(tile);
+        }
+
+        Ok(tiles)
+    }
+
+    fn add_pole(&mut self, tile: Tile, pole_type: PoleType, occupied: &mut HashSet<Tile>) -> usize {
+        let size = pole_type.prototype().size;
+        let id = self.entities.len();
+        self.entities.push(Entity::ElectricPole {
+            id,
+            pole_type,
+            position: position_on_tile(tile, size),
+            neighbours: Vec::new(),
+            connections: Vec::new(),
+            tags: None,
         });
+        occupied.extend(self.entities[id].tiles());
+        id
+    }
 
-        match (result1, result2) {
-            (Some(_), Some(_)) => Ok(()),
-            _ => unreachable!(),
+    /// Connects the given poles into a single network (minimum spanning tree over the poles
+    /// within reach of each other). Poles which are already connected, also over other poles,
+    /// are not connected again. Components that are too far apart are bridged with relay poles
+    /// of the given type.
+    fn join_pole_networks(
+        &mut self,
+        mut poles: Vec<usize>,
+        pole_type: PoleType,
+        occupied: &mut HashSet<Tile>,
+        placed: &mut Vec<usize>,
+    ) -> Result<()> {
+        poles.sort_unstable();
+        if poles.is_empty() {
+            return Ok(());
+        }
+
+        let mut components = PoleComponents::new(self);
+
+        let mut pairs = Vec::new();
+        for (i, &a) in poles.iter().enumerate() {
+            for &b in &poles[i + 1..] {
+                let length = self.pole_distance(a, b);
+                if length <= self.pole_reach(a, b) {
+                    pairs.push((length, a, b));
+                }
+            }
+        }
+        pairs.sort_by(|x, y| x.0.total_cmp(&y.0));
+        for (_, a, b) in pairs {
+            if components.union(a, b) {
+                self.connect_electric_poles(a, b)?;
+            }
+        }
+
+        // Bridge the remaining gaps starting from the component of the first pole
+        loop {
+            let root = components.find(poles[0]);
+            let (inside, outside): (Vec<usize>, Vec<usize>) =
+                poles.iter().partition(|&&p| components.find(p) == root);
+
+            let closest = inside
+                .iter()
+                .flat_map(|&a| outside.iter().map(move |&b| (a, b)))
+                .min_by(|x, y| self.pole_distance(x.0, x.1).total_cmp(&self.pole_distance(y.0, y.1)));
+            let (from, to) = match closest {
+                Some(pair) => pair,
+                None => return Ok(()),
+            };
+
+            if self.pole_distance(from, to) <= self.pole_reach(from, to) {
+                components.union(from, to);
+                self.connect_electric_poles(from, to)?;
+                continue;
+            }
+
+            let relay = self.relay_tile(from, to, pole_type, occupied)?;
+            let relay = self.add_pole(relay, pole_type, occupied);
+            placed.push(relay);
+            poles.push(relay);
+            components.parent.insert(relay, relay);
+            components.union(from, relay);
+            self.connect_electric_poles(from, relay)?;
+        }
+    }
+
+    /// Finds the free tile within reach of `from` which brings a new pole closest to `to`.
+    fn relay_tile(
+        &self,
+        from: usize,
+        to: usize,
+        pole_type: PoleType,
+        occupied: &HashSet<Tile>,
+    ) -> Result<Tile> {
+        let size = pole_type.prototype().size;
+        let origin = self.entities[from].position();
+        let target = self.entities[to].position();
+        let reach = self.entities[from].wire_reach().unwrap_or(0.0).min(pole_type.wire_reach());
+        let current = distance(origin, target);
+
+        let r = reach.ceil() as i32;
+        let (ox, oy) = (origin.x.floor() as i32, origin.y.floor() as i32);
+        let mut best: Option<(f32, Tile)> = None;
+        for x in ox - r..=ox + r {
+            for y in oy - r..=oy + r {
+                let tile = (x, y);
+                let position = position_on_tile(tile, size);
+                if !is_free(tile, size, occupied) || distance(origin, &position) > reach {
+                    continue;
+                }
+
+                let remaining = distance(&position, target);
+                if best.is_none_or(|(b, _)| remaining < b) {
+                    best = Some((remaining, tile));
+                }
+            }
+        }
+
+        match best {
+            Some((remaining, tile)) if remaining < current => Ok(tile),
+            _ => Err(UtilityError::NoFreeTile),
+        }
+    }
+
+    fn pole_distance(&self, a: usize, b: usize) -> f32 {
+        distance(self.entities[a].position(), self.entities[b].position())
+    }
+
+    fn pole_reach(&self, a: usize, b: usize) -> f32 {
+        let reach = |id: usize| self.entities[id].wire_reach().unwrap_or(0.0);
+        reach(a).min(reach(b))
+    }
+}
+
+/// Union find over the poles of a blueprint, initialised with their existing connections.
+struct PoleComponents {
+    parent: HashMap<usize, usize>,
+}
+
+impl PoleComponents {
+    /// Components of all poles of the blueprint.
+    fn new(blueprint: &Blueprint) -> Self {
+        let poles: Vec<usize> = blueprint
+            .entities
+            .iter()
+            .filter(|e| matches!(e, Entity::ElectricPole { .. }))
+            .map(Entity::id)
+            .collect();
+        let mut components = PoleComponents {
+            parent: poles.iter().map(|&p| (p, p)).collect(),
+        };
+
+        for &pole in &poles {
+            if let Entity::ElectricPole { neighbours, .. } = &blueprint.entities[pole] {
+                for &n in neighbours {
+                    if components.parent.contains_key(&n) {
+                        components.union(pole, n);
+                    }
+                }
+            }
+        }
+
+        components
+    }
+
+    fn find(&mut self, id: usize) -> usize {
+        let parent = self.parent[&id];
+        if parent == id {
+            return id;
         }
+
+        let root = self.find(parent);
+        self.parent.insert(id, root);
+        root
     }
+
+    /// Returns false if both were already part of the same component.
+    fn union(&mut self, a: usize, b: usize) -> bool {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return false;
+        }
+
+        self.parent.insert(max(a, b), min(a, b));
+        true
+    }
+}
+
+fn is_free(tile: Tile, size: (u32, u32), occupied: &HashSet<Tile>) -> bool {
+    (0..size.0 as i32).all(|dx| (0..size.1 as i32).all(|dy| !occupied.contains(&(tile.0 + dx, tile.1 + dy))))
+}
+
+/// True if the supply area of a pole at `pole` overlaps with the entity.
+fn supplies(pole: &Position, supply_area_distance: f32, entity: &Entity) -> bool {
+    let (width, height) = entity.footprint().unwrap_or((1, 1));
+    let (left, top) = entity.top_left_tile();
+    let (left, top) = (left as f32, top as f32);
+
+    left < pole.x + supply_area_distance
+        && left + width as f32 > pole.x - supply_area_distance
+        && top < pole.y + supply_area_distance
+        && top + height as f32 > pole.y - supply_area_distance
 }
 
 impl Entity {
@@ -197,7 +772,8 @@ impl Entity {
             Entity::DeciderCombinator { id, .. }
             | Entity::ArithmeticCombinator { id, .. }
             | Entity::ConstantCombinator { id, .. }
-            | Entity::ElectricPole { id, .. } => *id,
+            | Entity::ElectricPole { id, .. }
+            | Entity::Lamp { id, .. } => *id,
             Entity::Unknown(e) => (e.entity_number - 1) as usize,
         }
     }
@@ -207,7 +783,8 @@ impl Entity {
             Entity::DeciderCombinator { id, .. }
             | Entity::ArithmeticCombinator { id, .. }
             | Entity::ConstantCombinator { id, .. }
-            | Entity::ElectricPole { id, .. } => *id = new_id,
+            | Entity::ElectricPole { id, .. }
+            | Entity::Lamp { id, .. } => *id = new_id,
             Entity::Unknown(e) => e.entity_number = (new_id + 1) as u32,
         }
     }
@@ -217,7 +794,8 @@ impl Entity {
             Entity::DeciderCombinator { position, .. }
             | Entity::ArithmeticCombinator { position, .. }
             | Entity::ConstantCombinator { position, .. }
-            | Entity::ElectricPole { position, .. } => position,
+            | Entity::ElectricPole { position, .. }
+            | Entity::Lamp { position, .. } => position,
             Entity::Unknown(e) => &e.position,
         }
     }
@@ -227,26 +805,40 @@ impl Entity {
             Entity::DeciderCombinator { position, .. }
             | Entity::ArithmeticCombinator { position, .. }
             | Entity::ConstantCombinator { position, .. }
-            | Entity::ElectricPole { position, .. } => position,
+            | Entity::ElectricPole { position, .. }
+            | Entity::Lamp { position, .. } => position,
             Entity::Unknown(e) => &mut e.position,
         }
     }
 
+    pub fn tags(&self) -> Option<&model::Tags> {
+        match self {
+            Entity::DeciderCombinator { tags, .. }
+            | Entity::ArithmeticCombinator { tags, .. }
+            | Entity::ConstantCombinator { tags, .. }
+            | Entity::ElectricPole { tags, .. }
+            | Entity::Lamp { tags, .. } => tags.as_ref(),
+            Entity::Unknown(e) => e.tags.as_ref(),
+        }
+    }
+
     pub fn side_count(&self) -> SideCount {
         match self {
             Entity::DeciderCombinator { .. }
             | Entity::ArithmeticCombinator { .. }
             | Entity::ConstantCombinator { .. } => SideCount::Two,
-            Entity::ElectricPole { .. } => SideCount::One,
+            Entity::ElectricPole { .. } | Entity::Lamp { .. } => SideCount::One,
             Entity::Unknown(_) => SideCount::Zero,
         }
     }
 
+    /// Unknown entities accept wires on both sides, as their connection points are not modelled.
     pub fn can_connect(&self, side: Side) -> bool {
-        match (self.side_count(), side) {
-            (SideCount::Two, _) | (SideCount::One, Side::One) => true,
-            _ => false,
-        }
+        matches!(self, Entity::Unknown(_))
+            || matches!(
+                (self.side_count(), side),
+                (SideCount::Two, _) | (SideCount::One, Side::One)
+            )
     }
 
     pub fn connections(&self) -> Option<&Vec<Connection>> {
@@ -254,7 +846,8 @@ impl Entity {
             Entity::DeciderCombinator { connections, .. }
             | Entity::ArithmeticCombinator { connections, .. }
             | Entity::ConstantCombinator { connections, .. }
-            | Entity::ElectricPole { connections, .. } => Some(connections),
+            | Entity::ElectricPole { connections, .. }
+            | Entity::Lamp { connections, .. } => Some(connections),
             Entity::Unknown(_) => None,
         }
     }
@@ -264,11 +857,72 @@ impl Entity {
             Entity::DeciderCombinator { connections, .. }
             | Entity::ArithmeticCombinator { connections, .. }
             | Entity::ConstantCombinator { connections, .. }
-            | Entity::ElectricPole { connections, .. } => Some(connections),
+            | Entity::ElectricPole { connections, .. }
+            | Entity::Lamp { connections, .. } => Some(connections),
             Entity::Unknown(_) => None,
         }
     }
 
+    /// All wires of the entity. Unknown entities only keep their wires in the raw model,
+    /// they are converted on every call.
+    pub fn wires(&self) -> Vec<Connection> {
+        match self {
+            Entity::Unknown(e) => Connection::from_model(e.connections.clone()),
+            _ => self.connections().cloned().unwrap_or_default(),
+        }
+    }
+
+    /// Replaces all wires of the entity.
+    pub fn set_wires(&mut self, wires: Vec<Connection>) {
+        match self {
+            Entity::Unknown(e) => e.connections = Connection::to_model(wires),
+            _ => *self.connections_mut().unwrap() = wires,
+        }
+    }
+
+    /// Ids of the poles linked by copper cables, also for unknown poles.
+    pub fn neighbours(&self) -> Vec<usize> {
+        match self {
+            Entity::ElectricPole { neighbours, .. } => neighbours.clone(),
+            Entity::Unknown(e) => e
+                .neighbours
+                .iter()
+                .flatten()
+                .filter_map(|&n| (n as usize).checked_sub(1))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn push_wire(&mut self, wire: Connection) {
+        match self.connections_mut() {
+            Some(connections) => connections.push(wire),
+            None => {
+                let mut wires = self.wires();
+                wires.push(wire);
+                self.set_wires(wires);
+            }
+        }
+    }
+
+    /// Removes all wires and pole connections to entities for which `keep` returns false.
+    fn retain_links<F: Fn(usize) -> bool>(&mut self, keep: F) {
+        let mut wires = self.wires();
+        wires.retain(|c| keep(c.to.id));
+        self.set_wires(wires);
+
+        match self {
+            Entity::ElectricPole { neighbours, .. } => neighbours.retain(|&n| keep(n)),
+            Entity::Unknown(e) => {
+                // Neighbours of unknown poles are entity numbers, which count from 1
+                if let Some(neighbours) = &mut e.neighbours {
+                    neighbours.retain(|&n| n > 0 && keep(n as usize - 1));
+                }
+            }
+            _ => {}
+        }
+    }
+
     fn update_connections(&mut self, id_map: &HashMap<usize, usize>) {
         let update = |connections: &mut Vec<Connection>| {
             for c in connections {
@@ -279,7 +933,8 @@ impl Entity {
         match self {
             Entity::DeciderCombinator { connections, .. }
             | Entity::ArithmeticCombinator { connections, .. }
-            | Entity::ConstantCombinator { connections, .. } => update(connections),
+            | Entity::ConstantCombinator { connections, .. }
+            | Entity::Lamp { connections, .. } => update(connections),
             Entity::ElectricPole {
                 connections,
                 neighbours,
@@ -288,12 +943,24 @@ impl Entity {
                 update(connections);
 
                 for neighbour in neighbours {
-                    if let Some(new_id) = id_map.get(&neighbour) {
+                    if let Some(new_id) = id_map.get(neighbour) {
                         *neighbour = *new_id;
                     }
                 }
             }
-            Entity::Unknown(_) => {}
+            Entity::Unknown(e) => {
+                let mut wires = Connection::from_model(e.connections.take());
+                update(&mut wires);
+                e.connections = Connection::to_model(wires);
+
+                // Neighbours of unknown poles are entity numbers, which count from 1
+                for neighbour in e.neighbours.iter_mut().flatten() {
+                    let id = (*neighbour as usize).wrapping_sub(1);
+                    if let Some(new_id) = id_map.get(&id) {
+                        *neighbour = *new_id as u32 + 1;
+                    }
+                }
+            }
         }
     }
 }
@@ -305,3 +972,255 @@ impl Connection {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{abstract_model::PoleType, model};
+
+    fn pole(id: usize, x: f32) -> Entity {
+        Entity::ElectricPole {
+            id,
+            pole_type: PoleType::Medium,
+            position: Position { x, y: 0.5 },
+            neighbours: Vec::new(),
+            connections: Vec::new(),
+            tags: None,
+        }
+    }
+
+    fn constant_combinator(id: usize, x: f32, y: f32) -> Entity {
+        Entity::ConstantCombinator {
+            id,
+            position: Position { x, y },
+            direction: model::Direction::East,
+            is_on: true,
+            connections: Vec::new(),
+            condition: Vec::new(),
+            tags: None,
+        }
+    }
+
+    fn blueprint(entities: Vec<Entity>) -> Blueprint {
+        Blueprint::new(entities)
+    }
+
+    #[test]
+    fn clone_entities_keeps_input_order() {
+        let mut b = blueprint((0..5).map(|id| pole(id, id as f32)).collect());
+        let map = b.clone_entities(&[3, 0, 4]).unwrap();
+
+        assert_eq!(&[(3, 5), (0, 6), (4, 7)], map.pairs());
+        assert_eq!(Some(6), map.get(0));
+        assert_eq!(3f32, b.entities[5].position().x);
+        assert_eq!(0f32, b.entities[6].position().x);
+    }
+
+    #[test]
+    fn clone_entities_handles_external_links() {
+        let mut b = blueprint(vec![pole(0, 0.0), pole(1, 1.0)]);
+        b.connect_electric_poles(0, 1).unwrap();
+        b.connect_wire(0, 1, Wire::Red).unwrap();
+
+        let one_way = b.clone_entities(&[1]).unwrap().new_ids()[0];
+        assert_eq!(0, b.entities[one_way].connections().unwrap()[0].to.id);
+        assert_eq!(1, b.entities[0].connections().unwrap().len());
+
+        let dropped = b
+            .clone_entities_with(&[1], ExternalLinks::Drop)
+            .unwrap()
+            .new_ids()[0];
+        assert!(b.entities[dropped].connections().unwrap().is_empty());
+
+        let kept = b
+            .clone_entities_with(&[1], ExternalLinks::Keep)
+            .unwrap()
+            .new_ids()[0];
+        assert_eq!(1, b.entities[kept].connections().unwrap().len());
+        let back = &b.entities[0].connections().unwrap()[1];
+        assert_eq!(kept, back.to.id);
+        match &b.entities[0] {
+            Entity::ElectricPole { neighbours, .. } => assert_eq!(&vec![1, kept], neighbours),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn kept_links_to_unknown_entities_go_both_ways() {
+        let inserter = Entity::Unknown(model::Entity {
+            entity_number: 2,
+            name: "inserter".into(),
+            position: Position { x: 2.5, y: 0.5 },
+            direction: None,
+            connections: None,
+            control_behavior: None,
+            neighbours: None,
+            tags: None,
+        });
+        let mut b = blueprint(vec![constant_combinator(0, 0.5, 0.5), inserter]);
+        b.connect_wire(0, 1, Wire::Green).unwrap();
+
+        let kept = b
+            .clone_entities_with(&[0], ExternalLinks::Keep)
+            .unwrap()
+            .new_ids()[0];
+        let targets: Vec<usize> = b.entities[1].wires().iter().map(|c| c.to.id).collect();
+        assert_eq!(vec![0, kept], targets);
+    }
+
+    #[test]
+    fn replicate_builds_and_links_a_grid() {
+        let mut b = blueprint(vec![pole(0, 0.5), constant_combinator(1, 1.5, 0.5)]);
+        b.connect_wire(0, 1, Wire::Red).unwrap();
+
+        let mut customised = Vec::new();
+        let mut customise = |_: &mut Blueprint, cell: GridCell| {
+            customised.push((cell.col, cell.row));
+            Ok(())
+        };
+        let mut links = Vec::new();
+        let mut link = |b: &mut Blueprint, axis: Axis, from: GridCell, to: GridCell| {
+            links.push((axis, (from.col, from.row), (to.col, to.row)));
+            b.connect_electric_poles(from.ids[0], to.ids[0])
+        };
+        let grid = b
+            .replicate(
+                &[0, 1],
+                2,
+                3,
+                4.0,
+                2.0,
+                Some(&mut link),
+                Some(&mut customise),
+            )
+            .unwrap();
+
+        assert_eq!(12, b.entities.len());
+        assert_eq!(vec![0, 1], grid[0][0]);
+        assert_eq!(vec![2, 3], grid[0][1]);
+        let pole = &b.entities[grid[1][2][0]];
+        assert_eq!(Position { x: 4.5, y: 4.5 }, *pole.position());
+
+        // Wires inside of a copy are copied along
+        let wire = &b.entities[grid[1][2][1]].connections().unwrap()[0];
+        assert_eq!(grid[1][2][0], wire.to.id);
+
+        assert_eq!(
+            vec![(0, 0), (0, 1), (0, 2), (1, 0), (1, 1), (1, 2)],
+            customised
+        );
+        assert_eq!(7, links.len());
+        assert!(links.contains(&(Axis::Vertical, (1, 1), (1, 2))));
+        assert!(links.contains(&(Axis::Horizontal, (0, 2), (1, 2))));
+
+        assert_eq!(
+            Err(UtilityError::InvalidOperation),
+            b.replicate(&[0], 0, 1, 1.0, 1.0, None, None)
+        );
+        assert_eq!(
+            Err(UtilityError::InvalidId(99)),
+            b.replicate(&[99], 1, 1, 1.0, 1.0, None, None)
+        );
+    }
+
+    #[test]
+    fn copies_of_unknown_entities_are_linked_to_each_other() {
+        let unknown = |id: usize, name: &str, x: f32, neighbours: Option<Vec<u32>>| {
+            Entity::Unknown(model::Entity {
+                entity_number: id as u32 + 1,
+                name: name.into(),
+                position: Position { x, y: 0.5 },
+                direction: None,
+                connections: None,
+                control_behavior: None,
+                neighbours,
+                tags: None,
+            })
+        };
+        let mut module = blueprint(vec![
+            unknown(0, "inserter", 0.5, None),
+            unknown(1, "inserter", 2.5, None),
+            unknown(2, "modded-pole", 4.5, Some(vec![4])),
+            unknown(3, "modded-pole", 6.5, Some(vec![3])),
+        ]);
+        module.connect_wire(0, 1, Wire::Red).unwrap();
+
+        let mut b = Blueprint::default();
+        let neighbours = |e: &Entity| match e {
+            Entity::Unknown(e) => e.neighbours.clone(),
+            _ => unreachable!(),
+        };
+        for copy in 0..2 {
+            let module = blueprint(module.entities.clone());
+            let ids = b.append(module, 10.0 * copy as f32, 0.0).new_ids();
+            assert_eq!(ids[1], b.entities[ids[0]].wires()[0].to.id);
+            assert_eq!(ids[0], b.entities[ids[1]].wires()[0].to.id);
+            assert_eq!(
+                Some(vec![ids[3] as u32 + 1]),
+                neighbours(&b.entities[ids[2]])
+            );
+        }
+
+        let dropped = b
+            .clone_entities_with(&[1, 2], ExternalLinks::Drop)
+            .unwrap()
+            .new_ids();
+        assert!(b.entities[dropped[0]].wires().is_empty());
+        assert_eq!(Some(Vec::new()), neighbours(&b.entities[dropped[1]]));
+    }
+
+    #[test]
+    fn place_power_poles_joins_only_the_supplying_poles() {
+        let mut b = blueprint(vec![
+            constant_combinator(0, 0.5, 0.5),
+            constant_combinator(1, 12.5, 0.5),
+            pole(2, 1.5),
+            pole(3, 40.5),
+        ]);
+
+        let placed = b.place_power_poles(&[0, 1], PoleType::Medium).unwrap();
+        assert_eq!(1, placed.len());
+        let neighbours = |b: &Blueprint, id| match &b.entities[id] {
+            Entity::ElectricPole { neighbours, .. } => neighbours.clone(),
+            _ => unreachable!(),
+        };
+        assert_eq!(placed, neighbours(&b, 2));
+        assert!(neighbours(&b, 3).is_empty());
+    }
+
+    #[test]
+    fn place_power_poles_covers_and_connects() {
+        // Two clusters that are too far apart for a single wire
+        let mut entities = Vec::new();
+        for i in 0..6 {
+            entities.push(constant_combinator(i, i as f32 + 0.5, 0.5));
+            entities.push(constant_combinator(i + 6, i as f32 + 40.5, 10.5));
+        }
+        let mut b = blueprint(entities);
+        let ids: Vec<usize> = (0..12).collect();
+
+        let poles = b.place_power_poles(&ids, PoleType::Medium).unwrap();
+        assert!(poles.len() > 2);
+        assert!(b.check_wire_reach().is_empty());
+
+        for &id in &ids {
+            assert!(poles.iter().any(|&p| {
+                supplies(b.entities[p].position(), 3.5, &b.entities[id])
+            }));
+        }
+
+        let mut reached = vec![poles[0]];
+        let mut i = 0;
+        while i < reached.len() {
+            if let Entity::ElectricPole { neighbours, .. } = &b.entities[reached[i]] {
+                for n in neighbours {
+                    if !reached.contains(n) {
+                        reached.push(*n);
+                    }
+                }
+            }
+            i += 1;
+        }
+        assert_eq!(poles.len(), reached.len());
+    }
+}
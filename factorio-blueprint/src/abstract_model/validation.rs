@@ -0,0 +1,436 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use serde::Serialize;
+
+use super::{reach::Link, Blueprint, Connector, Entity, Wire};
+
+/// Signal slots of a constant combinator, their indices start at 1.
+pub const CONSTANT_COMBINATOR_SLOTS: u8 = 20;
+
+/// Icons a blueprint can show.
+pub const MAX_ICONS: usize = 4;
+
+#[derive(Serialize, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// The game rejects the blueprint or builds something else.
+    Error,
+
+    /// Probably a mistake, but the blueprint can be built.
+    Warning,
+}
+
+/// A problem found by [`Blueprint::validate`].
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+
+    /// Name of the check, e.g. `overlap`.
+    pub check: &'static str,
+
+    /// Entity number (id + 1, like in the blueprint json) of the affected entity.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entity: Option<u32>,
+
+    pub message: String,
+}
+
+/// A check adds its diagnostics to the list.
+pub type Check = fn(&Blueprint, &mut Vec<Diagnostic>);
+
+/// All checks of [`Blueprint::validate`] by name.
+pub const CHECKS: [(&str, Check); 8] = [
+    ("overlap", check_overlaps),
+    ("wire-reach", check_reach),
+    ("filter-index", check_filter_indices),
+    ("duplicate-filter-index", check_duplicate_filter_indices),
+    ("dangling-connection", check_dangling_connections),
+    ("one-way-connection", check_one_way_connections),
+    ("isolated-pole", check_isolated_poles),
+    ("icons", check_icons),
+];
+
+impl Blueprint {
+    /// Runs all [`CHECKS`] and returns their diagnostics, errors first and then ordered by
+    /// entity. Unlike the conversions of the model, nothing panics on invalid blueprints.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        for (_, check) in CHECKS {
+            check(self, &mut diagnostics);
+        }
+        diagnostics.sort_by_key(|d| (d.severity, d.entity));
+        diagnostics
+    }
+}
+
+fn diagnostic(severity: Severity, check: &'static str, id: usize, message: String) -> Diagnostic {
+    Diagnostic {
+        severity,
+        check,
+        entity: Some(id as u32 + 1),
+        message,
+    }
+}
+
+fn check_overlaps(blueprint: &Blueprint, diagnostics: &mut Vec<Diagnostic>) {
+    let mut occupied = HashMap::new();
+    let mut reported = HashSet::new();
+    for entity in &blueprint.entities {
+        for tile in entity.tiles() {
+            let Some(&other) = occupied.get(&tile) else {
+                occupied.insert(tile, entity.id());
+                continue;
+            };
+            if reported.insert((other, entity.id())) {
+                diagnostics.push(diagnostic(
+                    Severity::Error,
+                    "overlap",
+                    entity.id(),
+                    format!(
+                        "{} overlaps entity {} at tile ({}, {})",
+                        entity.name(),
+                        other + 1,
+                        tile.0,
+                        tile.1
+                    ),
+                ));
+            }
+        }
+    }
+}
+
+fn check_reach(blueprint: &Blueprint, diagnostics: &mut Vec<Diagnostic>) {
+    for v in blueprint.check_wire_reach() {
+        let link = match v.link {
+            Link::Circuit(Wire::Red) => "red wire",
+            Link::Circuit(Wire::Green) => "green wire",
+            Link::Copper => "copper cable",
+        };
+        diagnostics.push(diagnostic(
+            Severity::Error,
+            "wire-reach",
+            v.from.id,
+            format!(
+                "{} to entity {} is {:.1} tiles long, the reach is {:.1}",
+                link,
+                v.to.id + 1,
+                v.length,
+                v.max_length
+            ),
+        ));
+    }
+}
+
+fn check_filter_indices(blueprint: &Blueprint, diagnostics: &mut Vec<Diagnostic>) {
+    for entity in &blueprint.entities {
+        let Entity::ConstantCombinator { id, condition, .. } = entity else {
+            continue;
+        };
+        for filter in condition {
+            if !(1..=CONSTANT_COMBINATOR_SLOTS).contains(&filter.index) {
+                diagnostics.push(diagnostic(
+                    Severity::Error,
+                    "filter-index",
+                    *id,
+                    format!(
+                        "filter {} has index {}, valid are 1 to {}",
+                        filter.signal.name, filter.index, CONSTANT_COMBINATOR_SLOTS
+                    ),
+                ));
+            }
+        }
+    }
+}
+
+fn check_duplicate_filter_indices(blueprint: &Blueprint, diagnostics: &mut Vec<Diagnostic>) {
+    for entity in &blueprint.entities {
+        let Entity::ConstantCombinator { id, condition, .. } = entity else {
+            continue;
+        };
+        let mut seen = HashSet::new();
+        let mut duplicates = HashSet::new();
+        for filter in condition {
+            if !seen.insert(filter.index) && duplicates.insert(filter.index) {
+                diagnostics.push(diagnostic(
+                    Severity::Error,
+                    "duplicate-filter-index",
+                    *id,
+                    format!("more than one filter has index {}", filter.index),
+                ));
+            }
+        }
+    }
+}
+
+fn check_dangling_connections(blueprint: &Blueprint, diagnostics: &mut Vec<Diagnostic>) {
+    for entity in &blueprint.entities {
+        for c in entity.wires() {
+            let reason = match blueprint.entities.get(c.to.id) {
+                None => "does not exist",
+                Some(to) if !to.can_connect(c.to.side) => "has no such connector",
+                Some(_) => continue,
+            };
+            diagnostics.push(diagnostic(
+                Severity::Error,
+                "dangling-connection",
+                entity.id(),
+                format!(
+                    "wire to side {} of entity {}, which {}",
+                    c.to.side as u8 + 1,
+                    c.to.id + 1,
+                    reason
+                ),
+            ));
+        }
+        if let Entity::ElectricPole { id, neighbours, .. } = entity {
+            for &n in neighbours {
+                if !matches!(
+                    blueprint.entities.get(n),
+                    Some(Entity::ElectricPole { .. } | Entity::Unknown(_))
+                ) {
+                    diagnostics.push(diagnostic(
+                        Severity::Error,
+                        "dangling-connection",
+                        *id,
+                        format!("copper cable to entity {}, which is not a pole", n + 1),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+/// The game stores wires and copper cables at both ends, links stored at one end only are
+/// usually left over from editing the entities by hand or by a generator.
+fn check_one_way_connections(blueprint: &Blueprint, diagnostics: &mut Vec<Diagnostic>) {
+    for entity in &blueprint.entities {
+        let from = |side| Connector {
+            id: entity.id(),
+            side,
+        };
+        for c in entity.wires() {
+            let Some(to) = blueprint.entities.get(c.to.id) else {
+                continue;
+            };
+            let back = to
+                .wires()
+                .into_iter()
+                .any(|b| b.wire == c.wire && b.from_side == c.to.side && b.to == from(c.from_side));
+            if !back {
+                diagnostics.push(diagnostic(
+                    Severity::Warning,
+                    "one-way-connection",
+                    entity.id(),
+                    format!(
+                        "wire to entity {} is not stored at that entity",
+                        c.to.id + 1
+                    ),
+                ));
+            }
+        }
+        if let Entity::ElectricPole { id, neighbours, .. } = entity {
+            for &n in neighbours {
+                let back = match blueprint.entities.get(n) {
+                    Some(Entity::ElectricPole {
+                        neighbours: back, ..
+                    }) => back.contains(id),
+                    Some(Entity::Unknown(e)) => {
+                        e.neighbours.iter().flatten().any(|&b| b as usize == id + 1)
+                    }
+                    _ => true,
+                };
+                if !back {
+                    diagnostics.push(diagnostic(
+                        Severity::Warning,
+                        "one-way-connection",
+                        *id,
+                        format!("copper cable to entity {} is not stored at it", n + 1),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+/// Poles without copper cables, unless they are the only pole.
+fn check_isolated_poles(blueprint: &Blueprint, diagnostics: &mut Vec<Diagnostic>) {
+    let poles = blueprint
+        .entities
+        .iter()
+        .filter(|e| matches!(e, Entity::ElectricPole { .. }))
+        .count();
+    if poles < 2 {
+        return;
+    }
+
+    // Cables stored at one end only still connect the other end
+    let connected: HashSet<usize> = blueprint
+        .entities
+        .iter()
+        .flat_map(|e| match e {
+            Entity::ElectricPole { neighbours, .. } => neighbours.clone(),
+            _ => Vec::new(),
+        })
+        .collect();
+    for entity in &blueprint.entities {
+        if let Entity::ElectricPole { id, neighbours, .. } = entity {
+            if neighbours.is_empty() && !connected.contains(id) {
+                diagnostics.push(diagnostic(
+                    Severity::Warning,
+                    "isolated-pole",
+                    *id,
+                    format!("{} is not connected to any other pole", entity.name()),
+                ));
+            }
+        }
+    }
+}
+
+fn check_icons(blueprint: &Blueprint, diagnostics: &mut Vec<Diagnostic>) {
+    if blueprint.icons.len() > MAX_ICONS {
+        diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            check: "icons",
+            entity: None,
+            message: format!(
+                "{} icons, a blueprint can show at most {}",
+                blueprint.icons.len(),
+                MAX_ICONS
+            ),
+        });
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.entity {
+            Some(entity) => write!(
+                f,
+                "{}[{}] entity {}: {}",
+                self.severity, self.check, entity, self.message
+            ),
+            None => write!(f, "{}[{}] {}", self.severity, self.check, self.message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        abstract_model::{Connection, PoleType, Side},
+        model::{self, ConstantCondition, Direction, Icon, Position, Signal},
+    };
+
+    fn pole(id: usize, x: f32, neighbours: Vec<usize>) -> Entity {
+        Entity::ElectricPole {
+            id,
+            pole_type: PoleType::Small,
+            position: Position { x, y: 0.5 },
+            neighbours,
+            connections: Vec::new(),
+            tags: None,
+        }
+    }
+
+    fn checks(blueprint: &Blueprint) -> Vec<&'static str> {
+        let mut checks: Vec<_> = blueprint.validate().iter().map(|d| d.check).collect();
+        checks.sort();
+        checks
+    }
+
+    #[test]
+    fn valid_blueprints_have_no_diagnostics() {
+        let template = crate::primitives::memory_cell();
+        assert_eq!(Vec::<Diagnostic>::new(), template.blueprint().validate());
+    }
+
+    #[test]
+    fn problems_are_reported() {
+        let filter = |index| ConstantCondition {
+            count: 1,
+            index,
+            signal: Signal::virtual_signal("signal-A"),
+        };
+        let constant = Entity::ConstantCombinator {
+            id: 3,
+            position: Position { x: 0.5, y: 0.5 },
+            direction: Direction::North,
+            is_on: true,
+            connections: vec![Connection {
+                from_side: Side::One,
+                to: Connector {
+                    id: 9,
+                    side: Side::One,
+                },
+                wire: Wire::Red,
+            }],
+            condition: vec![filter(0), filter(21), filter(5), filter(5)],
+            tags: None,
+        };
+        let icon = Icon {
+            index: 1,
+            signal: Signal::item("iron-plate"),
+        };
+        let blueprint = Blueprint {
+            icons: vec![icon; 5],
+            ..Blueprint::new(vec![
+                pole(0, 0.5, vec![1]),
+                pole(1, 20.5, vec![]),
+                pole(2, 40.5, vec![]),
+                constant,
+            ])
+        };
+
+        assert_eq!(
+            vec![
+                "dangling-connection",
+                "duplicate-filter-index",
+                "filter-index",
+                "filter-index",
+                "icons",
+                "isolated-pole",
+                "one-way-connection",
+                "overlap",
+                "wire-reach",
+            ],
+            checks(&blueprint)
+        );
+        let diagnostics = blueprint.validate();
+        assert_eq!(Severity::Warning, diagnostics.last().unwrap().severity);
+        assert!(diagnostics.iter().any(|d| d.to_string()
+            == "error[overlap] entity 4: constant-combinator overlaps entity 1 at tile (0, 0)"));
+    }
+
+    #[test]
+    fn wires_to_unknown_entities_are_checked() {
+        let chest = Entity::Unknown(model::Entity {
+            entity_number: 2,
+            name: "iron-chest".into(),
+            position: Position { x: 2.5, y: 0.5 },
+            direction: None,
+            connections: None,
+            control_behavior: None,
+            neighbours: None,
+            tags: None,
+        });
+        let mut blueprint = Blueprint::new(vec![pole(0, 0.5, vec![]), chest]);
+        blueprint.connect_wire(0, 1, Wire::Red).unwrap();
+        assert!(checks(&blueprint).is_empty(), "{:?}", checks(&blueprint));
+
+        blueprint.entities[1].set_wires(Vec::new());
+        assert_eq!(vec!["one-way-connection"], checks(&blueprint));
+    }
+}
@@ -0,0 +1,719 @@
+//! Compiles arithmetic and boolean expressions into placed and wired combinators, e.g.
+//!
+//! ```text
+//! out[signal-O] = (A * 3 + B) >> 2 if C > 0
+//! out[iron-plate] = [iron-plate] / 2 - 5
+//! ```
+//!
+//! Every statement sets one output signal. Operators are the ones of the arithmetic combinator
+//! (`+ - * / % ** << >> & | ^`), comparisons (`< > <= >= == !=`) evaluate to 0 or 1 and can be
+//! combined with `and`, `or` and `not`. Single upper case letters name the virtual letter signals,
+//! other signals are written in brackets, optionally with a `virtual:` or `item:` prefix.
+
+pub mod parser;
+
+use std::{collections::BTreeSet, error::Error, fmt};
+
+use crate::{
+    abstract_model::{utility::UtilityError, Blueprint, Connector, Entity, PoleType, Side, Wire},
+    model::{
+        self, ArithmeticCondition, Comparator, ConstantCondition, DeciderCondition, Direction,
+        Operation, Position, Signal,
+    },
+    simulation,
+};
+
+use self::parser::{Expr, Statement};
+
+/// Blueprint version of Factorio 1.1.53, which is also used by the bundled templates.
+pub(crate) const VERSION: u64 = 281_479_275_151_360;
+
+#[derive(Debug, PartialEq)]
+pub enum CompileError {
+    Parse {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    DuplicateOutput(String),
+    NoFreeSignal,
+    NoOutput,
+    Layout(UtilityError),
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Parse {
+                line,
+                column,
+                message,
+            } => write!(f, "line {}, column {}: {}", line, column, message),
+            Self::DuplicateOutput(name) => {
+                write!(f, "signal '{}' is assigned more than once", name)
+            }
+            Self::NoFreeSignal => write!(f, "no unused virtual signal left for temporaries"),
+            Self::NoOutput => write!(f, "program does not output anything"),
+            Self::Layout(cause) => write!(f, "could not lay out the combinators: {}", cause),
+        }
+    }
+}
+
+impl Error for CompileError {}
+
+impl From<UtilityError> for CompileError {
+    fn from(e: UtilityError) -> Self {
+        CompileError::Layout(e)
+    }
+}
+
+pub type Result<T> = core::result::Result<T, CompileError>;
+
+/// Result of compiling a program.
+///
+/// All combinators which read signals share the red network at `input`, all results are
+/// written to the red network at `output`. The outputs are valid `latency` ticks after the
+/// inputs changed and do not glitch in between, since every path is padded to the same length.
+#[derive(Debug)]
+pub struct Compiled {
+    pub blueprint: Blueprint,
+    pub input: Option<Connector>,
+    pub output: Connector,
+    pub latency: u32,
+}
+
+/// Temporaries are taken from these virtual signals, skipping the ones used by the program.
+/// The input network must not carry the chosen temporaries.
+const TEMPORARIES: [&str; 45] = [
+    "signal-A",
+    "signal-B",
+    "signal-C",
+    "signal-D",
+    "signal-E",
+    "signal-F",
+    "signal-G",
+    "signal-H",
+    "signal-I",
+    "signal-J",
+    "signal-K",
+    "signal-L",
+    "signal-M",
+    "signal-N",
+    "signal-O",
+    "signal-P",
+    "signal-Q",
+    "signal-R",
+    "signal-S",
+    "signal-T",
+    "signal-U",
+    "signal-V",
+    "signal-W",
+    "signal-X",
+    "signal-Y",
+    "signal-Z",
+    "signal-0",
+    "signal-1",
+    "signal-2",
+    "signal-3",
+    "signal-4",
+    "signal-5",
+    "signal-6",
+    "signal-7",
+    "signal-8",
+    "signal-9",
+    "signal-red",
+    "signal-green",
+    "signal-blue",
+    "signal-yellow",
+    "signal-pink",
+    "signal-cyan",
+    "signal-white",
+    "signal-grey",
+    "signal-black",
+];
+
+/// Value read by a combinator.
+#[derive(Clone, PartialEq, Debug)]
+enum Operand {
+    Constant(i32),
+    /// Signal on the input network.
+    Input(Signal),
+    /// Output of another combinator.
+    Node(usize),
+}
+
+#[derive(Clone, Debug)]
+enum Kind {
+    Arithmetic(Operation, Operand, Operand),
+    /// Outputs 1 if the comparison holds.
+    Decider(Comparator, Operand, Operand),
+    /// Passes the value through if the condition is not zero. The value must already be
+    /// on the output signal.
+    Gate {
+        condition: Operand,
+        value: Operand,
+    },
+    Constant(i32),
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    kind: Kind,
+    output: Signal,
+    latency: u32,
+}
+
+impl Node {
+    fn operands(&self) -> Vec<&Operand> {
+        match &self.kind {
+            Kind::Arithmetic(_, a, b) | Kind::Decider(_, a, b) => vec![a, b],
+            Kind::Gate { condition, value } => vec![condition, value],
+            Kind::Constant(_) => Vec::new(),
+        }
+    }
+}
+
+struct Lowering {
+    nodes: Vec<Node>,
+    temporaries: [Signal; 2],
+}
+
+impl Lowering {
+    fn latency(&self, operand: &Operand) -> u32 {
+        match operand {
+            Operand::Node(id) => self.nodes[*id].latency,
+            _ => 0,
+        }
+    }
+
+    fn is_constant(&self, operand: &Operand) -> bool {
+        match operand {
+            Operand::Constant(_) => true,
+            Operand::Input(_) => false,
+            Operand::Node(id) => matches!(self.nodes[*id].kind, Kind::Constant(_)),
+        }
+    }
+
+    fn node(&mut self, kind: Kind, output: &Signal) -> Operand {
+        let mut node = Node {
+            kind,
+            output: output.clone(),
+            latency: 0,
+        };
+        node.latency = match node.kind {
+            Kind::Constant(_) => 0,
+            _ => {
+                node.operands()
+                    .iter()
+                    .map(|o| self.latency(o))
+                    .max()
+                    .unwrap_or(0)
+                    + 1
+            }
+        };
+        self.nodes.push(node);
+        Operand::Node(self.nodes.len() - 1)
+    }
+
+    /// Delays an operand until it is valid after `latency` ticks. Input signals are renamed to
+    /// `signal` on the way, so they no longer mix with the input network.
+    fn delay(&mut self, mut operand: Operand, latency: u32, signal: &Signal) -> Operand {
+        if self.is_constant(&operand) {
+            return operand;
+        }
+        while self.latency(&operand) < latency {
+            let output = match &operand {
+                Operand::Node(id) => self.nodes[*id].output.clone(),
+                _ => signal.clone(),
+            };
+            let kind = Kind::Arithmetic(Operation::Add, operand, Operand::Constant(0));
+            operand = self.node(kind, &output);
+        }
+        operand
+    }
+
+    fn align(
+        &mut self,
+        a: Operand,
+        a_signal: &Signal,
+        b: Operand,
+        b_signal: &Signal,
+    ) -> (Operand, Operand) {
+        let latency = self.latency(&a).max(self.latency(&b));
+        (
+            self.delay(a, latency, a_signal),
+            self.delay(b, latency, b_signal),
+        )
+    }
+
+    /// Generates the combinators for `expr`. The last of them writes its result to `output`.
+    fn lower(&mut self, expr: &Expr, output: &Signal) -> Operand {
+        let [t0, t1] = self.temporaries.clone();
+        match expr {
+            Expr::Constant(c) => Operand::Constant(*c),
+            Expr::Signal(s) => Operand::Input(s.clone()),
+            Expr::Negate(e) => {
+                let a = self.lower(e, &t0);
+                self.arithmetic(Operation::Mul, a, Operand::Constant(-1), output)
+            }
+            Expr::Binary(operation, l, r) => {
+                let (a, b) = (self.lower(l, &t0), self.lower(r, &t1));
+                self.arithmetic(*operation, a, b, output)
+            }
+            Expr::Compare(comparator, l, r) => {
+                let (a, b) = (self.lower(l, &t0), self.lower(r, &t1));
+                self.compare(*comparator, a, b, output)
+            }
+            Expr::Not(e) => {
+                let a = self.lower(e, &t0);
+                self.compare(Comparator::Eq, a, Operand::Constant(0), output)
+            }
+            Expr::And(l, r) | Expr::Or(l, r) => {
+                let (a, b) = (self.boolean(l, &t0), self.boolean(r, &t1));
+                let operation = match expr {
+                    Expr::And(..) => Operation::And,
+                    _ => Operation::Or,
+                };
+                self.arithmetic(operation, a, b, output)
+            }
+        }
+    }
+
+    /// Like `lower`, but maps every value other than 0 to 1.
+    fn boolean(&mut self, expr: &Expr, output: &Signal) -> Operand {
+        let value = self.lower(expr, output);
+        if expr.is_boolean() {
+            value
+        } else {
+            self.compare(Comparator::Neq, value, Operand::Constant(0), output)
+        }
+    }
+
+    fn arithmetic(
+        &mut self,
+        operation: Operation,
+        a: Operand,
+        b: Operand,
+        output: &Signal,
+    ) -> Operand {
+        if let (Operand::Constant(a), Operand::Constant(b)) = (&a, &b) {
+            return Operand::Constant(simulation::operate(operation, *a, *b));
+        }
+
+        let [t0, t1] = self.temporaries.clone();
+        let (a, b) = self.align(a, &t0, b, &t1);
+        self.node(Kind::Arithmetic(operation, a, b), output)
+    }
+
+    fn compare(
+        &mut self,
+        comparator: Comparator,
+        a: Operand,
+        b: Operand,
+        output: &Signal,
+    ) -> Operand {
+        let (a, b, comparator) = match (&a, &b) {
+            (Operand::Constant(x), Operand::Constant(y)) => {
+                return Operand::Constant(simulation::compare(comparator, *x, *y) as i32);
+            }
+            // Deciders need a signal as their first operand
+            (Operand::Constant(_), _) => (b, a, flip(comparator)),
+            _ => (a, b, comparator),
+        };
+
+        let [t0, t1] = self.temporaries.clone();
+        let (a, b) = self.align(a, &t0, b, &t1);
+        self.node(Kind::Decider(comparator, a, b), output)
+    }
+
+    /// Generates the combinators of a statement and returns the one that writes its result.
+    fn statement(&mut self, statement: &Statement) -> Operand {
+        let output = &statement.output;
+        let value = self.lower(&statement.value, output);
+
+        let condition = statement
+            .condition
+            .as_ref()
+            .map(|c| self.lower(c, &self.temporaries[0].clone()));
+        match condition {
+            Some(Operand::Constant(0)) => Operand::Constant(0),
+            Some(condition @ (Operand::Input(_) | Operand::Node(_))) => {
+                // The gate reads its value on the output signal, which may also be on the
+                // input network. It is therefore only fed by other combinators.
+                let t0 = self.temporaries[0].clone();
+                let value = match value {
+                    Operand::Constant(c) => self.node(Kind::Constant(c), output),
+                    v => v,
+                };
+                let latency = self.latency(&condition).max(self.latency(&value)).max(1);
+                let condition = self.delay(condition, latency, &t0);
+                let value = self.delay(value, latency, output);
+                self.node(Kind::Gate { condition, value }, output)
+            }
+            _ => match value {
+                Operand::Constant(c) => self.node(Kind::Constant(c), output),
+                v => self.delay(v, 1, output),
+            },
+        }
+    }
+}
+
+fn flip(comparator: Comparator) -> Comparator {
+    match comparator {
+        Comparator::Gt => Comparator::Lt,
+        Comparator::Lt => Comparator::Gt,
+        Comparator::Ge => Comparator::Le,
+        Comparator::Le => Comparator::Ge,
+        c => c,
+    }
+}
+
+/// Parses and compiles a program, see the module documentation for the syntax.
+pub fn compile(source: &str) -> Result<Compiled> {
+    compile_statements(&parser::parse(source)?, &[])
+}
+
+/// Compiles statements that were parsed or built before. Temporaries also avoid the `reserved`
+/// signals, e.g. signals on the input network which are not read by the statements.
+pub fn compile_statements(statements: &[Statement], reserved: &[Signal]) -> Result<Compiled> {
+    let mut compiled = compile_netlist(statements, reserved)?;
+    compiled.blueprint.layout(PoleType::Medium)?;
+    Ok(compiled)
+}
+
+/// Like [`compile_statements`], but the combinators are not placed yet.
+pub(crate) fn compile_netlist(statements: &[Statement], reserved: &[Signal]) -> Result<Compiled> {
+    let mut used = BTreeSet::new();
+    for statement in statements {
+        if !used.insert(statement.output.clone()) {
+            return Err(CompileError::DuplicateOutput(statement.output.name.clone()));
+        }
+    }
+    used.extend(reserved.iter().cloned());
+    for statement in statements {
+        let mut visit = |s: &Signal| {
+            used.insert(s.clone());
+        };
+        statement.value.visit_signals(&mut visit);
+        if let Some(c) = &statement.condition {
+            c.visit_signals(&mut visit);
+        }
+    }
+
+    let mut free = TEMPORARIES
+        .iter()
+        .map(|name| Signal::virtual_signal(name))
+        .filter(|s| !used.contains(s));
+    let (t0, t1) = match (free.next(), free.next()) {
+        (Some(t0), Some(t1)) => (t0, t1),
+        _ => return Err(CompileError::NoFreeSignal),
+    };
+
+    let mut lowering = Lowering {
+        nodes: Vec::new(),
+        temporaries: [t0, t1],
+    };
+    let mut results: Vec<Operand> = statements.iter().map(|s| lowering.statement(s)).collect();
+
+    // All results become valid in the same tick
+    let latency = results
+        .iter()
+        .map(|r| lowering.latency(r))
+        .max()
+        .unwrap_or(0);
+    for (result, statement) in results.iter_mut().zip(statements) {
+        *result = lowering.delay(result.clone(), latency, &statement.output);
+    }
+    let results: Vec<usize> = results
+        .into_iter()
+        .filter_map(|r| match r {
+            Operand::Node(id) => Some(id),
+            _ => None,
+        })
+        .collect();
+    if results.is_empty() {
+        return Err(CompileError::NoOutput);
+    }
+
+    let mut compiled = netlist(&lowering.nodes, &results, latency)?;
+    compiled.blueprint.icons = statements
+        .iter()
+        .take(1)
+        .map(|s| model::Icon {
+            index: 1,
+            signal: s.output.clone(),
+        })
+        .collect();
+    Ok(compiled)
+}
+
+/// Creates the combinators and wires them up, leaving the placement to [`Blueprint::layout`].
+fn netlist(nodes: &[Node], results: &[usize], latency: u32) -> Result<Compiled> {
+    let mut blueprint = Blueprint {
+        version: VERSION,
+        ..Blueprint::new((0..nodes.len()).map(|id| entity(id, nodes)).collect())
+    };
+
+    let output_side = |id: usize| match nodes[id].kind {
+        Kind::Constant(_) => Side::One,
+        _ => Side::Two,
+    };
+    let connector = |id: usize, side: Side| Connector { id, side };
+
+    for (id, node) in nodes.iter().enumerate() {
+        for operand in node.operands() {
+            if let Operand::Node(child) = operand {
+                blueprint.connect_wire_with_side(
+                    connector(*child, output_side(*child)),
+                    connector(id, Side::One),
+                    Wire::Green,
+                )?;
+            }
+        }
+    }
+
+    let readers: Vec<usize> = (0..nodes.len())
+        .filter(|&id| {
+            nodes[id]
+                .operands()
+                .iter()
+                .any(|o| matches!(o, Operand::Input(_)))
+        })
+        .collect();
+    for pair in readers.windows(2) {
+        blueprint.connect_wire_with_side(
+            connector(pair[0], Side::One),
+            connector(pair[1], Side::One),
+            Wire::Red,
+        )?;
+    }
+
+    for pair in results.windows(2) {
+        blueprint.connect_wire_with_side(
+            connector(pair[0], output_side(pair[0])),
+            connector(pair[1], output_side(pair[1])),
+            Wire::Red,
+        )?;
+    }
+
+    Ok(Compiled {
+        blueprint,
+        input: readers.first().map(|&id| connector(id, Side::One)),
+        output: connector(results[0], output_side(results[0])),
+        latency,
+    })
+}
+
+fn entity(id: usize, nodes: &[Node]) -> Entity {
+    let node = &nodes[id];
+    let signal = |o: &Operand| match o {
+        Operand::Input(s) => Some(s.clone()),
+        Operand::Node(child) => Some(nodes[*child].output.clone()),
+        Operand::Constant(_) => None,
+    };
+    let constant = |o: &Operand| match o {
+        Operand::Constant(c) => Some(*c),
+        _ => None,
+    };
+    let position = Position { x: 0.0, y: 0.0 };
+    let connections = Vec::new();
+    let direction = Direction::East;
+
+    match &node.kind {
+        Kind::Arithmetic(operation, a, b) => Entity::ArithmeticCombinator {
+            id,
+            position,
+            direction,
+            connections,
+            condition: ArithmeticCondition {
+                operation: *operation,
+                first_constant: constant(a),
+                second_constant: constant(b),
+                first_signal: signal(a),
+                second_signal: signal(b),
+                output_signal: Some(node.output.clone()),
+            },
+            tags: None,
+        },
+        Kind::Decider(comparator, a, b) => Entity::DeciderCombinator {
+            id,
+            position,
+            direction,
+            connections,
+            condition: DeciderCondition {
+                comparator: *comparator,
+                copy_count_from_input: false,
+                constant: constant(b),
+                first_signal: signal(a),
+                second_signal: signal(b),
+                output_signal: Some(node.output.clone()),
+            },
+            tags: None,
+        },
+        Kind::Gate { condition, .. } => Entity::DeciderCombinator {
+            id,
+            position,
+            direction,
+            connections,
+            condition: DeciderCondition {
+                comparator: Comparator::Neq,
+                copy_count_from_input: true,
+                constant: Some(0),
+                first_signal: signal(condition),
+                second_signal: None,
+                output_signal: Some(node.output.clone()),
+            },
+            tags: None,
+        },
+        Kind::Constant(c) => Entity::ConstantCombinator {
+            id,
+            position,
+            direction,
+            is_on: true,
+            connections,
+            condition: vec![ConstantCondition {
+                count: *c,
+                index: 1,
+                signal: node.output.clone(),
+            }],
+            tags: None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::harness::Harness;
+
+    fn s(name: &str) -> Signal {
+        Signal::virtual_signal(name)
+    }
+
+    /// Adds empty constant combinators as taps, so that the input and output networks exist
+    /// even if only one combinator is connected to them.
+    fn harness(compiled: &mut Compiled) -> Harness {
+        let mut taps = Vec::new();
+        for connector in [compiled.input.clone().unwrap(), compiled.output.clone()] {
+            let id = compiled.blueprint.entities.len();
+            compiled.blueprint.entities.push(Entity::ConstantCombinator {
+                id,
+                position: Position { x: -5.5, y: id as f32 + 0.5 },
+                direction: Direction::East,
+                is_on: true,
+                connections: Vec::new(),
+                condition: Vec::new(),
+                tags: None,
+            });
+            compiled
+                .blueprint
+                .connect_wire_with_side(connector, Connector { id, side: Side::One }, Wire::Red)
+                .unwrap();
+            taps.push(Connector { id, side: Side::One });
+        }
+
+        let mut harness = Harness::new(&compiled.blueprint);
+        harness.port("in", taps[0].clone(), Wire::Red).unwrap();
+        harness.port("out", taps[1].clone(), Wire::Red).unwrap();
+        harness
+    }
+
+    #[test]
+    fn parse_respects_precedence() {
+        let statements = parser::parse("out[signal-O] = -A + B * 2 ** 3 << 1 if not C > 0").unwrap();
+        let (a, b, c) = (
+            Box::new(Expr::Signal(s("signal-A"))),
+            Box::new(Expr::Signal(s("signal-B"))),
+            Box::new(Expr::Signal(s("signal-C"))),
+        );
+        let constant = |c| Box::new(Expr::Constant(c));
+        let power = Box::new(Expr::Binary(Operation::Pow, constant(2), constant(3)));
+        let product = Box::new(Expr::Binary(Operation::Mul, b, power));
+        let sum = Box::new(Expr::Binary(Operation::Add, Box::new(Expr::Negate(a)), product));
+        assert_eq!(
+            vec![Statement {
+                output: s("signal-O"),
+                value: Expr::Binary(Operation::Shl, sum, constant(1)),
+                condition: Some(Expr::Not(Box::new(Expr::Compare(Comparator::Gt, c, constant(0))))),
+                line: 1,
+            }],
+            statements
+        );
+
+        assert_eq!(
+            Err(CompileError::Parse {
+                line: 2,
+                column: 16,
+                message: "expected ')'".into()
+            }),
+            compile("out[A] = 1\nout[B] = (A + 1").map(|_| ())
+        );
+        assert_eq!(
+            Err(CompileError::DuplicateOutput("signal-A".into())),
+            compile("out[A] = 1; out[signal-A] = 2").map(|_| ())
+        );
+    }
+
+    #[test]
+    fn gated_constants_and_empty_programs_compile() {
+        let mut compiled = compile("out[X] = 5 if A > 0").unwrap();
+
+        // Constant combinators only have one connection point
+        for entity in &compiled.blueprint.entities {
+            for c in entity.connections().into_iter().flatten() {
+                if let Entity::ConstantCombinator { .. } = entity {
+                    assert_eq!(Side::One, c.from_side);
+                }
+                if let Entity::ConstantCombinator { .. } = compiled.blueprint.entities[c.to.id] {
+                    assert_eq!(Side::One, c.to.side);
+                }
+            }
+        }
+        let mut harness = harness(&mut compiled);
+        harness.drive("in", &[(s("signal-A"), 1)]).unwrap();
+        harness.step(compiled.latency as u64 + 1);
+        harness.expect("out", &s("signal-X"), 5).unwrap();
+
+        assert_eq!(
+            Err(CompileError::NoOutput),
+            compile("out[X] = A if 0").map(|_| ())
+        );
+    }
+
+    #[test]
+    fn compiled_expressions_match_their_value() {
+        let mut compiled = compile(
+            "out[signal-O] = (A * 3 + B) >> 2 if C > 0
+             out[iron-plate] = -A ** 2 % 7
+             out[X] = A > 2 and not B or C
+             out[Y] = 7",
+        )
+        .unwrap();
+        assert!(compiled.blueprint.check_wire_reach().is_empty());
+
+        let mut harness = harness(&mut compiled);
+        let latency = compiled.latency as u64;
+        let expect = |h: &Harness, values: [i32; 4]| {
+            let signals = [s("signal-O"), Signal::item("iron-plate"), s("signal-X"), s("signal-Y")];
+            for (signal, value) in signals.iter().zip(values) {
+                h.expect("out", signal, value).unwrap();
+            }
+        };
+
+        // All outputs switch together, exactly after the latency
+        harness.step(latency);
+        let mut previous = [0, 0, 0, 7];
+        for (a, b, c) in [(5, 3, 1), (-4, 0, 0), (3, 0, -2), (1, 1, 0)] {
+            let inputs = [(s("signal-A"), a), (s("signal-B"), b), (s("signal-C"), c)];
+            harness.drive("in", &inputs).unwrap();
+            harness.step(latency - 1);
+            expect(&harness, previous);
+
+            let o = if c > 0 { (a * 3 + b) >> 2 } else { 0 };
+            let x = ((a > 2 && b == 0) || c != 0) as i32;
+            previous = [o, -(a.pow(2)) % 7, x, 7];
+            harness.step(1);
+            expect(&harness, previous);
+        }
+    }
+}
@@ -0,0 +1,420 @@
+use crate::model::{Comparator, Operation, Signal, SignalType};
+
+use super::{CompileError, Result};
+
+/// Statement of the form `out[signal] = value` or `out[signal] = value if condition`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Statement {
+    pub output: Signal,
+    pub value: Expr,
+    pub condition: Option<Expr>,
+    pub line: usize,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Expr {
+    Constant(i32),
+    Signal(Signal),
+    Negate(Box<Expr>),
+    Binary(Operation, Box<Expr>, Box<Expr>),
+    /// Evaluates to 1 if the comparison holds and to 0 otherwise.
+    Compare(Comparator, Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// Whether the expression always evaluates to either 0 or 1.
+    pub fn is_boolean(&self) -> bool {
+        matches!(
+            self,
+            Expr::Compare(..) | Expr::Not(_) | Expr::And(..) | Expr::Or(..)
+        )
+    }
+
+    /// Calls `f` for every signal read by the expression.
+    pub fn visit_signals(&self, f: &mut dyn FnMut(&Signal)) {
+        match self {
+            Expr::Constant(_) => {}
+            Expr::Signal(s) => f(s),
+            Expr::Negate(e) | Expr::Not(e) => e.visit_signals(f),
+            Expr::Binary(_, l, r) | Expr::Compare(_, l, r) | Expr::And(l, r) | Expr::Or(l, r) => {
+                l.visit_signals(f);
+                r.visit_signals(f);
+            }
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token {
+    Number(i64),
+    Ident(String),
+    /// Contents of `[...]`, i.e. a full signal name.
+    Bracketed(String),
+    Symbol(&'static str),
+    Newline,
+}
+
+// Longer symbols first, so that e.g. `<<` is not read as two `<`
+const SYMBOLS: [&str; 22] = [
+    "**", "<<", ">>", "<=", ">=", "==", "!=", "=", "+", "-", "*", "/", "%", "&", "|", "^", "<",
+    ">", "(", ")", "!", ";",
+];
+
+#[derive(Clone, Debug)]
+struct Spanned {
+    token: Token,
+    line: usize,
+    column: usize,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Spanned>> {
+    let mut tokens = Vec::new();
+
+    for (line_index, line) in source.lines().enumerate() {
+        let line_number = line_index + 1;
+        let chars: Vec<char> = line.chars().collect();
+        let mut i = 0;
+
+        while i < chars.len() {
+            let c = chars[i];
+            let column = i + 1;
+            let error = |message: String| CompileError::Parse {
+                line: line_number,
+                column,
+                message,
+            };
+
+            if c == '#' {
+                break;
+            } else if c.is_whitespace() {
+                i += 1;
+                continue;
+            }
+
+            let token = if c.is_ascii_digit() {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                let text: String = chars[start..i].iter().filter(|&&c| c != '_').collect();
+                let parsed = match text.strip_prefix("0x") {
+                    Some(hex) => i64::from_str_radix(hex, 16),
+                    None => text.parse::<i64>(),
+                };
+                Token::Number(parsed.map_err(|_| error(format!("invalid number '{}'", text)))?)
+            } else if c.is_ascii_alphabetic() || c == '_' {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                Token::Ident(chars[start..i].iter().collect())
+            } else if c == '[' {
+                let start = i + 1;
+                while i < chars.len() && chars[i] != ']' {
+                    i += 1;
+                }
+                if i == chars.len() {
+                    return Err(error("missing ']'".into()));
+                }
+                i += 1;
+                Token::Bracketed(chars[start..i - 1].iter().collect::<String>().trim().into())
+            } else {
+                let rest: String = chars[i..].iter().collect();
+                let symbol = SYMBOLS
+                    .iter()
+                    .find(|s| rest.starts_with(*s))
+                    .ok_or_else(|| error(format!("unexpected character '{}'", c)))?;
+                i += symbol.len();
+                Token::Symbol(symbol)
+            };
+
+            tokens.push(Spanned {
+                token,
+                line: line_number,
+                column,
+            });
+        }
+
+        tokens.push(Spanned {
+            token: Token::Newline,
+            line: line_number,
+            column: chars.len() + 1,
+        });
+    }
+
+    Ok(tokens)
+}
+
+/// Resolves a signal reference. Single letters name the virtual letter signals, bracketed
+/// names may carry a `virtual:` or `item:` prefix and are virtual if they start with `signal-`.
+fn signal(name: &str) -> Option<Signal> {
+    if let Some((kind, name)) = name.split_once(':') {
+        return match kind.trim() {
+            "virtual" => Some(Signal::virtual_signal(name.trim())),
+            "item" => Some(Signal::item(name.trim())),
+            _ => None,
+        };
+    }
+
+    if name.is_empty() {
+        None
+    } else if name.starts_with("signal-") {
+        Some(Signal::virtual_signal(name))
+    } else if name.len() == 1 && name.chars().all(|c| c.is_ascii_uppercase()) {
+        Some(Signal::virtual_signal(&format!("signal-{}", name)))
+    } else {
+        Some(Signal::item(name))
+    }
+}
+
+struct Parser {
+    tokens: Vec<Spanned>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|t| &t.token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.position += 1;
+        token
+    }
+
+    fn error(&self, message: &str) -> CompileError {
+        let (line, column) = match self
+            .tokens
+            .get(self.position)
+            .or_else(|| self.tokens.last())
+        {
+            Some(t) => (t.line, t.column),
+            None => (1, 1),
+        };
+        CompileError::Parse {
+            line,
+            column,
+            message: message.into(),
+        }
+    }
+
+    fn eat_symbol(&mut self, symbol: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Ident(k)) if k == keyword) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<()> {
+        if self.eat_symbol(symbol) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", symbol)))
+        }
+    }
+
+    fn program(&mut self) -> Result<Vec<Statement>> {
+        let mut statements = Vec::new();
+        loop {
+            while matches!(self.peek(), Some(Token::Newline) | Some(Token::Symbol(";"))) {
+                self.position += 1;
+            }
+            if self.peek().is_none() {
+                return Ok(statements);
+            }
+
+            statements.push(self.statement()?);
+            match self.peek() {
+                Some(Token::Newline) | Some(Token::Symbol(";")) | None => {}
+                _ => return Err(self.error("expected end of statement")),
+            }
+        }
+    }
+
+    fn statement(&mut self) -> Result<Statement> {
+        let line = self.tokens[self.position].line;
+        if !self.eat_keyword("out") {
+            return Err(self.error("expected 'out[signal]'"));
+        }
+        let output = match self.next() {
+            Some(Token::Bracketed(name)) => {
+                signal(&name).ok_or_else(|| self.error(&format!("invalid signal '{}'", name)))?
+            }
+            _ => {
+                self.position -= 1;
+                return Err(self.error("expected '[signal]' after 'out'"));
+            }
+        };
+        self.expect_symbol("=")?;
+
+        let value = self.expression()?;
+        let condition = if self.eat_keyword("if") {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+
+        Ok(Statement {
+            output,
+            value,
+            condition,
+            line,
+        })
+    }
+
+    fn expression(&mut self) -> Result<Expr> {
+        let mut left = self.and()?;
+        while self.eat_keyword("or") {
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut left = self.not()?;
+        while self.eat_keyword("and") {
+            left = Expr::And(Box::new(left), Box::new(self.not()?));
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<Expr> {
+        if self.eat_keyword("not") || self.eat_symbol("!") {
+            Ok(Expr::Not(Box::new(self.not()?)))
+        } else {
+            self.comparison()
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Expr> {
+        let left = self.binary(0)?;
+        let comparator = match self.peek() {
+            Some(Token::Symbol("<")) => Comparator::Lt,
+            Some(Token::Symbol(">")) => Comparator::Gt,
+            Some(Token::Symbol("<=")) => Comparator::Le,
+            Some(Token::Symbol(">=")) => Comparator::Ge,
+            Some(Token::Symbol("==")) => Comparator::Eq,
+            Some(Token::Symbol("!=")) => Comparator::Neq,
+            _ => return Ok(left),
+        };
+        self.position += 1;
+        let right = self.binary(0)?;
+        Ok(Expr::Compare(comparator, Box::new(left), Box::new(right)))
+    }
+
+    /// Binary operators by increasing precedence, all left associative.
+    const LEVELS: [&'static [(&'static str, Operation)]; 6] = [
+        &[("|", Operation::Or)],
+        &[("^", Operation::Xor)],
+        &[("&", Operation::And)],
+        &[("<<", Operation::Shl), (">>", Operation::Shr)],
+        &[("+", Operation::Add), ("-", Operation::Sub)],
+        &[
+            ("*", Operation::Mul),
+            ("/", Operation::Div),
+            ("%", Operation::Mod),
+        ],
+    ];
+
+    fn binary(&mut self, level: usize) -> Result<Expr> {
+        if level == Self::LEVELS.len() {
+            return self.unary();
+        }
+
+        let mut left = self.binary(level + 1)?;
+        'outer: loop {
+            for (symbol, operation) in Self::LEVELS[level] {
+                if self.eat_symbol(symbol) {
+                    let right = self.binary(level + 1)?;
+                    left = Expr::Binary(*operation, Box::new(left), Box::new(right));
+                    continue 'outer;
+                }
+            }
+            return Ok(left);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.eat_symbol("-") {
+            return Ok(match self.unary()? {
+                Expr::Constant(c) => Expr::Constant(c.wrapping_neg()),
+                e => Expr::Negate(Box::new(e)),
+            });
+        }
+        self.power()
+    }
+
+    /// Exponentiation binds tighter than unary minus and is right associative.
+    fn power(&mut self) -> Result<Expr> {
+        let base = self.primary()?;
+        if self.eat_symbol("**") {
+            let exponent = self.unary()?;
+            return Ok(Expr::Binary(
+                Operation::Pow,
+                Box::new(base),
+                Box::new(exponent),
+            ));
+        }
+        Ok(base)
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::Number(n)) => {
+                // Literals may also describe the full 32 bit pattern, e.g. 0xFFFFFFFF
+                if (i32::MIN as i64..=u32::MAX as i64).contains(&n) {
+                    Ok(Expr::Constant(n as i32))
+                } else {
+                    self.position -= 1;
+                    Err(self.error("number does not fit into 32 bits"))
+                }
+            }
+            Some(Token::Ident(name)) => match signal(&name) {
+                Some(s) if s.signal_type == SignalType::Virtual => Ok(Expr::Signal(s)),
+                _ => {
+                    self.position -= 1;
+                    Err(self.error(&format!(
+                        "unknown name '{}', use [{}] for signals",
+                        name, name
+                    )))
+                }
+            },
+            Some(Token::Bracketed(name)) => signal(&name)
+                .map(Expr::Signal)
+                .ok_or_else(|| self.error(&format!("invalid signal '{}'", name))),
+            Some(Token::Symbol("(")) => {
+                let e = self.expression()?;
+                self.expect_symbol(")")?;
+                Ok(e)
+            }
+            _ => {
+                self.position -= 1;
+                Err(self.error("expected a number, signal or '('"))
+            }
+        }
+    }
+}
+
+/// Parses a program of statements separated by newlines or `;`. `#` starts a comment.
+pub fn parse(source: &str) -> Result<Vec<Statement>> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        position: 0,
+    };
+    parser.program()
+}
@@ -0,0 +1,411 @@
+//! Semantic comparison of two blueprints.
+//!
+//! Entities are matched by their name and position instead of their entity number, so
+//! reordering the entities of a blueprint does not change anything. Properties are compared
+//! on the model level, wires by the entities and sides they connect.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+    abstract_model::{Blueprint, Entity, Side, Wire},
+    model::{self, Position},
+};
+
+/// Entity of one of the compared blueprints.
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct EntityRef {
+    pub name: String,
+    pub position: Position,
+}
+
+/// End of a wire.
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct WireEnd {
+    pub entity: EntityRef,
+
+    /// Circuit connector (1 or 2), missing for copper cables.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub side: Option<u8>,
+}
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct WireRef {
+    /// `red`, `green` or `copper`.
+    pub colour: &'static str,
+    pub from: WireEnd,
+    pub to: WireEnd,
+}
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum Change {
+    Added {
+        entity: EntityRef,
+    },
+    Removed {
+        entity: EntityRef,
+    },
+    /// A property like `direction`, `filters` or `decider_conditions` differs.
+    Changed {
+        entity: EntityRef,
+        property: String,
+        old: Value,
+        new: Value,
+    },
+    WireAdded {
+        wire: WireRef,
+    },
+    WireRemoved {
+        wire: WireRef,
+    },
+}
+
+/// Changes from one blueprint to another: removed entities first, then added and changed
+/// entities and finally wires, each ordered by position.
+#[derive(Serialize, Clone, PartialEq, Debug, Default)]
+pub struct BlueprintDiff {
+    pub changes: Vec<Change>,
+}
+
+impl BlueprintDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// Matches entities by name and position, positions are multiples of half a tile.
+pub(crate) type Key = (i64, i64, String);
+
+/// Both ends of a wire, ordered, and its colour.
+pub(crate) type WireKey = ((Key, Option<u8>), (Key, Option<u8>), &'static str);
+
+fn key(entity: &Entity) -> Key {
+    let position = entity.position();
+    (
+        (position.y * 2.0).round() as i64,
+        (position.x * 2.0).round() as i64,
+        entity.name().into(),
+    )
+}
+
+pub(crate) fn entity_ref(key: &Key) -> EntityRef {
+    EntityRef {
+        name: key.2.clone(),
+        position: Position {
+            x: key.1 as f32 / 2.0,
+            y: key.0 as f32 / 2.0,
+        },
+    }
+}
+
+/// Compares two blueprints, see [`BlueprintDiff`].
+pub fn diff(old: &Blueprint, new: &Blueprint) -> BlueprintDiff {
+    let (old_entities, new_entities) = (entities(old), entities(new));
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
+    let mut changed = Vec::new();
+
+    for (key, old_entity) in &old_entities {
+        match new_entities.get(key) {
+            None => removed.push(Change::Removed {
+                entity: entity_ref(key),
+            }),
+            Some(new_entity) => changed.extend(changed_properties(
+                key,
+                &model::Entity::from((*old_entity).clone()),
+                &model::Entity::from((*new_entity).clone()),
+            )),
+        }
+    }
+    for key in new_entities.keys() {
+        if !old_entities.contains_key(key) {
+            added.push(Change::Added {
+                entity: entity_ref(key),
+            });
+        }
+    }
+
+    let (old_wires, new_wires) = (wires(old), wires(new));
+    let wires_removed = old_wires
+        .difference(&new_wires)
+        .map(|w| Change::WireRemoved { wire: wire_ref(w) });
+    let wires_added = new_wires
+        .difference(&old_wires)
+        .map(|w| Change::WireAdded { wire: wire_ref(w) });
+
+    let mut changes = removed;
+    changes.extend(added);
+    changes.extend(changed);
+    changes.extend(wires_removed);
+    changes.extend(wires_added);
+    BlueprintDiff { changes }
+}
+
+pub(crate) fn wire_ref((from, to, colour): &WireKey) -> WireRef {
+    WireRef {
+        colour,
+        from: WireEnd {
+            entity: entity_ref(&from.0),
+            side: from.1,
+        },
+        to: WireEnd {
+            entity: entity_ref(&to.0),
+            side: to.1,
+        },
+    }
+}
+
+/// Entities by key. Entities with the same key, which cannot exist in the game, are told
+/// apart by their order.
+pub(crate) fn entities(blueprint: &Blueprint) -> BTreeMap<Key, &Entity> {
+    keys(blueprint)
+        .into_iter()
+        .zip(&blueprint.entities)
+        .collect()
+}
+
+/// Keys of all entities in the order of their ids.
+fn keys(blueprint: &Blueprint) -> Vec<Key> {
+    let mut seen = BTreeSet::new();
+    blueprint
+        .entities
+        .iter()
+        .map(|entity| {
+            let (y, x, mut name) = key(entity);
+            while !seen.insert((y, x, name.clone())) {
+                name.push('\'');
+            }
+            (y, x, name)
+        })
+        .collect()
+}
+
+pub(crate) fn wires(blueprint: &Blueprint) -> BTreeSet<WireKey> {
+    let keys = keys(blueprint);
+    let side = |side: Side| match side {
+        Side::One => 1,
+        Side::Two => 2,
+    };
+    let ordered = |a: (Key, Option<u8>), b: (Key, Option<u8>), colour| match a <= b {
+        true => (a, b, colour),
+        false => (b, a, colour),
+    };
+
+    let mut wires = BTreeSet::new();
+    for entity in &blueprint.entities {
+        let from = &keys[entity.id()];
+        for c in entity.wires() {
+            let Some(to) = keys.get(c.to.id) else {
+                continue;
+            };
+            let colour = match c.wire {
+                Wire::Red => "red",
+                Wire::Green => "green",
+            };
+            wires.insert(ordered(
+                (from.clone(), Some(side(c.from_side))),
+                (to.clone(), Some(side(c.to.side))),
+                colour,
+            ));
+        }
+        let neighbours = match entity {
+            Entity::ElectricPole { neighbours, .. } => neighbours.clone(),
+            Entity::Unknown(e) => e
+                .neighbours
+                .iter()
+                .flatten()
+                .filter_map(|&n| (n as usize).checked_sub(1))
+                .collect(),
+            _ => Vec::new(),
+        };
+        for to in neighbours.iter().filter_map(|&n| keys.get(n)) {
+            wires.insert(ordered((from.clone(), None), (to.clone(), None), "copper"));
+        }
+    }
+    wires
+}
+
+/// Differences of the direction, the tags and the fields of the control behaviour.
+fn changed_properties(key: &Key, old: &model::Entity, new: &model::Entity) -> Vec<Change> {
+    let mut properties: BTreeMap<String, (Value, Value)> = BTreeMap::new();
+    properties.insert(
+        "direction".into(),
+        (json(&old.direction), json(&new.direction)),
+    );
+    properties.insert("tags".into(), (json(&old.tags), json(&new.tags)));
+
+    let fields = |entity: &model::Entity| match json(&entity.control_behavior) {
+        Value::Object(fields) => fields,
+        _ => Default::default(),
+    };
+    let (old_fields, new_fields) = (fields(old), fields(new));
+    for name in old_fields.keys().chain(new_fields.keys()) {
+        let value = |fields: &serde_json::Map<String, Value>| {
+            fields.get(name).cloned().unwrap_or(Value::Null)
+        };
+        properties.insert(name.clone(), (value(&old_fields), value(&new_fields)));
+    }
+
+    properties
+        .into_iter()
+        .filter(|(_, (old, new))| old != new)
+        .map(|(property, (old, new))| Change::Changed {
+            entity: entity_ref(key),
+            property,
+            old,
+            new,
+        })
+        .collect()
+}
+
+pub(crate) fn json<T: Serialize>(value: &T) -> Value {
+    serde_json::to_value(value).unwrap_or(Value::Null)
+}
+
+impl fmt::Display for EntityRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} at ({}, {})",
+            self.name, self.position.x, self.position.y
+        )
+    }
+}
+
+impl fmt::Display for WireEnd {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.side {
+            Some(side) => write!(f, "{} [{}]", self.entity, side),
+            None => write!(f, "{}", self.entity),
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Change::Added { entity } => write!(f, "+ {}", entity),
+            Change::Removed { entity } => write!(f, "- {}", entity),
+            Change::Changed {
+                entity,
+                property,
+                old,
+                new,
+            } => write!(f, "~ {}: {} {} -> {}", entity, property, old, new),
+            Change::WireAdded { wire } => {
+                write!(f, "+ {} wire {} -- {}", wire.colour, wire.from, wire.to)
+            }
+            Change::WireRemoved { wire } => {
+                write!(f, "- {} wire {} -- {}", wire.colour, wire.from, wire.to)
+            }
+        }
+    }
+}
+
+impl fmt::Display for BlueprintDiff {
+    /// One change per line.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{model::Comparator, primitives};
+
+    fn blueprint(entities: Vec<Entity>) -> Blueprint {
+        Blueprint::new(entities)
+    }
+
+    #[test]
+    fn reordered_entities_are_equal() {
+        let template = primitives::memory_cell();
+        let old = template.blueprint();
+        let mut entities = old.entities.clone();
+        entities.reverse();
+        let last = entities.len() - 1;
+        for (id, entity) in entities.iter_mut().enumerate() {
+            entity.update_id(id);
+            for c in entity.connections_mut().into_iter().flatten() {
+                c.to.id = last - c.to.id;
+            }
+        }
+        let new = blueprint(entities);
+        assert!(diff(old, &new).is_empty());
+    }
+
+    #[test]
+    fn entities_properties_and_wires_are_compared() {
+        let old = primitives::loader_cell().blueprint().entities.clone();
+        let mut new = old.clone();
+        let moved = new
+            .iter()
+            .position(|e| e.name() == "constant-combinator")
+            .unwrap();
+        let decider = 1 - moved;
+        new[moved].position_mut().x += 3.0;
+        for entity in &mut new {
+            entity.connections_mut().unwrap().clear();
+        }
+        if let Entity::DeciderCombinator { condition, .. } = &mut new[decider] {
+            condition.comparator = Comparator::Gt;
+        }
+
+        let changes = diff(&blueprint(old), &blueprint(new)).changes;
+        assert_eq!(4, changes.len(), "{:?}", changes);
+        assert!(
+            matches!(&changes[0], Change::Removed { entity } if entity.name == "constant-combinator")
+        );
+        assert!(
+            matches!(&changes[1], Change::Added { entity } if entity.name == "constant-combinator")
+        );
+        match &changes[2] {
+            Change::Changed {
+                property, old, new, ..
+            } => {
+                assert_eq!("decider_conditions", property);
+                assert_eq!("=", old["comparator"]);
+                assert_eq!(">", new["comparator"]);
+            }
+            change => panic!("unexpected {:?}", change),
+        }
+        assert!(matches!(&changes[3], Change::WireRemoved { wire } if wire.colour == "green"));
+
+        let text = BlueprintDiff { changes }.to_string();
+        assert!(text.starts_with("- constant-combinator at ("));
+        assert_eq!(4, text.lines().count());
+    }
+
+    #[test]
+    fn wires_of_unknown_entities_are_compared() {
+        let chest = |id: usize, x: f32| {
+            Entity::Unknown(model::Entity {
+                entity_number: id as u32 + 1,
+                name: "iron-chest".into(),
+                position: Position { x, y: 0.5 },
+                direction: None,
+                connections: None,
+                control_behavior: None,
+                neighbours: None,
+                tags: None,
+            })
+        };
+        let old = blueprint(vec![chest(0, 0.5), chest(1, 2.5)]);
+        let mut new = blueprint(vec![chest(0, 0.5), chest(1, 2.5)]);
+        new.connect_wire(0, 1, Wire::Red).unwrap();
+
+        let changes = diff(&old, &new).changes;
+        assert_eq!(1, changes.len(), "{:?}", changes);
+        assert!(matches!(&changes[0], Change::WireAdded { wire } if wire.colour == "red"));
+    }
+}
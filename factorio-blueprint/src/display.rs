@@ -0,0 +1,731 @@
+//! Pixel art and text for status boards.
+//!
+//! A [`Bitmap`] is either loaded from an image or rendered from text with a built-in 5x7 font.
+//! It is turned into a grid of coloured lamps with [`lamps`] or into a floor pattern with
+//! [`tiles`]. Lamps of one colour that touch each other share a circuit network, which is fed
+//! by a constant combinator with the colour signal.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    error::Error,
+    fmt,
+    str::FromStr,
+};
+
+use crate::{
+    abstract_model::{
+        prototype::{self, Tile},
+        utility::UtilityError,
+        Blueprint, Entity, PoleType, Wire,
+    },
+    compiler,
+    model::{CircuitCondition, Comparator, ConstantCondition, Direction, Position, Signal},
+};
+
+/// Width of a glyph of the font, followed by one column of space.
+const GLYPH_WIDTH: usize = 5;
+
+/// Height of a glyph of the font, followed by one row of space.
+const GLYPH_HEIGHT: usize = 7;
+
+/// Pixels whose brightest channel is below this value are off.
+const DARK: u8 = 64;
+
+#[derive(Debug, PartialEq)]
+pub enum DisplayError {
+    InvalidImage(String),
+    Empty,
+    NoRoom { x: usize, y: usize },
+    Layout(UtilityError),
+}
+
+impl fmt::Display for DisplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidImage(reason) => write!(f, "invalid image: {}", reason),
+            Self::Empty => write!(f, "the image has no lit pixels"),
+            Self::NoRoom { x, y } => write!(
+                f,
+                "no free tile for the combinator of the pixel at ({}, {})",
+                x, y
+            ),
+            Self::Layout(cause) => write!(f, "could not lay out the display: {}", cause),
+        }
+    }
+}
+
+impl Error for DisplayError {}
+
+impl From<UtilityError> for DisplayError {
+    fn from(e: UtilityError) -> Self {
+        DisplayError::Layout(e)
+    }
+}
+
+pub type Result<T> = core::result::Result<T, DisplayError>;
+
+/// Colours a lamp can show.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Colour {
+    Red,
+    Green,
+    Blue,
+    Yellow,
+    Pink,
+    Cyan,
+    White,
+}
+
+impl Colour {
+    pub const ALL: [Colour; 7] = [
+        Colour::Red,
+        Colour::Green,
+        Colour::Blue,
+        Colour::Yellow,
+        Colour::Pink,
+        Colour::Cyan,
+        Colour::White,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Colour::Red => "red",
+            Colour::Green => "green",
+            Colour::Blue => "blue",
+            Colour::Yellow => "yellow",
+            Colour::Pink => "pink",
+            Colour::Cyan => "cyan",
+            Colour::White => "white",
+        }
+    }
+
+    /// Signal which makes a lamp show this colour.
+    pub fn signal(&self) -> Signal {
+        Signal::virtual_signal(&format!("signal-{}", self.name()))
+    }
+
+    fn rgb(&self) -> [u8; 3] {
+        match self {
+            Colour::Red => [255, 0, 0],
+            Colour::Green => [0, 255, 0],
+            Colour::Blue => [0, 0, 255],
+            Colour::Yellow => [255, 255, 0],
+            Colour::Pink => [255, 0, 255],
+            Colour::Cyan => [0, 255, 255],
+            Colour::White => [255, 255, 255],
+        }
+    }
+
+    /// Lamp colour with the closest hue, `None` for dark pixels.
+    pub fn nearest(rgb: [u8; 3]) -> Option<Colour> {
+        let brightest = *rgb.iter().max().unwrap();
+        if brightest < DARK {
+            return None;
+        }
+
+        // Compare hues, not brightness
+        let scaled = rgb.map(|c| c as i32 * 255 / brightest as i32);
+        let distance = |colour: &Colour| -> i32 {
+            let target = colour.rgb();
+            (0..3).map(|i| (scaled[i] - target[i] as i32).pow(2)).sum()
+        };
+        Colour::ALL.into_iter().min_by_key(distance)
+    }
+}
+
+impl FromStr for Colour {
+    type Err = String;
+
+    fn from_str(s: &str) -> core::result::Result<Self, Self::Err> {
+        Colour::ALL
+            .into_iter()
+            .find(|c| c.name() == s)
+            .ok_or_else(|| format!("unknown colour '{}'", s))
+    }
+}
+
+/// Image with one lamp colour per pixel, `None` for pixels which are off.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Bitmap {
+    width: usize,
+    height: usize,
+    pixels: Vec<Option<Colour>>,
+}
+
+impl Bitmap {
+    /// Bitmap of the given size with all pixels off.
+    pub fn new(width: usize, height: usize) -> Self {
+        Bitmap {
+            width,
+            height,
+            pixels: vec![None; width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Colour of the pixel, `None` if it is off or outside of the bitmap.
+    pub fn get(&self, x: usize, y: usize) -> Option<Colour> {
+        if x < self.width && y < self.height {
+            self.pixels[y * self.width + x]
+        } else {
+            None
+        }
+    }
+
+    /// Sets a pixel. Panics if it is outside of the bitmap.
+    pub fn set(&mut self, x: usize, y: usize, colour: Option<Colour>) {
+        assert!(x < self.width && y < self.height, "pixel outside of bitmap");
+        self.pixels[y * self.width + x] = colour;
+    }
+
+    /// Lit pixels in rows from top to bottom.
+    pub fn lit(&self) -> impl Iterator<Item = (usize, usize, Colour)> + '_ {
+        self.pixels
+            .iter()
+            .enumerate()
+            .filter_map(|(i, c)| c.map(|c| (i % self.width, i / self.width, c)))
+    }
+
+    /// Loads a plain (P1) or raw (P4) portable bitmap. Black pixels are lit with `colour`.
+    pub fn from_pbm(data: &[u8], colour: Colour) -> Result<Self> {
+        let invalid = |reason: &str| DisplayError::InvalidImage(reason.into());
+        let mut position = 0;
+
+        // Header: magic number, width and height, separated by whitespace and comments
+        let mut header = Vec::new();
+        while header.len() < 3 {
+            match data.get(position) {
+                None => return Err(invalid("truncated header")),
+                Some(b'#') => {
+                    while data.get(position).is_some_and(|&b| b != b'\n') {
+                        position += 1;
+                    }
+                }
+                Some(b) if b.is_ascii_whitespace() => position += 1,
+                Some(_) => {
+                    let start = position;
+                    while data.get(position).is_some_and(|b| !b.is_ascii_whitespace()) {
+                        position += 1;
+                    }
+                    header.push(String::from_utf8_lossy(&data[start..position]).into_owned());
+                }
+            }
+        }
+        let size = |value: &str| value.parse::<usize>().map_err(|_| invalid("invalid size"));
+        let (width, height) = (size(&header[1])?, size(&header[2])?);
+        let mut bitmap = Bitmap::new(width, height);
+        let mut set = |i: usize| bitmap.pixels[i] = Some(colour);
+
+        match header[0].as_str() {
+            "P1" => {
+                let bits = data[position..]
+                    .split(|&b| b == b'\n')
+                    .flat_map(|line| line.split(|&b| b == b'#').next().unwrap_or_default())
+                    .filter(|b| !b.is_ascii_whitespace())
+                    .copied();
+                let mut count = 0;
+                for (i, bit) in bits.take(width * height).enumerate() {
+                    match bit {
+                        b'1' => set(i),
+                        b'0' => {}
+                        _ => return Err(invalid("pixels must be 0 or 1")),
+                    }
+                    count += 1;
+                }
+                if count < width * height {
+                    return Err(invalid("truncated pixel data"));
+                }
+            }
+            "P4" => {
+                // A single whitespace character separates the header from the pixels
+                let pixels = &data[(position + 1).min(data.len())..];
+                let row_bytes = width.div_ceil(8);
+                if pixels.len() < row_bytes * height {
+                    return Err(invalid("truncated pixel data"));
+                }
+                for y in 0..height {
+                    for x in 0..width {
+                        if pixels[y * row_bytes + x / 8] & (0x80 >> (x % 8)) != 0 {
+                            set(y * width + x);
+                        }
+                    }
+                }
+            }
+            _ => return Err(invalid("not a portable bitmap")),
+        }
+        Ok(bitmap)
+    }
+
+    /// Converts 8 bit RGBA pixels, e.g. of a decoded PNG, to the closest lamp colours.
+    /// Transparent and dark pixels are off.
+    pub fn from_rgba(width: usize, height: usize, rgba: &[u8]) -> Result<Self> {
+        if rgba.len() != width * height * 4 {
+            return Err(DisplayError::InvalidImage(format!(
+                "expected {} bytes of RGBA data, got {}",
+                width * height * 4,
+                rgba.len()
+            )));
+        }
+
+        let pixels = rgba
+            .chunks(4)
+            .map(|p| match p[3] {
+                0..=127 => None,
+                _ => Colour::nearest([p[0], p[1], p[2]]),
+            })
+            .collect();
+        Ok(Bitmap {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Renders text with the built-in 5x7 font. Characters without a glyph are shown as `?`.
+    pub fn text(text: &str, colour: Colour) -> Self {
+        let lines: Vec<Vec<char>> = text.lines().map(|l| l.chars().collect()).collect();
+        let columns = lines.iter().map(Vec::len).max().unwrap_or(0);
+        let mut bitmap = Bitmap::new(
+            (columns * (GLYPH_WIDTH + 1)).saturating_sub(1),
+            (lines.len() * (GLYPH_HEIGHT + 1)).saturating_sub(1),
+        );
+
+        for (row, line) in lines.iter().enumerate() {
+            for (col, &c) in line.iter().enumerate() {
+                for (dx, bits) in glyph(c).iter().enumerate() {
+                    for dy in (0..GLYPH_HEIGHT).filter(|dy| bits & (1 << dy) != 0) {
+                        let x = col * (GLYPH_WIDTH + 1) + dx;
+                        let y = row * (GLYPH_HEIGHT + 1) + dy;
+                        bitmap.set(x, y, Some(colour));
+                    }
+                }
+            }
+        }
+        bitmap
+    }
+
+    /// Enlarges every pixel to `factor` x `factor` pixels.
+    pub fn scaled(&self, factor: usize) -> Self {
+        let mut bitmap = Bitmap::new(self.width * factor, self.height * factor);
+        for (i, pixel) in bitmap.pixels.iter_mut().enumerate() {
+            let (x, y) = (i % (self.width * factor), i / (self.width * factor));
+            *pixel = self.get(x / factor, y / factor);
+        }
+        bitmap
+    }
+}
+
+/// Floor tiles of a tile pattern.
+#[derive(Clone, Debug)]
+pub struct TileOptions {
+    /// Tile of lit pixels.
+    pub foreground: String,
+
+    /// Tile of the other pixels, e.g. `landfill`. They are left empty without one.
+    pub background: Option<String>,
+}
+
+impl Default for TileOptions {
+    /// Refined concrete on an empty background.
+    fn default() -> Self {
+        TileOptions {
+            foreground: "refined-concrete".into(),
+            background: None,
+        }
+    }
+}
+
+/// Places a small lamp per lit pixel. Lamps of one colour that touch each other are wired
+/// together and to a constant combinator with the colour signal, which is placed on a free
+/// tile nearby. Finally the lamps are supplied with power.
+///
+/// Combinators and poles are only placed around the image, so unlit pixels stay empty.
+/// Areas out of reach of the border cannot be wired or powered.
+pub fn lamps(bitmap: &Bitmap, pole_type: PoleType) -> Result<Blueprint> {
+    let mut blueprint = empty_blueprint();
+    let mut ids = vec![None; bitmap.width * bitmap.height];
+    for (x, y, colour) in bitmap.lit() {
+        let id = blueprint.entities.len();
+        let condition = CircuitCondition {
+            comparator: Comparator::Gt,
+            constant: Some(0),
+            first_signal: Some(colour.signal()),
+            second_signal: None,
+        };
+        blueprint.entities.push(Entity::Lamp {
+            id,
+            position: centre((x as i32, y as i32)),
+            connections: Vec::new(),
+            condition: Some(condition),
+            use_colors: true,
+            tags: None,
+        });
+        ids[y * bitmap.width + x] = Some(id);
+    }
+    if blueprint.entities.is_empty() {
+        return Err(DisplayError::Empty);
+    }
+    let lamp_ids: Vec<usize> = (0..blueprint.entities.len()).collect();
+
+    let reach = ["small-lamp", "constant-combinator"]
+        .iter()
+        .filter_map(|name| prototype::prototype(name)?.wire_reach)
+        .fold(f32::MAX, f32::min);
+    let image: HashSet<Tile> = (0..bitmap.height as i32)
+        .flat_map(|y| (0..bitmap.width as i32).map(move |x| (x, y)))
+        .collect();
+    let mut occupied = blueprint.occupied_tiles();
+    occupied.extend(&image);
+    let mut visited = vec![false; ids.len()];
+    for (x, y, colour) in bitmap.lit() {
+        if visited[y * bitmap.width + x] {
+            continue;
+        }
+
+        // Wire the lamps of the area along the search tree
+        let mut area = Vec::new();
+        let mut queue = VecDeque::from([(x, y)]);
+        visited[y * bitmap.width + x] = true;
+        while let Some((x, y)) = queue.pop_front() {
+            let id = ids[y * bitmap.width + x].unwrap();
+            area.push(((x as i32, y as i32), id));
+            let neighbours = [
+                (x.wrapping_sub(1), y),
+                (x + 1, y),
+                (x, y.wrapping_sub(1)),
+                (x, y + 1),
+            ];
+            for (nx, ny) in neighbours {
+                if bitmap.get(nx, ny) != Some(colour) || visited[ny * bitmap.width + nx] {
+                    continue;
+                }
+                visited[ny * bitmap.width + nx] = true;
+                let neighbour = ids[ny * bitmap.width + nx].unwrap();
+                blueprint.connect_wire(id, neighbour, Wire::Green)?;
+                queue.push_back((nx, ny));
+            }
+        }
+
+        let (tile, lamp) =
+            free_tile_near(&area, &occupied, reach).ok_or(DisplayError::NoRoom { x, y })?;
+        occupied.insert(tile);
+        let combinator = blueprint.entities.len();
+        blueprint.entities.push(Entity::ConstantCombinator {
+            id: combinator,
+            position: centre(tile),
+            direction: Direction::North,
+            is_on: true,
+            connections: Vec::new(),
+            condition: vec![ConstantCondition {
+                count: 1,
+                index: 1,
+                signal: colour.signal(),
+            }],
+            tags: None,
+        });
+        blueprint.connect_wire(combinator, lamp, Wire::Green)?;
+    }
+
+    blueprint.place_power_poles_avoiding(&lamp_ids, pole_type, &image)?;
+    Ok(blueprint)
+}
+
+/// Covers lit pixels with the foreground tile and the others with the background tile.
+pub fn tiles(bitmap: &Bitmap, options: &TileOptions) -> Result<Blueprint> {
+    if bitmap.lit().next().is_none() {
+        return Err(DisplayError::Empty);
+    }
+
+    let mut blueprint = empty_blueprint();
+    for y in 0..bitmap.height {
+        for x in 0..bitmap.width {
+            let name = match bitmap.get(x, y) {
+                Some(_) => &options.foreground,
+                None => match &options.background {
+                    Some(background) => background,
+                    None => continue,
+                },
+            };
+            blueprint.tiles.push(crate::model::Tile {
+                name: name.clone(),
+                position: Position {
+                    x: x as f32,
+                    y: y as f32,
+                },
+            });
+        }
+    }
+    Ok(blueprint)
+}
+
+fn empty_blueprint() -> Blueprint {
+    Blueprint {
+        version: compiler::VERSION,
+        ..Blueprint::default()
+    }
+}
+
+fn centre(tile: Tile) -> Position {
+    Position {
+        x: tile.0 as f32 + 0.5,
+        y: tile.1 as f32 + 0.5,
+    }
+}
+
+/// Free tile closest to the lamps of an area (searching ring by ring) and the id of the lamp
+/// it is closest to.
+fn free_tile_near(
+    area: &[(Tile, usize)],
+    occupied: &HashSet<Tile>,
+    reach: f32,
+) -> Option<(Tile, usize)> {
+    for ring in 1..=reach as i32 {
+        let mut best: Option<(i32, Tile, usize)> = None;
+        for &((x, y), id) in area {
+            for dy in -ring..=ring {
+                for dx in -ring..=ring {
+                    let distance = dx * dx + dy * dy;
+                    let tile = (x + dx, y + dy);
+                    if dx.abs().max(dy.abs()) != ring
+                        || distance as f32 > reach * reach
+                        || occupied.contains(&tile)
+                    {
+                        continue;
+                    }
+                    if best.is_none_or(|(d, t, _)| (distance, (tile.1, tile.0)) < (d, (t.1, t.0))) {
+                        best = Some((distance, tile, id));
+                    }
+                }
+            }
+        }
+        if let Some((_, tile, id)) = best {
+            return Some((tile, id));
+        }
+    }
+    None
+}
+
+/// Columns of the glyph from left to right, the lowest bit is the top row.
+fn glyph(c: char) -> [u8; GLYPH_WIDTH] {
+    let index = match c {
+        ' '..='~' => c as usize - ' ' as usize,
+        _ => '?' as usize - ' ' as usize,
+    };
+    FONT[index]
+}
+
+/// Printable ASCII characters from space to tilde.
+const FONT: [[u8; GLYPH_WIDTH]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], // space
+    [0x00, 0x00, 0x5f, 0x00, 0x00], // !
+    [0x00, 0x07, 0x00, 0x07, 0x00], // "
+    [0x14, 0x7f, 0x14, 0x7f, 0x14], // #
+    [0x24, 0x2a, 0x7f, 0x2a, 0x12], // $
+    [0x23, 0x13, 0x08, 0x64, 0x62], // %
+    [0x36, 0x49, 0x55, 0x22, 0x50], // &
+    [0x00, 0x05, 0x03, 0x00, 0x00], // '
+    [0x00, 0x1c, 0x22, 0x41, 0x00], // (
+    [0x00, 0x41, 0x22, 0x1c, 0x00], // )
+    [0x14, 0x08, 0x3e, 0x08, 0x14], // *
+    [0x08, 0x08, 0x3e, 0x08, 0x08], // +
+    [0x00, 0x50, 0x30, 0x00, 0x00], // ,
+    [0x08, 0x08, 0x08, 0x08, 0x08], // -
+    [0x00, 0x60, 0x60, 0x00, 0x00], // .
+    [0x20, 0x10, 0x08, 0x04, 0x02], // /
+    [0x3e, 0x51, 0x49, 0x45, 0x3e], // 0
+    [0x00, 0x42, 0x7f, 0x40, 0x00], // 1
+    [0x42, 0x61, 0x51, 0x49, 0x46], // 2
+    [0x21, 0x41, 0x45, 0x4b, 0x31], // 3
+    [0x18, 0x14, 0x12, 0x7f, 0x10], // 4
+    [0x27, 0x45, 0x45, 0x45, 0x39], // 5
+    [0x3c, 0x4a, 0x49, 0x49, 0x30], // 6
+    [0x01, 0x71, 0x09, 0x05, 0x03], // 7
+    [0x36, 0x49, 0x49, 0x49, 0x36], // 8
+    [0x06, 0x49, 0x49, 0x29, 0x1e], // 9
+    [0x00, 0x36, 0x36, 0x00, 0x00], // :
+    [0x00, 0x56, 0x36, 0x00, 0x00], // ;
+    [0x08, 0x14, 0x22, 0x41, 0x00], // <
+    [0x14, 0x14, 0x14, 0x14, 0x14], // =
+    [0x00, 0x41, 0x22, 0x14, 0x08], // >
+    [0x02, 0x01, 0x51, 0x09, 0x06], // ?
+    [0x32, 0x49, 0x79, 0x41, 0x3e], // @
+    [0x7e, 0x11, 0x11, 0x11, 0x7e], // A
+    [0x7f, 0x49, 0x49, 0x49, 0x36], // B
+    [0x3e, 0x41, 0x41, 0x41, 0x22], // C
+    [0x7f, 0x41, 0x41, 0x22, 0x1c], // D
+    [0x7f, 0x49, 0x49, 0x49, 0x41], // E
+    [0x7f, 0x09, 0x09, 0x09, 0x01], // F
+    [0x3e, 0x41, 0x49, 0x49, 0x7a], // G
+    [0x7f, 0x08, 0x08, 0x08, 0x7f], // H
+    [0x00, 0x41, 0x7f, 0x41, 0x00], // I
+    [0x20, 0x40, 0x41, 0x3f, 0x01], // J
+    [0x7f, 0x08, 0x14, 0x22, 0x41], // K
+    [0x7f, 0x40, 0x40, 0x40, 0x40], // L
+    [0x7f, 0x02, 0x0c, 0x02, 0x7f], // M
+    [0x7f, 0x04, 0x08, 0x10, 0x7f], // N
+    [0x3e, 0x41, 0x41, 0x41, 0x3e], // O
+    [0x7f, 0x09, 0x09, 0x09, 0x06], // P
+    [0x3e, 0x41, 0x51, 0x21, 0x5e], // Q
+    [0x7f, 0x09, 0x19, 0x29, 0x46], // R
+    [0x46, 0x49, 0x49, 0x49, 0x31], // S
+    [0x01, 0x01, 0x7f, 0x01, 0x01], // T
+    [0x3f, 0x40, 0x40, 0x40, 0x3f], // U
+    [0x1f, 0x20, 0x40, 0x20, 0x1f], // V
+    [0x3f, 0x40, 0x38, 0x40, 0x3f], // W
+    [0x63, 0x14, 0x08, 0x14, 0x63], // X
+    [0x07, 0x08, 0x70, 0x08, 0x07], // Y
+    [0x61, 0x51, 0x49, 0x45, 0x43], // Z
+    [0x00, 0x7f, 0x41, 0x41, 0x00], // [
+    [0x02, 0x04, 0x08, 0x10, 0x20], // backslash
+    [0x00, 0x41, 0x41, 0x7f, 0x00], // ]
+    [0x04, 0x02, 0x01, 0x02, 0x04], // ^
+    [0x40, 0x40, 0x40, 0x40, 0x40], // _
+    [0x00, 0x01, 0x02, 0x04, 0x00], // `
+    [0x20, 0x54, 0x54, 0x54, 0x78], // a
+    [0x7f, 0x48, 0x44, 0x44, 0x38], // b
+    [0x38, 0x44, 0x44, 0x44, 0x20], // c
+    [0x38, 0x44, 0x44, 0x48, 0x7f], // d
+    [0x38, 0x54, 0x54, 0x54, 0x18], // e
+    [0x08, 0x7e, 0x09, 0x01, 0x02], // f
+    [0x0c, 0x52, 0x52, 0x52, 0x3e], // g
+    [0x7f, 0x08, 0x04, 0x04, 0x78], // h
+    [0x00, 0x44, 0x7d, 0x40, 0x00], // i
+    [0x20, 0x40, 0x44, 0x3d, 0x00], // j
+    [0x7f, 0x10, 0x28, 0x44, 0x00], // k
+    [0x00, 0x41, 0x7f, 0x40, 0x00], // l
+    [0x7c, 0x04, 0x18, 0x04, 0x78], // m
+    [0x7c, 0x08, 0x04, 0x04, 0x78], // n
+    [0x38, 0x44, 0x44, 0x44, 0x38], // o
+    [0x7c, 0x14, 0x14, 0x14, 0x08], // p
+    [0x08, 0x14, 0x14, 0x18, 0x7c], // q
+    [0x7c, 0x08, 0x04, 0x04, 0x08], // r
+    [0x48, 0x54, 0x54, 0x54, 0x20], // s
+    [0x04, 0x3f, 0x44, 0x40, 0x20], // t
+    [0x3c, 0x40, 0x40, 0x20, 0x7c], // u
+    [0x1c, 0x20, 0x40, 0x20, 0x1c], // v
+    [0x3c, 0x40, 0x30, 0x40, 0x3c], // w
+    [0x44, 0x28, 0x10, 0x28, 0x44], // x
+    [0x0c, 0x50, 0x50, 0x50, 0x3c], // y
+    [0x44, 0x64, 0x54, 0x4c, 0x44], // z
+    [0x00, 0x08, 0x36, 0x41, 0x00], // {
+    [0x00, 0x00, 0x7f, 0x00, 0x00], // |
+    [0x00, 0x41, 0x36, 0x08, 0x00], // }
+    [0x08, 0x04, 0x08, 0x10, 0x08], // ~
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::abstract_model::{Connector, Side};
+
+    #[test]
+    fn text_and_images_become_bitmaps() {
+        let text = Bitmap::text("Hi\n!", Colour::Red);
+        assert_eq!((11, 15), (text.width(), text.height()));
+        // Left stem of the H, dot of the i and the exclamation mark
+        assert_eq!(Some(Colour::Red), text.get(0, 6));
+        assert_eq!(None, text.get(1, 6));
+        assert_eq!(Some(Colour::Red), text.get(8, 0));
+        assert_eq!(None, text.get(8, 1));
+        assert_eq!(Some(Colour::Red), text.get(2, 8));
+
+        let plain = Bitmap::from_pbm(b"P1\n# comment\n3 2\n1 0 1\n0 1 0\n", Colour::White).unwrap();
+        let raw = Bitmap::from_pbm(b"P4 3 2\n\xa0\x40", Colour::White).unwrap();
+        assert_eq!(plain, raw);
+        assert_eq!(
+            vec![(0, 0), (2, 0), (1, 1)],
+            plain.lit().map(|(x, y, _)| (x, y)).collect::<Vec<_>>()
+        );
+        assert!(Bitmap::from_pbm(b"P1 2 2 1 0", Colour::White).is_err());
+
+        let rgba = [
+            250, 10, 10, 255, 40, 40, 40, 255, 90, 90, 100, 255, 0, 0, 255, 0,
+        ];
+        let image = Bitmap::from_rgba(4, 1, &rgba).unwrap();
+        let colours: Vec<Option<Colour>> = (0..4).map(|x| image.get(x, 0)).collect();
+        assert_eq!(
+            vec![Some(Colour::Red), None, Some(Colour::White), None],
+            colours
+        );
+        assert_eq!(8, image.scaled(2).lit().count());
+    }
+
+    #[test]
+    fn lamps_of_a_colour_share_a_network() {
+        // Two red areas separated by a blue column, fully lit
+        let mut bitmap = Bitmap::new(3, 3);
+        for y in 0..3 {
+            bitmap.set(0, y, Some(Colour::Red));
+            bitmap.set(1, y, Some(Colour::Blue));
+            bitmap.set(2, y, Some(Colour::Red));
+        }
+        let blueprint = lamps(&bitmap, PoleType::Medium).unwrap();
+        assert!(blueprint.check_wire_reach().is_empty());
+
+        let networks = blueprint.circuit_networks();
+        let signals = |id: usize| -> Vec<String> {
+            let connector = Connector {
+                id,
+                side: Side::One,
+            };
+            let network = networks.network_of(&connector, Wire::Green).unwrap();
+            network
+                .connectors
+                .iter()
+                .filter_map(|c| match &blueprint.entities[c.id] {
+                    Entity::ConstantCombinator { condition, .. } => {
+                        Some(condition[0].signal.name.clone())
+                    }
+                    _ => None,
+                })
+                .collect()
+        };
+        for (x, y, colour) in bitmap.lit() {
+            assert_eq!(vec![colour.signal().name], signals(y * 3 + x));
+        }
+        let lamps = blueprint
+            .entities
+            .iter()
+            .filter(|e| matches!(e, Entity::Lamp { .. }))
+            .count();
+        assert_eq!(9, lamps);
+
+        // Nothing but lamps inside of the image, even on unlit pixels
+        bitmap.set(1, 1, None);
+        let blueprint = super::lamps(&bitmap, PoleType::Medium).unwrap();
+        let inside = |&(x, y): &Tile| (0..3).contains(&x) && (0..3).contains(&y);
+        for entity in &blueprint.entities {
+            let is_lamp = matches!(entity, Entity::Lamp { .. });
+            assert_eq!(is_lamp, entity.tiles().iter().all(inside));
+        }
+
+        assert_eq!(
+            DisplayError::Empty,
+            super::lamps(&Bitmap::new(2, 2), PoleType::Medium).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn tiles_cover_the_image() {
+        let bitmap = Bitmap::text("I", Colour::White);
+        let options = TileOptions {
+            background: Some("landfill".into()),
+            ..TileOptions::default()
+        };
+        let blueprint = tiles(&bitmap, &options).unwrap();
+        assert_eq!(35, blueprint.tiles.len());
+        let concrete = blueprint
+            .tiles
+            .iter()
+            .filter(|t| t.name == "refined-concrete")
+            .count();
+        assert_eq!(bitmap.lit().count(), concrete);
+    }
+}
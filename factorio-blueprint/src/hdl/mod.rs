@@ -0,0 +1,752 @@
+//! Builder for synchronous circuits made of registers, memories and combinational logic,
+//! which are lowered to combinators. For example a counter that adds an input every cycle:
+//!
+//! ```
+//! use factorio_blueprint::{hdl::Design, model::Signal};
+//!
+//! let mut design = Design::new();
+//! let step = design.input(Signal::virtual_signal("signal-S"));
+//! let counter = design.register(Signal::virtual_signal("signal-C"));
+//! design.set(&counter, counter.q() + step);
+//! let circuit = design.build().unwrap();
+//! assert!(circuit.period >= 2);
+//! ```
+//!
+//! All registers and inputs share one red network, the bus. Every `period` ticks a clock pulse
+//! makes the registers take over their next value and the memories perform their write.
+//! The combinational logic is compiled by [`crate::compiler`], so all paths are padded to the
+//! same latency, and the period is derived from the longest path through logic and memories.
+
+mod ops;
+
+use std::{collections::BTreeSet, error::Error, fmt};
+
+use crate::{
+    abstract_model::{utility::UtilityError, Blueprint, Connector, Entity, PoleType, Side, Wire},
+    compiler::{self, parser::Expr, parser::Statement, CompileError, Compiled},
+    model::{
+        ArithmeticCondition, Comparator, DeciderCondition, Direction, Operation, Position, Signal,
+    },
+    primitives::{self, Clock},
+    ram::{self, RamError, RamOptions},
+};
+
+/// Signal of the clock pulse. It only exists on the clock network.
+const CLOCK: &str = "signal-dot";
+const RESERVED: [&str; 4] = [CLOCK, "signal-everything", "signal-anything", "signal-each"];
+
+/// Memories are split into columns of this many cells.
+const MEMORY_HEIGHT: usize = 16;
+
+#[derive(Debug, PartialEq)]
+pub enum HdlError {
+    DuplicateSignal(String),
+    ReservedSignal(String),
+    UnknownSignal(String),
+    MultipleAssignments(String),
+    Empty,
+    Compile(CompileError),
+    Memory(RamError),
+    Layout(UtilityError),
+}
+
+impl fmt::Display for HdlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::DuplicateSignal(name) => write!(f, "signal '{}' is used on the bus twice", name),
+            Self::ReservedSignal(name) => write!(f, "signal '{}' is reserved", name),
+            Self::UnknownSignal(name) => write!(f, "signal '{}' is not on the bus", name),
+            Self::MultipleAssignments(name) => {
+                write!(f, "'{}' is assigned more than once", name)
+            }
+            Self::Empty => write!(f, "design does not contain any registers or memories"),
+            Self::Compile(cause) => write!(f, "could not compile logic: {}", cause),
+            Self::Memory(cause) => write!(f, "could not generate a memory: {}", cause),
+            Self::Layout(cause) => write!(f, "could not lay out the design: {}", cause),
+        }
+    }
+}
+
+impl Error for HdlError {}
+
+impl From<CompileError> for HdlError {
+    fn from(e: CompileError) -> Self {
+        HdlError::Compile(e)
+    }
+}
+
+impl From<RamError> for HdlError {
+    fn from(e: RamError) -> Self {
+        HdlError::Memory(e)
+    }
+}
+
+impl From<UtilityError> for HdlError {
+    fn from(e: UtilityError) -> Self {
+        HdlError::Layout(e)
+    }
+}
+
+pub type Result<T> = core::result::Result<T, HdlError>;
+
+/// Handle of a register, its value is available on the bus as its signal.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Register {
+    index: usize,
+    signal: Signal,
+}
+
+impl Register {
+    pub fn signal(&self) -> &Signal {
+        &self.signal
+    }
+
+    /// Current value of the register.
+    pub fn q(&self) -> Expr {
+        Expr::Signal(self.signal.clone())
+    }
+}
+
+/// Handle of a memory, the word at its read address is available on the bus as its signal.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Memory {
+    index: usize,
+    signal: Signal,
+}
+
+impl Memory {
+    pub fn signal(&self) -> &Signal {
+        &self.signal
+    }
+
+    /// Word at the current read address.
+    pub fn data(&self) -> Expr {
+        Expr::Signal(self.signal.clone())
+    }
+}
+
+#[derive(Clone, Debug)]
+struct RegisterSpec {
+    signal: Signal,
+    next: Vec<Expr>,
+}
+
+#[derive(Clone, Debug)]
+struct Write {
+    address: Expr,
+    data: Expr,
+    enable: Option<Expr>,
+}
+
+#[derive(Clone, Debug)]
+struct MemorySpec {
+    signal: Signal,
+    words: usize,
+    read: Vec<Expr>,
+    write: Vec<Write>,
+}
+
+/// Description of a synchronous circuit, see the module documentation.
+#[derive(Clone, Debug, Default)]
+pub struct Design {
+    inputs: Vec<Signal>,
+    registers: Vec<RegisterSpec>,
+    memories: Vec<MemorySpec>,
+    min_period: u32,
+}
+
+/// A lowered design.
+#[derive(Debug)]
+pub struct Circuit {
+    pub blueprint: Blueprint,
+
+    /// Red network with the inputs, the registers and the data read from the memories.
+    pub bus: Connector,
+
+    /// Green network which carries the clock pulse on `signal-dot`.
+    pub clock: Connector,
+
+    /// Ticks between two clock pulses. Inputs have to be stable for a whole period.
+    pub period: u32,
+}
+
+impl Design {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declares a signal which is driven onto the bus from outside.
+    pub fn input(&mut self, signal: Signal) -> Expr {
+        self.inputs.push(signal.clone());
+        Expr::Signal(signal)
+    }
+
+    /// Adds a register which starts at 0 and keeps its value until it is set.
+    pub fn register(&mut self, signal: Signal) -> Register {
+        self.registers.push(RegisterSpec {
+            signal: signal.clone(),
+            next: Vec::new(),
+        });
+        Register {
+            index: self.registers.len() - 1,
+            signal,
+        }
+    }
+
+    /// Sets the value the register takes over on the next clock pulse.
+    pub fn set(&mut self, register: &Register, next: Expr) {
+        self.registers[register.index].next.push(next);
+    }
+
+    /// Like [`Design::set`], but the register keeps its value while `enable` is 0.
+    pub fn set_if(&mut self, register: &Register, next: Expr, enable: Expr) {
+        let next = Expr::select(enable, next, register.q());
+        self.set(register, next);
+    }
+
+    /// Adds a memory of `words` words, which are addressed from 0. Memories start zeroed.
+    pub fn memory(&mut self, signal: Signal, words: usize) -> Memory {
+        self.memories.push(MemorySpec {
+            signal: signal.clone(),
+            words: words.max(1),
+            read: Vec::new(),
+            write: Vec::new(),
+        });
+        Memory {
+            index: self.memories.len() - 1,
+            signal,
+        }
+    }
+
+    /// Sets the read address of the memory and returns the word that is read.
+    pub fn read(&mut self, memory: &Memory, address: Expr) -> Expr {
+        self.memories[memory.index].read.push(address);
+        memory.data()
+    }
+
+    /// Writes `data` to `address` on every clock pulse.
+    pub fn write(&mut self, memory: &Memory, address: Expr, data: Expr) {
+        self.memories[memory.index].write.push(Write {
+            address,
+            data,
+            enable: None,
+        });
+    }
+
+    /// Like [`Design::write`], but only writes while `enable` is not 0.
+    pub fn write_if(&mut self, memory: &Memory, address: Expr, data: Expr, enable: Expr) {
+        self.memories[memory.index].write.push(Write {
+            address,
+            data,
+            enable: Some(enable),
+        });
+    }
+
+    /// Lower bound for the clock period, e.g. to give slow inputs more time.
+    pub fn min_period(&mut self, ticks: u32) {
+        self.min_period = ticks;
+    }
+
+    /// Signals on the bus, checking that they are unique and not reserved.
+    fn bus_signals(&self) -> Result<Vec<Signal>> {
+        let signals: Vec<Signal> = self
+            .inputs
+            .iter()
+            .chain(self.registers.iter().map(|r| &r.signal))
+            .chain(self.memories.iter().map(|m| &m.signal))
+            .cloned()
+            .collect();
+
+        let mut seen = BTreeSet::new();
+        for signal in &signals {
+            if RESERVED.contains(&signal.name.as_str()) {
+                return Err(HdlError::ReservedSignal(signal.name.clone()));
+            }
+            if !seen.insert(signal) {
+                return Err(HdlError::DuplicateSignal(signal.name.clone()));
+            }
+        }
+
+        Ok(signals)
+    }
+
+    fn check_assignments(&self, bus: &[Signal]) -> Result<()> {
+        let mut expressions = Vec::new();
+        for register in &self.registers {
+            if register.next.len() > 1 {
+                return Err(HdlError::MultipleAssignments(register.signal.name.clone()));
+            }
+            expressions.extend(&register.next);
+        }
+        for memory in &self.memories {
+            if memory.read.len() > 1 || memory.write.len() > 1 {
+                return Err(HdlError::MultipleAssignments(memory.signal.name.clone()));
+            }
+            expressions.extend(&memory.read);
+            for write in &memory.write {
+                expressions.extend([&write.address, &write.data]);
+                expressions.extend(&write.enable);
+            }
+        }
+
+        let mut unknown = None;
+        for expr in expressions {
+            expr.visit_signals(&mut |s| {
+                if !bus.contains(s) {
+                    unknown.get_or_insert_with(|| s.name.clone());
+                }
+            });
+        }
+        match unknown {
+            Some(name) => Err(HdlError::UnknownSignal(name)),
+            None => Ok(()),
+        }
+    }
+
+    /// Lowers the design to combinators, lays them out and connects them to power.
+    pub fn build(&self) -> Result<Circuit> {
+        if self.registers.is_empty() && self.memories.is_empty() {
+            return Err(HdlError::Empty);
+        }
+        let bus = self.bus_signals()?;
+        self.check_assignments(&bus)?;
+
+        let mut reserved = bus.clone();
+        reserved.push(Signal::virtual_signal(CLOCK));
+        let compile = |statements: Vec<Statement>| -> Result<Option<Compiled>> {
+            if statements.is_empty() {
+                return Ok(None);
+            }
+            Ok(Some(compiler::compile_netlist(&statements, &reserved)?))
+        };
+
+        let next_state = compile(
+            self.registers
+                .iter()
+                .filter_map(|r| {
+                    r.next
+                        .first()
+                        .map(|next| statement(&r.signal, next.clone()))
+                })
+                .collect(),
+        )?;
+
+        let mut memories = Vec::new();
+        for memory in &self.memories {
+            let read = compile(
+                memory
+                    .read
+                    .iter()
+                    .map(|address| statement(&signal("signal-R"), address.clone() + 1))
+                    .collect(),
+            )?;
+            let write = match memory.write.first() {
+                Some(write) => {
+                    let mut address = statement(&signal("signal-W"), write.address.clone() + 1);
+                    address.condition = write.enable.clone();
+                    let data = statement(&signal("signal-green"), write.data.clone());
+                    compile(vec![address, data])?
+                }
+                None => None,
+            };
+            memories.push((memory, read, write));
+        }
+
+        let period = self.period(&next_state, &memories);
+        let mut netlist = Netlist::new();
+
+        // Clock: counts through the period and pulses once per cycle
+        let clock = netlist.add(clock(period));
+        let pulse = Connector {
+            id: clock[clock.len() - 1],
+            side: Side::Two,
+        };
+
+        let next_state = next_state.map(|program| netlist.add_program(program));
+        let signals: Vec<&Signal> = self.registers.iter().map(|r| &r.signal).collect();
+        let registers = netlist.add(registers(&signals));
+
+        let mut bus_members = Vec::new();
+        let mut clocked = Vec::new();
+        for (i, register) in self.registers.iter().enumerate() {
+            let (hold, load) = (registers[2 * i], registers[2 * i + 1]);
+            bus_members.extend([
+                Connector {
+                    id: hold,
+                    side: Side::One,
+                },
+                Connector {
+                    id: hold,
+                    side: Side::Two,
+                },
+                Connector {
+                    id: load,
+                    side: Side::Two,
+                },
+            ]);
+            clocked.extend([hold, load]);
+            if let (Some(next_state), false) = (&next_state, register.next.is_empty()) {
+                netlist.wires.push((
+                    next_state.output.clone(),
+                    Connector {
+                        id: load,
+                        side: Side::One,
+                    },
+                    Wire::Red,
+                ));
+            }
+        }
+        if let Some(program) = &next_state {
+            bus_members.extend(program.input.clone());
+        }
+
+        for (memory, read, write) in memories {
+            let read = read.map(|program| netlist.add_program(program));
+            let write = write.map(|program| netlist.add_program(program));
+            let ports = netlist.add(memory_ports(&memory.signal));
+            let (gate, rename) = (ports[0], ports[1]);
+            let ram = ram::ram(&RamOptions {
+                width: memory.words.div_ceil(MEMORY_HEIGHT),
+                height: memory.words.min(MEMORY_HEIGHT),
+                ..RamOptions::default()
+            })?;
+            let cells = netlist.add(ram.blueprint);
+            let memory_bus = Connector {
+                id: cells[ram.bus.id],
+                side: Side::One,
+            };
+
+            if let Some(read) = read {
+                bus_members.extend(read.input.clone());
+                netlist.wires.extend([
+                    (read.output, memory_bus.clone(), Wire::Green),
+                    (
+                        memory_bus.clone(),
+                        Connector {
+                            id: rename,
+                            side: Side::One,
+                        },
+                        Wire::Red,
+                    ),
+                ]);
+                bus_members.push(Connector {
+                    id: rename,
+                    side: Side::Two,
+                });
+            }
+            if let Some(write) = write {
+                bus_members.extend(write.input.clone());
+                clocked.push(gate);
+                netlist.wires.extend([
+                    (
+                        write.output,
+                        Connector {
+                            id: gate,
+                            side: Side::One,
+                        },
+                        Wire::Red,
+                    ),
+                    (
+                        Connector {
+                            id: gate,
+                            side: Side::Two,
+                        },
+                        memory_bus,
+                        Wire::Green,
+                    ),
+                ]);
+            }
+        }
+
+        for id in clocked {
+            netlist.wires.push((
+                pulse.clone(),
+                Connector {
+                    id,
+                    side: Side::One,
+                },
+                Wire::Green,
+            ));
+        }
+        let bus = bus_members.first().cloned().ok_or(HdlError::Empty)?;
+        for member in &bus_members[1..] {
+            netlist.wires.push((bus.clone(), member.clone(), Wire::Red));
+        }
+
+        Ok(Circuit {
+            blueprint: netlist.finish()?,
+            bus,
+            clock: pulse,
+            period,
+        })
+    }
+
+    /// Ticks between two clock pulses, such that all values settled before the next pulse.
+    ///
+    /// Registers update one tick after the pulse. Data read from a memory reaches the bus
+    /// two ticks after its address, which may depend on data read from other memories.
+    /// A write shows up on the bus five ticks after the pulse.
+    fn period(
+        &self,
+        next_state: &Option<Compiled>,
+        memories: &[(&MemorySpec, Option<Compiled>, Option<Compiled>)],
+    ) -> u32 {
+        let latency = |program: &Option<Compiled>| program.as_ref().map_or(0, |p| p.latency);
+
+        let mut settled = 1;
+        let mut logic = latency(next_state);
+        for (_, read, write) in memories {
+            if read.is_some() {
+                settled += latency(read) + 2;
+                if write.is_some() {
+                    settled = settled.max(5);
+                }
+            }
+            logic = logic.max(latency(write));
+        }
+
+        (settled + logic).max(2).max(self.min_period)
+    }
+}
+
+fn signal(name: &str) -> Signal {
+    Signal::virtual_signal(name)
+}
+
+fn statement(output: &Signal, value: Expr) -> Statement {
+    Statement {
+        output: output.clone(),
+        value,
+        condition: None,
+        line: 0,
+    }
+}
+
+/// Decider facing east, its position is left to the layout.
+fn decider(id: usize, condition: DeciderCondition) -> Entity {
+    Entity::DeciderCombinator {
+        id,
+        position: Position { x: 0.0, y: 0.0 },
+        direction: Direction::East,
+        connections: Vec::new(),
+        condition,
+        tags: None,
+    }
+}
+
+/// Condition that copies `output` from the input if `first` compares to `constant`.
+fn copy_if(
+    first: &str,
+    comparator: Comparator,
+    constant: i32,
+    output: &Signal,
+) -> DeciderCondition {
+    DeciderCondition {
+        comparator,
+        copy_count_from_input: true,
+        constant: Some(constant),
+        first_signal: Some(signal(first)),
+        second_signal: None,
+        output_signal: Some(output.clone()),
+    }
+}
+
+fn empty_blueprint() -> Blueprint {
+    Blueprint {
+        version: compiler::VERSION,
+        ..Blueprint::default()
+    }
+}
+
+/// Counter that cycles through `period` values, its constant combinator and a decider (the
+/// last entity) that outputs a pulse of one tick once per cycle.
+fn clock(period: u32) -> Blueprint {
+    let template = primitives::clock();
+    let mut blueprint = empty_blueprint();
+    let clock = template.instantiate(&mut blueprint, 0.0, 0.0);
+    if let Entity::DeciderCombinator { condition, .. } =
+        &mut blueprint.entities[clock.id(Clock::Counter)]
+    {
+        condition.comparator = Comparator::Lt;
+        condition.first_signal = Some(signal("signal-W"));
+        condition.constant = Some(period as i32);
+    }
+    if let Entity::ConstantCombinator { is_on, .. } =
+        &mut blueprint.entities[clock.id(Clock::Constant)]
+    {
+        *is_on = true;
+    }
+
+    // The counter network carries the counter plus the one of the constant combinator
+    let mut condition = copy_if("signal-W", Comparator::Eq, 1, &signal(CLOCK));
+    condition.copy_count_from_input = false;
+    let pulse = blueprint.entities.len();
+    blueprint.entities.push(decider(pulse, condition));
+    blueprint
+        .connect_wire_with_side(
+            clock.connector(Clock::Output),
+            Connector {
+                id: pulse,
+                side: Side::One,
+            },
+            Wire::Green,
+        )
+        .expect("clock entities exist");
+    blueprint
+}
+
+/// Pairs of a holding decider (even ids) and a loading decider (odd ids) per register.
+/// The first feeds the register back into itself while there is no clock pulse, the second
+/// takes over the next value during the pulse.
+fn registers(signals: &[&Signal]) -> Blueprint {
+    let mut blueprint = empty_blueprint();
+    for (i, &signal) in signals.iter().enumerate() {
+        let hold = copy_if(CLOCK, Comparator::Eq, 0, signal);
+        let load = copy_if(CLOCK, Comparator::Neq, 0, signal);
+        blueprint.entities.push(decider(2 * i, hold));
+        blueprint.entities.push(decider(2 * i + 1, load));
+    }
+    blueprint
+}
+
+/// Decider (id 0) which passes writes to the memory bus during the clock pulse and
+/// arithmetic combinator (id 1) which puts the data that was read on the bus as `data`.
+fn memory_ports(data: &Signal) -> Blueprint {
+    let mut blueprint = empty_blueprint();
+    let gate = copy_if(CLOCK, Comparator::Neq, 0, &signal("signal-everything"));
+    blueprint.entities.push(decider(0, gate));
+    blueprint.entities.push(Entity::ArithmeticCombinator {
+        id: 1,
+        position: Position { x: 0.0, y: 0.0 },
+        direction: Direction::East,
+        connections: Vec::new(),
+        condition: ArithmeticCondition {
+            operation: Operation::Add,
+            first_constant: None,
+            second_constant: Some(0),
+            first_signal: Some(signal("signal-green")),
+            second_signal: None,
+            output_signal: Some(data.clone()),
+        },
+        tags: None,
+    });
+    blueprint
+}
+
+/// Collects the parts of the design and their wires, the placement is left to
+/// [`Blueprint::layout`].
+struct Netlist {
+    blueprint: Blueprint,
+    wires: Vec<(Connector, Connector, Wire)>,
+}
+
+impl Netlist {
+    fn new() -> Self {
+        Netlist {
+            blueprint: empty_blueprint(),
+            wires: Vec::new(),
+        }
+    }
+
+    /// Adds the part and returns the new ids of its entities.
+    fn add(&mut self, part: Blueprint) -> Vec<usize> {
+        self.blueprint.append(part, 0.0, 0.0).new_ids()
+    }
+
+    /// Adds a compiled program and moves its ports along.
+    fn add_program(&mut self, mut program: Compiled) -> Compiled {
+        let blueprint = std::mem::replace(&mut program.blueprint, empty_blueprint());
+        let ids = self.add(blueprint);
+        let update = |c: &mut Connector| c.id = ids[c.id];
+        program.input.iter_mut().for_each(update);
+        update(&mut program.output);
+        program
+    }
+
+    fn finish(mut self) -> Result<Blueprint> {
+        for (c1, c2, wire) in std::mem::take(&mut self.wires) {
+            self.blueprint.connect_wire_with_side(c1, c2, wire)?;
+        }
+        self.blueprint.layout(PoleType::Medium)?;
+        Ok(self.blueprint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::harness::Harness;
+
+    fn s(name: &str) -> Signal {
+        Signal::virtual_signal(name)
+    }
+
+    #[test]
+    fn registers_and_memory_update_once_per_period() {
+        let mut design = Design::new();
+        let (step, enable) = (design.input(s("signal-S")), design.input(s("signal-E")));
+        let count = design.register(s("signal-C"));
+        let sum = design.register(s("signal-A"));
+        let memory = design.memory(s("signal-M"), 20);
+
+        design.set(&count, count.q() + 1);
+        design.set_if(&sum, sum.q() + step, enable);
+        design.write(&memory, count.q() % 20, count.q() * 10);
+        let previous = design.read(&memory, (count.q() + 19) % 20);
+
+        let circuit = design.build().unwrap();
+        assert!(circuit.blueprint.check_wire_reach().is_empty());
+
+        let mut harness = Harness::new(&circuit.blueprint);
+        harness.port("bus", circuit.bus.clone(), Wire::Red).unwrap();
+        harness
+            .drive("bus", &[(s("signal-S"), 5), (s("signal-E"), 1)])
+            .unwrap();
+        while harness.read_signal("bus", &s("signal-C")).unwrap() == 0 {
+            assert!(harness.tick() < 2 * circuit.period as u64);
+            harness.step(1);
+        }
+
+        // Check the last tick of every period, when everything has settled
+        let period = circuit.period as u64;
+        harness.step(period - 1);
+        for k in 1..30 {
+            harness.expect("bus", &s("signal-C"), k).unwrap();
+            harness
+                .expect("bus", &s("signal-A"), 5 * k.min(21))
+                .unwrap();
+            harness
+                .expect("bus", memory.signal(), (k - 1) * 10)
+                .unwrap();
+            harness.step(1);
+            harness.expect("bus", &s("signal-C"), k + 1).unwrap();
+
+            // Inputs are sampled before the pulse, so changes apply from the next period
+            if k == 20 {
+                harness.drive("bus", &[(s("signal-S"), 5)]).unwrap();
+            }
+            harness.step(period - 1);
+        }
+        assert_eq!(Expr::Signal(s("signal-M")), previous);
+    }
+
+    #[test]
+    fn build_checks_signals() {
+        let mut design = Design::new();
+        assert_eq!(HdlError::Empty, design.build().unwrap_err());
+
+        let r = design.register(s("signal-R"));
+        design.set(&r, r.q() + Expr::Signal(s("signal-X")));
+        assert_eq!(
+            HdlError::UnknownSignal("signal-X".into()),
+            design.build().unwrap_err()
+        );
+
+        design.input(s("signal-X"));
+        design.input(s("signal-R"));
+        assert_eq!(
+            HdlError::DuplicateSignal("signal-R".into()),
+            design.build().unwrap_err()
+        );
+    }
+}
@@ -0,0 +1,116 @@
+//! Operators to build expressions in Rust, e.g. `(a * 3 + b) >> 2`.
+
+use std::ops;
+
+use crate::{
+    compiler::parser::Expr,
+    model::{Comparator, Operation, Signal},
+};
+
+impl From<i32> for Expr {
+    fn from(value: i32) -> Self {
+        Expr::Constant(value)
+    }
+}
+
+impl From<Signal> for Expr {
+    fn from(signal: Signal) -> Self {
+        Expr::Signal(signal)
+    }
+}
+
+macro_rules! binary_operator {
+    ($trait:ident, $method:ident, $operation:expr) => {
+        impl<T: Into<Expr>> ops::$trait<T> for Expr {
+            type Output = Expr;
+
+            fn $method(self, rhs: T) -> Expr {
+                Expr::Binary($operation, Box::new(self), Box::new(rhs.into()))
+            }
+        }
+    };
+}
+
+binary_operator!(Add, add, Operation::Add);
+binary_operator!(Sub, sub, Operation::Sub);
+binary_operator!(Mul, mul, Operation::Mul);
+binary_operator!(Div, div, Operation::Div);
+binary_operator!(Rem, rem, Operation::Mod);
+binary_operator!(Shl, shl, Operation::Shl);
+binary_operator!(Shr, shr, Operation::Shr);
+binary_operator!(BitAnd, bitand, Operation::And);
+binary_operator!(BitOr, bitor, Operation::Or);
+binary_operator!(BitXor, bitxor, Operation::Xor);
+
+impl ops::Neg for Expr {
+    type Output = Expr;
+
+    fn neg(self) -> Expr {
+        Expr::Negate(Box::new(self))
+    }
+}
+
+/// Logical negation, 1 if the value is 0 and 0 otherwise.
+impl ops::Not for Expr {
+    type Output = Expr;
+
+    fn not(self) -> Expr {
+        Expr::Not(Box::new(self))
+    }
+}
+
+impl Expr {
+    pub fn pow(self, exponent: impl Into<Expr>) -> Expr {
+        Expr::Binary(Operation::Pow, Box::new(self), Box::new(exponent.into()))
+    }
+
+    /// 1 if the comparison holds, 0 otherwise.
+    pub fn compare(self, comparator: Comparator, rhs: impl Into<Expr>) -> Expr {
+        Expr::Compare(comparator, Box::new(self), Box::new(rhs.into()))
+    }
+
+    pub fn lt(self, rhs: impl Into<Expr>) -> Expr {
+        self.compare(Comparator::Lt, rhs)
+    }
+
+    pub fn le(self, rhs: impl Into<Expr>) -> Expr {
+        self.compare(Comparator::Le, rhs)
+    }
+
+    pub fn gt(self, rhs: impl Into<Expr>) -> Expr {
+        self.compare(Comparator::Gt, rhs)
+    }
+
+    pub fn ge(self, rhs: impl Into<Expr>) -> Expr {
+        self.compare(Comparator::Ge, rhs)
+    }
+
+    pub fn equals(self, rhs: impl Into<Expr>) -> Expr {
+        self.compare(Comparator::Eq, rhs)
+    }
+
+    pub fn not_equals(self, rhs: impl Into<Expr>) -> Expr {
+        self.compare(Comparator::Neq, rhs)
+    }
+
+    /// Logical and, 1 if both values are not 0.
+    pub fn and(self, rhs: impl Into<Expr>) -> Expr {
+        Expr::And(Box::new(self), Box::new(rhs.into()))
+    }
+
+    /// Logical or, 1 if any value is not 0.
+    pub fn or(self, rhs: impl Into<Expr>) -> Expr {
+        Expr::Or(Box::new(self), Box::new(rhs.into()))
+    }
+
+    /// `then` if the condition is not 0, `otherwise` if it is.
+    pub fn select(condition: Expr, then: impl Into<Expr>, otherwise: impl Into<Expr>) -> Expr {
+        let condition = if condition.is_boolean() {
+            condition
+        } else {
+            condition.not_equals(0)
+        };
+        let otherwise = otherwise.into();
+        otherwise.clone() + (then.into() - otherwise) * condition
+    }
+}
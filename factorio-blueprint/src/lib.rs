@@ -1,5 +1,18 @@
 pub mod abstract_model;
+pub mod compiler;
+pub mod diff;
+pub mod display;
+pub mod hdl;
+pub mod merge;
 pub mod model;
+pub mod primitives;
+pub mod query;
+pub mod ram;
+pub mod render;
+pub mod rom;
+pub mod simulation;
+pub mod stats;
+pub mod template;
 
 use core::fmt;
 use std::{
@@ -23,6 +36,9 @@ pub enum BlueprintError {
     JsonDecode,
     JsonSerialize,
     JsonDeserialize,
+    InvalidEntityNumber { expected: u32, found: u32 },
+    UnknownEntity { from: u32, to: u32 },
+    MissingCondition { entity: u32 },
 }
 
 impl fmt::Display for BlueprintError {
@@ -39,6 +55,17 @@ impl fmt::Display for BlueprintError {
             Self::JsonDecode => write!(f, "json decode of blueprint failed"),
             Self::JsonSerialize => write!(f, "json serialize of blueprint failed"),
             Self::JsonDeserialize => write!(f, "json deserialize of blueprint failed"),
+            Self::InvalidEntityNumber { expected, found } => write!(
+                f,
+                "entity numbers must count up from 1, expected {} but found {}",
+                expected, found
+            ),
+            Self::UnknownEntity { from, to } => {
+                write!(f, "entity {} is connected to unknown entity {}", from, to)
+            }
+            Self::MissingCondition { entity } => {
+                write!(f, "combinator {} has no condition", entity)
+            }
         }
     }
 }
@@ -49,14 +76,14 @@ pub type Result<T> = core::result::Result<T, BlueprintError>;
 
 pub fn blueprint_string_to_json(blueprint: &str) -> Result<serde_json::Value> {
     // Check version
-    match blueprint.chars().nth(0) {
+    match blueprint.chars().next() {
         Some('0') => {}
         _ => return Err(BlueprintError::InvalidVersion),
     }
 
     // Base64 Decode
     let decoded = base64::decode(&blueprint[1..])
-        .map_err(|cause| BlueprintError::Base64Decode(cause))?;
+        .map_err(BlueprintError::Base64Decode)?;
 
     // Zlib Inflate
     let mut inflator = ZlibDecoder::new(&decoded[..]);
@@ -86,7 +113,7 @@ pub fn blueprint_string_to_raw_model(blueprint: &str) -> Result<BlueprintContain
 
 pub fn blueprint_string_to_model(blueprint: &str) -> Result<Blueprint> {
     let raw_model = blueprint_string_to_raw_model(blueprint)?;
-    Ok(Blueprint::from(raw_model))
+    Blueprint::try_from_raw(raw_model)
 }
 
 pub fn raw_model_to_pretty_json(raw_model: &BlueprintContainer) -> Result<String> {
@@ -100,6 +127,57 @@ pub fn model_to_pretty_json(model: Blueprint) -> Result<String> {
     raw_model_to_pretty_json(&raw_model)
 }
 
+/// Checks that the entity numbers count up from 1 and that wires and pole connections only
+/// refer to entities of the blueprint and that combinators have their conditions, which the
+/// game and [`Blueprint::from`] rely on.
+pub fn check_raw_model(raw_model: &BlueprintContainer) -> Result<()> {
+    let entities = &raw_model.blueprint.entities;
+    for (expected, entity) in (1u32..).zip(entities) {
+        if entity.entity_number != expected {
+            return Err(BlueprintError::InvalidEntityNumber {
+                expected,
+                found: entity.entity_number,
+            });
+        }
+    }
+
+    for entity in entities {
+        let wires = entity
+            .connections
+            .iter()
+            .flat_map(|c| [&c.connection1, &c.connection2])
+            .flatten()
+            .flat_map(|point| [&point.red, &point.green])
+            .flatten()
+            .flatten()
+            .map(|data| data.entity_id);
+        let neighbours = entity.neighbours.iter().flatten().copied();
+        if let Some(to) = wires
+            .chain(neighbours)
+            .find(|&id| id == 0 || id as usize > entities.len())
+        {
+            return Err(BlueprintError::UnknownEntity {
+                from: entity.entity_number,
+                to,
+            });
+        }
+
+        let control_behavior = entity.control_behavior.as_ref();
+        let missing = match entity.name.as_str() {
+            "decider-combinator" => control_behavior.and_then(|c| c.decider_conditions.as_ref()).is_none(),
+            "arithmetic-combinator" => control_behavior.and_then(|c| c.arithmetic_conditions.as_ref()).is_none(),
+            _ => false,
+        };
+        if missing {
+            return Err(BlueprintError::MissingCondition {
+                entity: entity.entity_number,
+            });
+        }
+    }
+
+    Ok(())
+}
+
 pub fn raw_model_to_blueprint_string(raw_model: &BlueprintContainer) -> Result<String> {
     let json = raw_model_to_pretty_json(raw_model)?;
 
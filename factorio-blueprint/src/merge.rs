@@ -0,0 +1,457 @@
+//! Three-way merge of blueprints.
+//!
+//! Like [`diff`](crate::diff), entities are matched by their name and position. A change of
+//! only one side to the base is taken over, changes of both sides to the same entity, tile or
+//! to the ends of a wire are conflicts.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
+    fmt,
+};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+    abstract_model::{Blueprint, Connector, Entity, Side, Wire},
+    diff::{self, entity_ref, json, wire_ref, EntityRef, WireRef},
+    model::{self, Position},
+};
+
+/// How one side changed an entity or tile of the base.
+#[derive(Serialize, Copy, Clone, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Edit {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+#[serde(tag = "conflict", rename_all = "snake_case")]
+pub enum Conflict {
+    /// Both sides changed the entity in different ways.
+    Entity {
+        entity: EntityRef,
+        ours: Edit,
+        theirs: Edit,
+    },
+    /// Both sides placed different tiles at the position.
+    Tile {
+        position: Position,
+        ours: Edit,
+        theirs: Edit,
+    },
+    /// One side kept or added the wire, the other removed the entity at one of its ends.
+    Wire { wire: WireRef, removed: EntityRef },
+    /// The wire cannot connect its ends, e.g. a copper cable to an entity which is no pole.
+    InvalidWire { wire: WireRef },
+}
+
+/// All conflicts of a merge, ordered like the changes of a diff.
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct MergeConflicts {
+    pub conflicts: Vec<Conflict>,
+}
+
+pub type Result<T> = core::result::Result<T, MergeConflicts>;
+
+/// Merges the changes from `base` to `ours` and from `base` to `theirs`. The entities of the
+/// result are ordered by position.
+pub fn merge(base: &Blueprint, ours: &Blueprint, theirs: &Blueprint) -> Result<Blueprint> {
+    let mut conflicts = Vec::new();
+
+    let (base_entities, our_entities, their_entities) = (
+        diff::entities(base),
+        diff::entities(ours),
+        diff::entities(theirs),
+    );
+    let mut entities = BTreeMap::new();
+    for key in keys([&base_entities, &our_entities, &their_entities]) {
+        let [b, o, t] = [&base_entities, &our_entities, &their_entities].map(|e| e.get(&key));
+        match three_way(b, o, t, |e| content(e)) {
+            Ok(Some(entity)) => {
+                entities.insert(key, *entity);
+            }
+            Ok(None) => {}
+            Err((ours, theirs)) => conflicts.push(Conflict::Entity {
+                entity: entity_ref(&key),
+                ours,
+                theirs,
+            }),
+        }
+    }
+
+    let (base_tiles, our_tiles, their_tiles) = (tiles(base), tiles(ours), tiles(theirs));
+    let mut merged_tiles = Vec::new();
+    for key in keys([&base_tiles, &our_tiles, &their_tiles]) {
+        let [b, o, t] = [&base_tiles, &our_tiles, &their_tiles].map(|e| e.get(&key));
+        match three_way(b, o, t, |t| t.name.clone()) {
+            Ok(tile) => merged_tiles.extend(tile.map(|&t| t.clone())),
+            Err((ours, theirs)) => conflicts.push(Conflict::Tile {
+                position: Position {
+                    x: key.1 as f32,
+                    y: key.0 as f32,
+                },
+                ours,
+                theirs,
+            }),
+        }
+    }
+
+    let (base_wires, our_wires, their_wires) =
+        (diff::wires(base), diff::wires(ours), diff::wires(theirs));
+    let mut wires = Vec::new();
+    let all_wires: BTreeSet<_> = base_wires
+        .iter()
+        .chain(&our_wires)
+        .chain(&their_wires)
+        .collect();
+    for wire in all_wires {
+        let [b, o, t] = [&base_wires, &our_wires, &their_wires].map(|w| w.get(wire));
+        if let Ok(None) | Err(_) = three_way(b, o, t, |_| ()) {
+            continue;
+        }
+        match [&wire.0, &wire.1]
+            .into_iter()
+            .find(|end| !entities.contains_key(&end.0))
+        {
+            Some(end) => conflicts.push(Conflict::Wire {
+                wire: wire_ref(wire),
+                removed: entity_ref(&end.0),
+            }),
+            None => wires.push(wire.clone()),
+        }
+    }
+
+    if !conflicts.is_empty() {
+        return Err(MergeConflicts { conflicts });
+    }
+
+    let icons = match json(&ours.icons) == json(&base.icons) {
+        true => &theirs.icons,
+        false => &ours.icons,
+    };
+    let mut merged = Blueprint {
+        entities: Vec::new(),
+        tiles: merged_tiles,
+        version: ours.version.max(theirs.version),
+        icons: icons.clone(),
+    };
+    let mut ids = BTreeMap::new();
+    for (id, (key, entity)) in entities.into_iter().enumerate() {
+        merged.entities.push(unlinked(entity, id));
+        ids.insert(key, id);
+    }
+    for key in wires {
+        let ((from, from_side), (to, to_side), colour) = &key;
+        let (from, to) = (ids[from], ids[to]);
+        let side = |side: &Option<u8>| match side {
+            Some(2) => Side::Two,
+            _ => Side::One,
+        };
+        let connected = match *colour {
+            "red" | "green" => {
+                let wire = match *colour {
+                    "red" => Wire::Red,
+                    _ => Wire::Green,
+                };
+                let from = Connector {
+                    id: from,
+                    side: side(from_side),
+                };
+                let to = Connector {
+                    id: to,
+                    side: side(to_side),
+                };
+                merged.connect_wire_with_side(from, to, wire).is_ok()
+            }
+            _ => connect_copper(&mut merged, from, to),
+        };
+        if !connected {
+            conflicts.push(Conflict::InvalidWire {
+                wire: wire_ref(&key),
+            });
+        }
+    }
+
+    match conflicts.is_empty() {
+        true => Ok(merged),
+        false => Err(MergeConflicts { conflicts }),
+    }
+}
+
+/// Connects two poles with a copper cable, also if they are unknown entities.
+fn connect_copper(blueprint: &mut Blueprint, from: usize, to: usize) -> bool {
+    if from == to {
+        return false;
+    }
+    let is_pole = |id: usize| match &blueprint.entities[id] {
+        Entity::ElectricPole { .. } => true,
+        Entity::Unknown(e) => e.neighbours.is_some(),
+        _ => false,
+    };
+    if !is_pole(from) || !is_pole(to) {
+        return false;
+    }
+    for (id, neighbour) in [(from, to), (to, from)] {
+        match &mut blueprint.entities[id] {
+            Entity::ElectricPole { neighbours, .. } if !neighbours.contains(&neighbour) => {
+                neighbours.push(neighbour)
+            }
+            Entity::Unknown(e) => {
+                let neighbours = e.neighbours.get_or_insert_with(Vec::new);
+                if !neighbours.contains(&(neighbour as u32 + 1)) {
+                    neighbours.push(neighbour as u32 + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    true
+}
+
+/// Result of the base `b`, ours `o` and theirs `t`, compared by `value`, or the edits of both
+/// sides if they conflict.
+fn three_way<T: Copy, V: PartialEq, F: Fn(T) -> V>(
+    b: Option<T>,
+    o: Option<T>,
+    t: Option<T>,
+    value: F,
+) -> core::result::Result<Option<T>, (Edit, Edit)> {
+    let [vb, vo, vt] = [b, o, t].map(|x| x.map(&value));
+    if vo == vt || vt == vb {
+        Ok(o)
+    } else if vo == vb {
+        Ok(t)
+    } else {
+        let edit = |v: &Option<V>| match (&vb, v) {
+            (None, _) => Edit::Added,
+            (_, None) => Edit::Removed,
+            _ => Edit::Changed,
+        };
+        Err((edit(&vo), edit(&vt)))
+    }
+}
+
+/// Union of the keys of all three maps, in order.
+fn keys<K: Ord + Clone, V>(maps: [&BTreeMap<K, V>; 3]) -> Vec<K> {
+    let mut keys: Vec<K> = maps.iter().flat_map(|m| m.keys().cloned()).collect();
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+fn tiles(blueprint: &Blueprint) -> BTreeMap<(i64, i64), &model::Tile> {
+    blueprint
+        .tiles
+        .iter()
+        .map(|t| {
+            (
+                (t.position.y.round() as i64, t.position.x.round() as i64),
+                t,
+            )
+        })
+        .collect()
+}
+
+/// Everything of an entity except for its id, wires and pole connections.
+fn content(entity: &Entity) -> Value {
+    json(&model::Entity::from(unlinked(entity, 0)))
+}
+
+fn unlinked(entity: &Entity, id: usize) -> Entity {
+    let mut entity = entity.clone();
+    entity.update_id(id);
+    if let Some(connections) = entity.connections_mut() {
+        connections.clear();
+    }
+    match &mut entity {
+        Entity::ElectricPole { neighbours, .. } => neighbours.clear(),
+        Entity::Unknown(e) => {
+            e.connections = None;
+            e.neighbours = e.neighbours.as_ref().map(|_| Vec::new());
+        }
+        _ => {}
+    }
+    entity
+}
+
+impl fmt::Display for Edit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Edit::Added => write!(f, "added"),
+            Edit::Removed => write!(f, "removed"),
+            Edit::Changed => write!(f, "changed"),
+        }
+    }
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Conflict::Entity {
+                entity,
+                ours,
+                theirs,
+            } => write!(f, "{}: {} in ours, {} in theirs", entity, ours, theirs),
+            Conflict::Tile {
+                position,
+                ours,
+                theirs,
+            } => write!(
+                f,
+                "tile at ({}, {}): {} in ours, {} in theirs",
+                position.x, position.y, ours, theirs
+            ),
+            Conflict::Wire { wire, removed } => write!(
+                f,
+                "{} wire {} -- {}: {} is removed",
+                wire.colour, wire.from, wire.to, removed
+            ),
+            Conflict::InvalidWire { wire } => write!(
+                f,
+                "{} wire {} -- {}: cannot be connected",
+                wire.colour, wire.from, wire.to
+            ),
+        }
+    }
+}
+
+impl fmt::Display for MergeConflicts {
+    /// One conflict per line.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for conflict in &self.conflicts {
+            writeln!(f, "{}", conflict)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for MergeConflicts {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{model::Comparator, primitives};
+
+    fn blueprint(entities: Vec<Entity>) -> Blueprint {
+        Blueprint::new(entities)
+    }
+
+    fn comparator(blueprint: &mut Blueprint, comparator: Comparator) {
+        for entity in &mut blueprint.entities {
+            if let Entity::DeciderCombinator { condition, .. } = entity {
+                condition.comparator = comparator;
+            }
+        }
+    }
+
+    #[test]
+    fn changes_of_both_sides_are_merged() {
+        let base = blueprint(primitives::loader_cell().blueprint().entities.clone());
+        let mut ours = blueprint(base.entities.clone());
+        comparator(&mut ours, Comparator::Gt);
+        let mut theirs = blueprint(base.entities.clone());
+        theirs.tiles.push(model::Tile {
+            name: "landfill".into(),
+            position: Position { x: 4.0, y: 0.0 },
+        });
+        for entity in &mut theirs.entities {
+            entity.connections_mut().unwrap().clear();
+        }
+
+        let merged = merge(&base, &ours, &theirs).unwrap();
+        let changes = diff::diff(&ours, &merged).changes;
+        assert_eq!(1, changes.len(), "{:?}", changes);
+        assert!(matches!(&changes[0], diff::Change::WireRemoved { .. }));
+        assert_eq!(1, merged.tiles.len());
+        assert!(diff::diff(&base, &merge(&base, &base, &base).unwrap()).is_empty());
+    }
+
+    #[test]
+    fn conflicts_are_reported() {
+        let base = blueprint(primitives::loader_cell().blueprint().entities.clone());
+        let mut ours = blueprint(base.entities.clone());
+        comparator(&mut ours, Comparator::Gt);
+        let constant = base
+            .entities
+            .iter()
+            .position(|e| e.name() == "constant-combinator")
+            .unwrap();
+        ours.connect_wire(0, 1, Wire::Red).unwrap();
+        let mut theirs = blueprint(vec![unlinked(&base.entities[1 - constant], 0)]);
+        comparator(&mut theirs, Comparator::Lt);
+
+        let conflicts = merge(&base, &ours, &theirs).unwrap_err().conflicts;
+        assert_eq!(2, conflicts.len(), "{:?}", conflicts);
+        assert!(matches!(
+            &conflicts[0],
+            Conflict::Entity {
+                ours: Edit::Changed,
+                theirs: Edit::Changed,
+                ..
+            }
+        ));
+        assert!(
+            matches!(&conflicts[1], Conflict::Wire { removed, .. } if removed.name == "constant-combinator")
+        );
+        assert!(MergeConflicts { conflicts }
+            .to_string()
+            .contains(": changed in ours, changed in theirs\n"));
+    }
+
+    #[test]
+    fn wires_of_unknown_entities_are_kept() {
+        let unknown = |id: usize, name: &str, x: f32, neighbours: Option<Vec<u32>>| {
+            Entity::Unknown(model::Entity {
+                entity_number: id as u32 + 1,
+                name: name.into(),
+                position: Position { x, y: 0.5 },
+                direction: None,
+                connections: None,
+                control_behavior: None,
+                neighbours,
+                tags: None,
+            })
+        };
+        let constant = Entity::ConstantCombinator {
+            id: 0,
+            position: Position { x: 0.5, y: 0.5 },
+            direction: model::Direction::North,
+            is_on: true,
+            connections: Vec::new(),
+            condition: Vec::new(),
+            tags: None,
+        };
+        let mut base = blueprint(vec![
+            constant,
+            unknown(1, "inserter", 2.5, None),
+            unknown(2, "modded-pole", 4.5, Some(vec![4])),
+            unknown(3, "modded-pole", 8.5, Some(vec![3])),
+        ]);
+        base.connect_wire(0, 1, Wire::Red).unwrap();
+        let ours = blueprint(base.entities.clone());
+        let mut theirs = blueprint(base.entities.clone());
+        theirs.tiles.push(model::Tile {
+            name: "landfill".into(),
+            position: Position { x: 4.0, y: 0.0 },
+        });
+
+        let merged = merge(&base, &ours, &theirs).unwrap();
+        assert!(diff::diff(&base, &merged).is_empty());
+        assert_eq!(1, merged.entities[1].wires().len());
+        match &merged.entities[3] {
+            Entity::Unknown(e) => assert_eq!(Some(vec![3]), e.neighbours),
+            entity => panic!("unexpected {:?}", entity),
+        }
+
+        if let Entity::Unknown(e) = &mut theirs.entities[2] {
+            e.neighbours = Some(vec![1, 4]);
+        }
+        let conflicts = merge(&base, &ours, &theirs).unwrap_err().conflicts;
+        assert_eq!(1, conflicts.len(), "{:?}", conflicts);
+        assert!(matches!(&conflicts[0], Conflict::InvalidWire { wire } if wire.colour == "copper"));
+    }
+}
@@ -1,6 +1,7 @@
 use serde::{Serialize, Deserialize};
 use serde_repr::{Serialize_repr, Deserialize_repr};
 use serde_with::skip_serializing_none;
+use std::str::FromStr;
 
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
 pub struct BlueprintContainer {
@@ -10,6 +11,10 @@ pub struct BlueprintContainer {
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
 pub struct Blueprint {
     pub entities: Vec<Entity>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tiles: Vec<Tile>,
+
     pub version: u64,
     pub item: Item,
     pub icons: Vec<Icon>,
@@ -23,7 +28,7 @@ pub enum Item {
     Blueprint,
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub struct Icon {
     pub index: u32,
     pub signal: Signal,
@@ -47,6 +52,20 @@ pub struct Entity {
 
     #[serde(default)]
     pub neighbours: Option<Vec<u32>>,
+
+    /// Arbitrary data attached to the entity, e.g. by mods.
+    #[serde(default)]
+    pub tags: Option<Tags>,
+}
+
+/// Tags of an entity by their name.
+pub type Tags = serde_json::Map<String, serde_json::Value>;
+
+/// Floor tile like concrete or landfill. Its position is the top left corner.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Tile {
+    pub name: String,
+    pub position: Position,
 }
 
 #[derive(Serialize_repr, Deserialize_repr, Clone, PartialEq, Debug)]
@@ -110,7 +129,7 @@ pub enum CircuitId {
 }
 
 #[skip_serializing_none]
-#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
 pub struct ControlBehavior {
     #[serde(default)]
     pub decider_conditions: Option<DeciderCondition>,
@@ -123,10 +142,17 @@ pub struct ControlBehavior {
 
     #[serde(default)]
     pub is_on: Option<bool>,
+
+    /// Condition of entities which are switched by the circuit network, e.g. lamps.
+    #[serde(default)]
+    pub circuit_condition: Option<CircuitCondition>,
+
+    #[serde(default)]
+    pub use_colors: Option<bool>,
 }
 
 
-#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
 pub struct Signal {
     pub name: String,
 
@@ -135,12 +161,55 @@ pub struct Signal {
 }
 
 
-#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
 pub enum SignalType {
     #[serde(rename = "virtual")]
     Virtual,
     #[serde(rename = "item")]
     Item,
+    #[serde(rename = "fluid")]
+    Fluid,
+}
+
+impl Signal {
+    pub fn virtual_signal(name: &str) -> Self {
+        Signal {
+            name: name.into(),
+            signal_type: SignalType::Virtual,
+        }
+    }
+
+    pub fn item(name: &str) -> Self {
+        Signal {
+            name: name.into(),
+            signal_type: SignalType::Item,
+        }
+    }
+
+    pub fn fluid(name: &str) -> Self {
+        Signal {
+            name: name.into(),
+            signal_type: SignalType::Fluid,
+        }
+    }
+}
+
+/// Parses `virtual:name`, `item:name` or `fluid:name`. Without a type, names starting with `signal-` are
+/// virtual signals and all others items.
+impl FromStr for Signal {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("virtual", name)) if !name.is_empty() => Ok(Signal::virtual_signal(name)),
+            Some(("item", name)) if !name.is_empty() => Ok(Signal::item(name)),
+            Some(("fluid", name)) if !name.is_empty() => Ok(Signal::fluid(name)),
+            Some(_) => Err(format!("invalid signal '{}', expected virtual:name, item:name or fluid:name", s)),
+            None if s.is_empty() => Err("empty signal name".into()),
+            None if s.starts_with("signal-") => Ok(Signal::virtual_signal(s)),
+            None => Ok(Signal::item(s)),
+        }
+    }
 }
 
 #[skip_serializing_none]
@@ -162,6 +231,21 @@ pub struct DeciderCondition {
     pub output_signal: Option<Signal>,
 }
 
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct CircuitCondition {
+    pub comparator: Comparator,
+
+    #[serde(default)]
+    pub constant: Option<i32>,
+
+    #[serde(default)]
+    pub first_signal: Option<Signal>,
+
+    #[serde(default)]
+    pub second_signal: Option<Signal>,
+}
+
 #[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Debug)]
 pub enum Comparator {
     /// Greater than (>)
@@ -263,6 +347,6 @@ pub enum Operation {
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub struct ConstantCondition {
     pub count: i32,
-    pub index: u8, // only values in range (0,21] are valid
+    pub index: u8, // only values in range [1,20] are valid, see abstract_model::validation
     pub signal: Signal,
 }
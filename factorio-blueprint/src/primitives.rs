@@ -0,0 +1,85 @@
+//! Small circuits that are used as building blocks by the generators, [`crate::ram`],
+//! [`crate::rom`] and the hardware description in [`crate::hdl`].
+
+use crate::{
+    abstract_model::Side,
+    template::{Selector, Template},
+};
+
+const MEMORY_CELL: &str = "0eNrNVsFu2zAM/RceB7uIZHvdDOwndtlhKAzHZlsClmTQUrAg8L+Psrc0SZvASYF1lwQSxcdHvgfCO1h3AXsm66HcATXODlD+3MFAT7bu4p3f9gglkEcDCdjaxFOLDbXIaePMmmztHcOYANkWf0GpxocE0HryhDPadNhWNpg1sjy4hJNA7wZJdTZWF7hU5wls5T/Td4VUaYmxmeM6AWHs2XXVGp/rDUm+JP1BrSTWTkhDvH0kHnz1qrENsQ9ys+c0v0hXsaM4D1/H4aziwfQ1TyRL+CYJLvg+XAH5xIh2hu23wi5YXz2yMxVZwYHSc8BxrmrnFifiKv7MuQfDpHYaZEPcBPLTUY/JUTg/DquTcHGa/SC1dSzG2J6W0qdYovESTvJwHA8K//WAvtID+iM88P3YA+qDPfCGLK8ccE6W7KK62RmVsj19gy0Fk2InpJiatHcdvqXT6q44UmphH3ppH/oqT59zX77vq2byzwa9tLRsCamFBnwBfp8H94YZMOJUL3ZMRXzXo7hx4gGfbrfje7aOuuishYIUN64D9S/XwY//fh3kN6yD5VtcwKbPgPLgqyGBDfIwj/6Lyu+/6vtCFSr7vBrH390R3ds=";
+const LOADER_CELL: &str = "0eNqVk91qwzAMhd9F126Zs6bZDHuOXYwS8qO2gkQOjlwWSt59djxKGV233gRkW+ccf3LOUHceB0csYM5AjeURzMcZRjpw1cU1mQYEAyTYgwKu+li12FCLbtXYviauxDqYFRC3+AlGz+pPgWgkFctthWzeKUAWEsKUZymmkn1fowsWd4UUDHYMvZajf9BbZZvtOlcwgSn0Og9GLTls0olMRRFxtitrPFYnCgqhbU+doPuFxomc+LByiZFOrA4OkeNFGusjUl1cUdkt68zJeIxyOn5S09UtqU2pyDWeZClj9xy5/gCR3RvJLQ4PUvhWLcNeS5fYe3KjlP+m8p6IpEEtwwshh8otIQ28hQbrZfAPSOIJ3SRH4kPSHqZyQV7une1L4iAGRpzH+RHoiXJ4e8tjNVc/h4JgOCZSL3pTvGZFrnP9vH2a5y806h5c";
+const CLOCK: &str = "0eNqlk9tOwzAMht/F1xmiZQeIxHNwgVDVg7tZtEmVOhPV1HfHaTZUwdiGuImUOP78239ygKLx2DkyDPoAVFrTg349QE9bkzfhjIcOQQMxtqDA5G3YVVhShW5R2rYgk7N1MCogU+EH6GRUVwGhEOeGzxPS8U0BGiYmjHqmzZAZ3xbopMQlJQo620uqNaG84Bbpcq1gAL3e3K2kSkUOyxhPVZDCzjZZgbt8T5IvSUdqJrFqIvXhtCbXc/ajsz059nLypSneWJQ7LN9DV6dmJ90itMvdJFTDsyRZz53/A/YlIrtB1HnDWe1sm5ERBmh2HsdY0cQWJ+FJWLYO0cyHSVUURK70xNM2DdbNwmKE0NJb0xO5Ps4QJ7vSi76f80uM+ptjNTWM7pfHe2WU/mjNxfd7g8vfONMrpj4Lquu86fEfxsTJBl74Rnr2bRXspe84msdkuXlKN6tklTys78fxE0HyUOw=";
+
+/// Parts of a [`memory_cell`]. All cells of a memory share their bus at the pole: write
+/// address (`signal-W`), read address (`signal-R`) and data (`signal-green`) on green, the data
+/// of read cells on red.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum MemoryCell {
+    /// Decider which holds the value.
+    Latch,
+    /// Decider which outputs the value while `signal-R` matches the address.
+    Read,
+    Pole,
+    /// Arithmetic combinator which clears the old value during a write.
+    Invert,
+    /// Decider which passes the new value while `signal-W` matches the address.
+    Write,
+}
+
+/// Parts of a [`loader_cell`].
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum LoaderCell {
+    /// Constant combinator with the value.
+    Data,
+    Decider,
+}
+
+/// Parts of a [`clock`].
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Clock {
+    Counter,
+    /// Output of the counter.
+    Output,
+    Constant,
+}
+
+fn reads(signal: &str) -> Selector {
+    Selector::Reads(signal.into())
+}
+
+fn named(name: &str) -> Selector {
+    Selector::Named(name.into())
+}
+
+/// Memory cell which stores `signal-green`. It is written when `signal-W` on the bus matches
+/// its address for exactly one tick and outputs its value while `signal-R` matches.
+/// The address is the constant of the [`MemoryCell::Read`] and [`MemoryCell::Write`] deciders.
+pub fn memory_cell() -> Template<MemoryCell> {
+    Template::from_blueprint_string(MEMORY_CELL)
+        .and_then(|t| t.bind(MemoryCell::Latch, reads("signal-0")))
+        .and_then(|t| t.bind(MemoryCell::Read, reads("signal-R")))
+        .and_then(|t| t.bind(MemoryCell::Pole, named("medium-electric-pole")))
+        .and_then(|t| t.bind(MemoryCell::Invert, named("arithmetic-combinator")))
+        .and_then(|t| t.bind(MemoryCell::Write, reads("signal-W")))
+        .expect("bundled template is valid")
+}
+
+/// Decider combinator which outputs `signal-W` and the value of its constant combinator
+/// (on `signal-green`) while `signal-W` at its input equals its address.
+pub fn loader_cell() -> Template<LoaderCell> {
+    Template::from_blueprint_string(LOADER_CELL)
+        .and_then(|t| t.bind(LoaderCell::Data, named("constant-combinator")))
+        .and_then(|t| t.bind(LoaderCell::Decider, reads("signal-W")))
+        .expect("bundled template is valid")
+}
+
+/// Counter that increments `signal-W` by one every tick while its constant combinator is on.
+/// The counter feeds its output back into itself and keeps counting as long as `signal-check`
+/// is 1. The constant combinator is switched off.
+pub fn clock() -> Template<Clock> {
+    Template::from_blueprint_string(CLOCK)
+        .and_then(|t| t.bind(Clock::Counter, named("decider-combinator")))
+        .and_then(|t| t.bind_connector(Clock::Output, named("decider-combinator"), Side::Two))
+        .and_then(|t| t.bind(Clock::Constant, named("constant-combinator")))
+        .expect("bundled template is valid")
+}
@@ -0,0 +1,548 @@
+//! Finding and editing entities with a small selector language.
+//!
+//! A selector is a list of conditions like `name=decider-combinator and
+//! condition.first_signal=signal-R`. Conditions are joined with `and`, which binds stronger
+//! than `or`. Each condition compares a path with a value using `=`, `!=`, `<`, `<=`, `>` or
+//! `>=`.
+//!
+//! Paths refer to the fields of an entity in the blueprint json. The fields of the control
+//! behaviour are moved up one level and the conditions of combinators and lamps are all
+//! called `condition`, e.g. `condition.constant` or `filters.count`. A path through a list
+//! without an index matches every element. Signals are compared by their name.
+//!
+//! Assignments like `condition.constant=7` use the same paths, see [`set`]. The number, name
+//! and links of an entity cannot be assigned, as that could break the wires or skip the checks
+//! of [`replace_entity`](crate::abstract_model::Blueprint::replace_entity). Entities which are
+//! moved or turned must not overlap others. Signals are assigned like `fluid:water`, see
+//! [`Signal::from_str`].
+
+use std::{error::Error, fmt, str::FromStr};
+
+use serde_json::{Map, Value};
+
+use crate::{
+    abstract_model::{
+        validation::{Diagnostic, CHECKS},
+        Blueprint,
+    },
+    diff::json,
+    model::{self, Signal},
+};
+
+#[derive(Debug, PartialEq)]
+pub enum QueryError {
+    Syntax(String),
+
+    /// An assignment made the entity with this number invalid.
+    InvalidEntity {
+        entity: u32,
+        reason: String,
+    },
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Syntax(reason) => write!(f, "invalid selector: {}", reason),
+            Self::InvalidEntity { entity, reason } => {
+                write!(f, "entity {} became invalid: {}", entity, reason)
+            }
+        }
+    }
+}
+
+impl Error for QueryError {}
+
+pub type Result<T> = core::result::Result<T, QueryError>;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum Operator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+struct Condition {
+    path: Vec<String>,
+    operator: Operator,
+    value: String,
+}
+
+/// Entities which fulfil all conditions of one of the alternatives.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Selector {
+    alternatives: Vec<Vec<Condition>>,
+}
+
+/// Sets the field at the path to a value, e.g. `condition.constant=7`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Assignment {
+    path: Vec<String>,
+    value: Value,
+}
+
+const OPERATORS: [(&str, Operator); 6] = [
+    ("!=", Operator::Ne),
+    ("<=", Operator::Le),
+    (">=", Operator::Ge),
+    ("=", Operator::Eq),
+    ("<", Operator::Lt),
+    (">", Operator::Gt),
+];
+
+/// Fields which cannot be assigned, see the module docs.
+const FIXED: [&str; 4] = ["entity_number", "name", "neighbours", "connections"];
+
+/// Fields of the control behaviour which hold the condition of an entity.
+const CONDITIONS: [&str; 3] = [
+    "decider_conditions",
+    "arithmetic_conditions",
+    "circuit_condition",
+];
+
+fn path(path: &str) -> Result<Vec<String>> {
+    let path: Vec<String> = path.split('.').map(String::from).collect();
+    match path.iter().any(String::is_empty) {
+        true => Err(QueryError::Syntax(format!(
+            "empty field in path '{}'",
+            path.join(".")
+        ))),
+        false => Ok(path),
+    }
+}
+
+impl FromStr for Condition {
+    type Err = QueryError;
+
+    fn from_str(condition: &str) -> Result<Self> {
+        let (start, symbol, operator) = OPERATORS
+            .iter()
+            .filter_map(|&(symbol, operator)| Some((condition.find(symbol)?, symbol, operator)))
+            .min_by_key(|&(start, symbol, _)| (start, usize::MAX - symbol.len()))
+            .ok_or_else(|| QueryError::Syntax(format!("'{}' has no operator", condition)))?;
+
+        Ok(Condition {
+            path: path(&condition[..start])?,
+            operator,
+            value: condition[start + symbol.len()..].into(),
+        })
+    }
+}
+
+impl FromStr for Selector {
+    type Err = QueryError;
+
+    fn from_str(selector: &str) -> Result<Self> {
+        let mut alternatives = vec![Vec::new()];
+        // Also set at the start, which has no condition to join with
+        let mut joined = true;
+        for token in selector.split_whitespace() {
+            match token {
+                "and" | "or" if joined => {
+                    return Err(QueryError::Syntax(format!(
+                        "'{}' without a condition",
+                        token
+                    )))
+                }
+                "and" => joined = true,
+                "or" => {
+                    alternatives.push(Vec::new());
+                    joined = true;
+                }
+                _ => {
+                    let conditions = alternatives.last_mut().unwrap();
+                    if !conditions.is_empty() && !joined {
+                        return Err(QueryError::Syntax(format!(
+                            "missing 'and' or 'or' before '{}'",
+                            token
+                        )));
+                    }
+                    conditions.push(token.parse()?);
+                    joined = false;
+                }
+            }
+        }
+
+        match alternatives.iter().any(Vec::is_empty) {
+            true => Err(QueryError::Syntax("missing condition".into())),
+            false => Ok(Selector { alternatives }),
+        }
+    }
+}
+
+impl FromStr for Assignment {
+    type Err = QueryError;
+
+    fn from_str(assignment: &str) -> Result<Self> {
+        let (path_part, value) = assignment
+            .split_once('=')
+            .ok_or_else(|| QueryError::Syntax(format!("'{}' has no '='", assignment)))?;
+        let value = match value {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            "null" => Value::Null,
+            _ => match (value.parse::<i64>(), value.parse::<f64>()) {
+                (Ok(integer), _) => Value::from(integer),
+                (_, Ok(number)) => Value::from(number),
+                _ => Value::from(value),
+            },
+        };
+        let path = path(path_part)?;
+        if FIXED.contains(&path[0].as_str()) {
+            return Err(QueryError::Syntax(format!(
+                "'{}' cannot be assigned",
+                path[0]
+            )));
+        }
+        let value = match value {
+            Value::String(name) if path.last().is_some_and(|f| f.ends_with("signal")) => {
+                json(&name.parse::<Signal>().map_err(QueryError::Syntax)?)
+            }
+            value => value,
+        };
+        Ok(Assignment { path, value })
+    }
+}
+
+impl Selector {
+    pub fn matches(&self, entity: &model::Entity) -> bool {
+        let view = view(entity);
+        self.alternatives
+            .iter()
+            .any(|conditions| conditions.iter().all(|c| c.matches(&view)))
+    }
+}
+
+impl Condition {
+    fn matches(&self, view: &Value) -> bool {
+        let mut values = Vec::new();
+        lookup(view, &self.path, &mut values);
+        match self.operator {
+            Operator::Ne => !values.iter().any(|v| equals(v, &self.value)),
+            Operator::Eq => values.iter().any(|v| equals(v, &self.value)),
+            operator => {
+                let Ok(expected) = self.value.parse::<f64>() else {
+                    return false;
+                };
+                values
+                    .iter()
+                    .filter_map(|v| v.as_f64())
+                    .any(|v| match operator {
+                        Operator::Lt => v < expected,
+                        Operator::Le => v <= expected,
+                        Operator::Gt => v > expected,
+                        _ => v >= expected,
+                    })
+            }
+        }
+    }
+}
+
+/// Entity as it is seen by paths: the json of the model with the control behaviour moved up.
+pub fn view(entity: &model::Entity) -> Value {
+    let mut view = match json(entity) {
+        Value::Object(view) => view,
+        _ => Map::new(),
+    };
+    if let Some(Value::Object(control_behavior)) = view.remove("control_behavior") {
+        for (key, value) in control_behavior {
+            match CONDITIONS.contains(&key.as_str()) {
+                true => view.insert("condition".into(), value),
+                false => view.insert(key, value),
+            };
+        }
+    }
+    Value::Object(view)
+}
+
+/// Reverse of [`view`], `original` decides where the condition is stored.
+fn entity_of(view: Value, original: &model::Entity) -> Result<model::Entity> {
+    let Value::Object(mut view) = view else {
+        unreachable!("views are objects");
+    };
+    let condition_key = match (&original.control_behavior, original.name.as_str()) {
+        (Some(c), _) if c.decider_conditions.is_some() => "decider_conditions",
+        (Some(c), _) if c.arithmetic_conditions.is_some() => "arithmetic_conditions",
+        (_, "decider-combinator") => "decider_conditions",
+        (_, "arithmetic-combinator") => "arithmetic_conditions",
+        _ => "circuit_condition",
+    };
+
+    let mut control_behavior = Map::new();
+    for key in ["condition", "filters", "is_on", "use_colors"] {
+        if let Some(value) = view.remove(key).filter(|v| !v.is_null()) {
+            let key = if key == "condition" {
+                condition_key
+            } else {
+                key
+            };
+            control_behavior.insert(key.into(), value);
+        }
+    }
+    if !control_behavior.is_empty() {
+        view.insert("control_behavior".into(), Value::Object(control_behavior));
+    }
+
+    serde_json::from_value(Value::Object(view)).map_err(|e| QueryError::InvalidEntity {
+        entity: original.entity_number,
+        reason: e.to_string(),
+    })
+}
+
+/// Collects the values at the path, lists without an index contribute all their elements.
+fn lookup<'a>(value: &'a Value, path: &[String], values: &mut Vec<&'a Value>) {
+    let Some((field, rest)) = path.split_first() else {
+        values.push(value);
+        return;
+    };
+    match value {
+        Value::Object(map) => {
+            if let Some(value) = map.get(field) {
+                lookup(value, rest, values);
+            }
+        }
+        Value::Array(list) => match field.parse::<usize>() {
+            Ok(index) => {
+                if let Some(value) = list.get(index) {
+                    lookup(value, rest, values);
+                }
+            }
+            Err(_) => {
+                for value in list {
+                    lookup(value, path, values);
+                }
+            }
+        },
+        _ => {}
+    }
+}
+
+/// Compares with a value of a condition, signals by their name.
+fn equals(value: &Value, expected: &str) -> bool {
+    match value {
+        Value::String(s) => s == expected,
+        Value::Number(n) => expected.parse::<f64>().ok() == n.as_f64(),
+        Value::Bool(b) => expected.parse::<bool>().ok() == Some(*b),
+        Value::Object(map) => map.get("name").and_then(Value::as_str) == Some(expected),
+        _ => expected == "null",
+    }
+}
+
+/// Sets the path inside of `target`, creating missing objects. Strings assigned to fields
+/// that end with `signal` become signals, `signal-…` virtual ones and everything else items.
+fn assign(target: &mut Value, path: &[String], value: &Value) {
+    let Some((field, rest)) = path.split_first() else {
+        *target = value.clone();
+        return;
+    };
+    if let Value::Array(list) = target {
+        match field.parse::<usize>() {
+            Ok(index) => {
+                if let Some(element) = list.get_mut(index) {
+                    assign(element, rest, value);
+                }
+            }
+            Err(_) => list.iter_mut().for_each(|e| assign(e, path, value)),
+        }
+        return;
+    }
+
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    let Value::Object(map) = target else {
+        unreachable!();
+    };
+    assign(map.entry(field.clone()).or_insert(Value::Null), rest, value);
+}
+
+/// Entity numbers of the matching entities.
+pub fn select(blueprint: &model::Blueprint, selector: &Selector) -> Vec<u32> {
+    blueprint
+        .entities
+        .iter()
+        .filter(|e| selector.matches(e))
+        .map(|e| e.entity_number)
+        .collect()
+}
+
+/// Applies the assignments to all matching entities and returns their entity numbers.
+/// Nothing is changed if one of the entities would become invalid or overlap another.
+pub fn set(
+    blueprint: &mut model::Blueprint,
+    selector: &Selector,
+    assignments: &[Assignment],
+) -> Result<Vec<u32>> {
+    let mut changed = Vec::new();
+    for (i, entity) in blueprint.entities.iter().enumerate() {
+        if selector.matches(entity) {
+            let mut view = view(entity);
+            for a in assignments {
+                assign(&mut view, &a.path, &a.value);
+            }
+            changed.push((i, entity_of(view, entity)?));
+        }
+    }
+
+    let numbers = changed.iter().map(|(_, e)| e.entity_number).collect();
+    let mut entities = blueprint.entities.clone();
+    for (i, entity) in changed {
+        entities[i] = entity;
+    }
+    let before = overlaps(&blueprint.entities);
+    if let Some(overlap) = overlaps(&entities)
+        .into_iter()
+        .find(|d| !before.contains(d))
+    {
+        return Err(QueryError::InvalidEntity {
+            entity: overlap.entity.unwrap_or_default(),
+            reason: overlap.message,
+        });
+    }
+    blueprint.entities = entities;
+    Ok(numbers)
+}
+
+/// Diagnostics of the overlap check, empty if the entities cannot be converted.
+fn overlaps(entities: &[model::Entity]) -> Vec<Diagnostic> {
+    let container = model::BlueprintContainer {
+        blueprint: model::Blueprint {
+            entities: entities.to_vec(),
+            tiles: Vec::new(),
+            version: 0,
+            item: model::Item::Blueprint,
+            icons: Vec::new(),
+        },
+    };
+    let Ok(blueprint) = Blueprint::try_from_raw(container) else {
+        return Vec::new();
+    };
+    let mut diagnostics = Vec::new();
+    for (_, check) in CHECKS.iter().filter(|(name, _)| *name == "overlap") {
+        check(&blueprint, &mut diagnostics);
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{abstract_model::Blueprint, model::BlueprintContainer, primitives};
+
+    fn raw_model() -> model::Blueprint {
+        let template = primitives::memory_cell();
+        let blueprint = template.blueprint();
+        BlueprintContainer::from(Blueprint::new(blueprint.entities.clone())).blueprint
+    }
+
+    #[test]
+    fn selectors_are_parsed_and_matched() {
+        let blueprint = raw_model();
+        let deciders: Selector = "name=decider-combinator".parse().unwrap();
+        let reading: Selector = "name=decider-combinator and condition.first_signal=signal-R"
+            .parse()
+            .unwrap();
+        let either: Selector = "condition.first_signal=signal-R or name!=decider-combinator"
+            .parse()
+            .unwrap();
+
+        let all = select(&blueprint, &deciders);
+        let read = select(&blueprint, &reading);
+        assert!(all.len() > read.len() && read.len() == 1);
+        assert!(select(&blueprint, &either).contains(&read[0]));
+        assert!(select(&blueprint, &either).len() < blueprint.entities.len());
+        assert_eq!(
+            blueprint.entities.len(),
+            select(&blueprint, &"position.x>=-1000".parse().unwrap()).len()
+        );
+
+        for invalid in [
+            "",
+            "name",
+            "and name=x",
+            "name=x name=y",
+            "name=x or",
+            ".x=1",
+        ] {
+            assert!(
+                matches!(invalid.parse::<Selector>(), Err(QueryError::Syntax(_))),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn assignments_change_matching_entities() {
+        let mut blueprint = raw_model();
+        let selector: Selector = "condition.first_signal=signal-R".parse().unwrap();
+        let assignments: Vec<Assignment> =
+            ["condition.constant=42", "condition.first_signal=signal-A"]
+                .iter()
+                .map(|a| a.parse().unwrap())
+                .collect();
+
+        let changed = set(&mut blueprint, &selector, &assignments).unwrap();
+        assert_eq!(1, changed.len());
+        assert!(select(&blueprint, &selector).is_empty());
+        let moved: Selector = "condition.first_signal=signal-A and condition.constant=42"
+            .parse()
+            .unwrap();
+        assert_eq!(changed, select(&blueprint, &moved));
+        let decider = &blueprint.entities[changed[0] as usize - 1];
+        let condition = decider.control_behavior.as_ref().unwrap();
+        assert_eq!(
+            Some(Signal::virtual_signal("signal-A")),
+            condition.decider_conditions.as_ref().unwrap().first_signal
+        );
+
+        let water = ["condition.first_signal=fluid:water".parse().unwrap()];
+        set(&mut blueprint, &moved, &water).unwrap();
+        let decider = &blueprint.entities[changed[0] as usize - 1];
+        let condition = decider.control_behavior.as_ref().unwrap();
+        assert_eq!(
+            Some(Signal::fluid("water")),
+            condition.decider_conditions.as_ref().unwrap().first_signal
+        );
+        assert!(matches!(
+            "condition.first_signal=liquid:water".parse::<Assignment>(),
+            Err(QueryError::Syntax(_))
+        ));
+        let moved: Selector = "condition.first_signal=water".parse().unwrap();
+
+        let invalid = ["condition.comparator=maybe".parse().unwrap()];
+        assert!(matches!(
+            set(&mut blueprint, &moved, &invalid),
+            Err(QueryError::InvalidEntity { .. })
+        ));
+        assert_eq!(changed, select(&blueprint, &moved));
+
+        let first: Selector = "entity_number=1".parse().unwrap();
+        let onto = &blueprint.entities[1].position;
+        let moved = [
+            format!("position.x={}", onto.x).parse().unwrap(),
+            format!("position.y={}", onto.y).parse().unwrap(),
+        ];
+        assert!(matches!(
+            set(&mut blueprint, &first, &moved),
+            Err(QueryError::InvalidEntity { .. })
+        ));
+        let away = ["position.y=-10".parse().unwrap()];
+        assert_eq!(vec![1], set(&mut blueprint, &first, &away).unwrap());
+
+        for fixed in [
+            "name=constant-combinator",
+            "entity_number=1",
+            "connections=null",
+        ] {
+            assert!(matches!(
+                fixed.parse::<Assignment>(),
+                Err(QueryError::Syntax(_))
+            ));
+        }
+    }
+}
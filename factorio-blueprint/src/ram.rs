@@ -0,0 +1,327 @@
+//! Random access memory made of memory cells, see [`primitives::memory_cell`].
+//!
+//! All cells share the bus at [`Ram::bus`]. A word is written by putting the write address and
+//! the word on the green wire for exactly one tick. While the read address is on the green
+//! wire, the word at that address is put on the red wire. Words of several signals are stored
+//! in one cell per signal, which share the address.
+
+use std::{collections::BTreeSet, error::Error, fmt};
+
+use crate::{
+    abstract_model::{
+        utility::{Axis, GridCell, UtilityError},
+        Blueprint, Connector, Entity, Side, Wire,
+    },
+    model::Signal,
+    primitives::{self, MemoryCell},
+};
+
+/// Signals with a fixed meaning inside of the memory cells or the combinators.
+const RESERVED: [&str; 4] = [
+    "signal-0",
+    "signal-everything",
+    "signal-anything",
+    "signal-each",
+];
+
+#[derive(Debug, PartialEq)]
+pub enum RamError {
+    InvalidSize,
+    NoDataSignal,
+    DuplicateSignal(String),
+    ReservedSignal(String),
+    AddressOverflow,
+    Layout(UtilityError),
+}
+
+impl fmt::Display for RamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidSize => write!(f, "width and height must be at least 1"),
+            Self::NoDataSignal => write!(f, "no data signal given"),
+            Self::DuplicateSignal(name) => write!(f, "signal '{}' is used twice", name),
+            Self::ReservedSignal(name) => write!(f, "signal '{}' is reserved", name),
+            Self::AddressOverflow => write!(f, "addresses do not fit into a signal"),
+            Self::Layout(cause) => write!(f, "could not lay out the memory: {}", cause),
+        }
+    }
+}
+
+impl Error for RamError {}
+
+impl From<UtilityError> for RamError {
+    fn from(e: UtilityError) -> Self {
+        RamError::Layout(e)
+    }
+}
+
+pub type Result<T> = core::result::Result<T, RamError>;
+
+/// Size, signals and addresses of a memory.
+#[derive(Clone, Debug)]
+pub struct RamOptions {
+    /// Columns of words.
+    pub width: usize,
+
+    /// Words per column.
+    pub height: usize,
+
+    pub read_address: Signal,
+    pub write_address: Signal,
+
+    /// Address of the first word. The word in column `col` and row `row` has the address
+    /// `base + col * height + row`.
+    pub base: i32,
+
+    /// Signals of a word.
+    pub data: Vec<Signal>,
+}
+
+impl Default for RamOptions {
+    /// A single column of 16 words on `signal-green`, read with `signal-R` and written with
+    /// `signal-W`, starting at address 1.
+    fn default() -> Self {
+        RamOptions {
+            width: 1,
+            height: 16,
+            read_address: Signal::virtual_signal("signal-R"),
+            write_address: Signal::virtual_signal("signal-W"),
+            base: 1,
+            data: vec![Signal::virtual_signal("signal-green")],
+        }
+    }
+}
+
+/// Where a word is stored.
+#[derive(Clone, PartialEq, Debug)]
+pub struct WordLocation {
+    pub address: i32,
+    pub col: usize,
+    pub row: usize,
+
+    /// Ids of the cell entities per data signal, in the order of the [`primitives::memory_cell`]
+    /// template.
+    pub cells: Vec<Vec<usize>>,
+}
+
+/// Generated memory and its bus.
+#[derive(Debug)]
+pub struct Ram {
+    pub blueprint: Blueprint,
+
+    /// Pole that joins the bus of all cells.
+    pub bus: Connector,
+
+    /// All words ordered by address.
+    pub address_map: Vec<WordLocation>,
+}
+
+impl Ram {
+    pub fn word(&self, address: i32) -> Option<&WordLocation> {
+        self.address_map.iter().find(|w| w.address == address)
+    }
+}
+
+/// Generates a memory, see [`RamOptions`]. The cells of the signals of a word are placed next
+/// to each other, so there are `width * data.len()` columns of cells.
+pub fn ram(options: &RamOptions) -> Result<Ram> {
+    check_options(options)?;
+    let lanes = options.data.len();
+    let height = options.height;
+
+    let template = primitives::memory_cell();
+    let mut blueprint = Blueprint {
+        version: template.blueprint().version,
+        ..Blueprint::default()
+    };
+    let memory_cell_ids = template.instantiate(&mut blueprint, 0.0, 0.0).ids;
+    let (dx, dy) = template.size();
+    let part = |cell: &GridCell, part: MemoryCell| cell.ids[template.id(part)];
+
+    let address = |col: usize, row: usize| options.base + (col / lanes * height + row) as i32;
+
+    // Update read and write address and the signal of the cell
+    let mut customise = |blueprint: &mut Blueprint, cell: GridCell| {
+        let data = &options.data[cell.col % lanes];
+        let address = address(cell.col, cell.row);
+        let deciders = [
+            (MemoryCell::Latch, None),
+            (MemoryCell::Read, Some(&options.read_address)),
+            (MemoryCell::Write, Some(&options.write_address)),
+        ];
+        for (decider, selector) in deciders {
+            if let Entity::DeciderCombinator { condition, .. } =
+                &mut blueprint.entities[part(&cell, decider)]
+            {
+                if let Some(selector) = selector {
+                    condition.first_signal = Some(selector.clone());
+                    condition.constant = Some(address);
+                }
+                condition.output_signal = Some(data.clone());
+            }
+        }
+        if let Entity::ArithmeticCombinator { condition, .. } =
+            &mut blueprint.entities[part(&cell, MemoryCell::Invert)]
+        {
+            condition.first_signal = Some(data.clone());
+            condition.output_signal = Some(data.clone());
+        }
+        Ok(())
+    };
+
+    // Chain cells of a column and connect the columns along the top row
+    let mut connect_cells =
+        |blueprint: &mut Blueprint, axis: Axis, from: GridCell, to: GridCell| {
+            if axis == Axis::Horizontal && to.row != 0 {
+                return Ok(());
+            }
+            let (id1, id2) = (part(&from, MemoryCell::Pole), part(&to, MemoryCell::Pole));
+            blueprint.connect_electric_poles(id1, id2)?;
+            blueprint.connect_wire(id1, id2, Wire::Green)?;
+            blueprint.connect_wire(id1, id2, Wire::Red)
+        };
+
+    let grid = blueprint.replicate(
+        &memory_cell_ids,
+        options.width * lanes,
+        height,
+        dx as f32,
+        dy as f32,
+        Some(&mut connect_cells),
+        Some(&mut customise),
+    )?;
+
+    let address_map = (0..options.width)
+        .flat_map(|col| (0..height).map(move |row| (col, row)))
+        .map(|(col, row)| WordLocation {
+            address: address(col * lanes, row),
+            col,
+            row,
+            cells: (0..lanes)
+                .map(|lane| grid[col * lanes + lane][row].clone())
+                .collect(),
+        })
+        .collect();
+
+    Ok(Ram {
+        blueprint,
+        bus: Connector {
+            id: grid[0][0][template.id(MemoryCell::Pole)],
+            side: Side::One,
+        },
+        address_map,
+    })
+}
+
+fn check_options(options: &RamOptions) -> Result<()> {
+    if options.width == 0 || options.height == 0 {
+        return Err(RamError::InvalidSize);
+    }
+    if options.data.is_empty() {
+        return Err(RamError::NoDataSignal);
+    }
+
+    let last = options
+        .width
+        .checked_mul(options.height)
+        .and_then(|words| i32::try_from(words - 1).ok())
+        .and_then(|offset| options.base.checked_add(offset));
+    if last.is_none() {
+        return Err(RamError::AddressOverflow);
+    }
+
+    let mut seen = BTreeSet::new();
+    let addresses = [&options.read_address, &options.write_address];
+    for signal in addresses.into_iter().chain(&options.data) {
+        if RESERVED.contains(&signal.name.as_str()) {
+            return Err(RamError::ReservedSignal(signal.name.clone()));
+        }
+        if !seen.insert(signal) {
+            return Err(RamError::DuplicateSignal(signal.name.clone()));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::harness::Harness;
+
+    fn s(name: &str) -> Signal {
+        Signal::virtual_signal(name)
+    }
+
+    #[test]
+    fn words_of_several_signals_are_written_and_read() {
+        let options = RamOptions {
+            width: 2,
+            height: 3,
+            read_address: s("signal-P"),
+            write_address: s("signal-Q"),
+            base: 10,
+            data: vec![s("signal-A"), s("signal-B")],
+        };
+        let ram = ram(&options).unwrap();
+        assert!(ram.blueprint.check_wire_reach().is_empty());
+        assert_eq!(6, ram.address_map.len());
+        let word = ram.word(14).unwrap();
+        assert_eq!((1, 1, 2), (word.col, word.row, word.cells.len()));
+
+        let mut harness = Harness::new(&ram.blueprint);
+        harness.port("bus", ram.bus.clone(), Wire::Green).unwrap();
+        harness.port("out", ram.bus.clone(), Wire::Red).unwrap();
+
+        let value = |address: i32| (address * 100, -address);
+        for address in 10..16 {
+            let (a, b) = value(address);
+            harness
+                .drive(
+                    "bus",
+                    &[
+                        (s("signal-Q"), address),
+                        (s("signal-A"), a),
+                        (s("signal-B"), b),
+                    ],
+                )
+                .unwrap();
+            harness.step(1);
+            harness.release("bus").unwrap();
+            harness.step(3);
+        }
+
+        for address in (10..16).rev() {
+            harness.drive("bus", &[(s("signal-P"), address)]).unwrap();
+            harness.step(1);
+            let (a, b) = value(address);
+            harness.expect("out", &s("signal-A"), a).unwrap();
+            harness.expect("out", &s("signal-B"), b).unwrap();
+            harness.release("bus").unwrap();
+            harness.step(1);
+        }
+    }
+
+    #[test]
+    fn ram_checks_options() {
+        let options = |data: &[&str]| RamOptions {
+            data: data.iter().map(|name| s(name)).collect(),
+            ..RamOptions::default()
+        };
+        assert_eq!(RamError::NoDataSignal, ram(&options(&[])).unwrap_err());
+        assert_eq!(
+            RamError::DuplicateSignal("signal-R".into()),
+            ram(&options(&["signal-R"])).unwrap_err()
+        );
+        assert_eq!(
+            RamError::ReservedSignal("signal-0".into()),
+            ram(&options(&["signal-0"])).unwrap_err()
+        );
+
+        let overflow = RamOptions {
+            base: i32::MAX - 10,
+            ..RamOptions::default()
+        };
+        assert_eq!(RamError::AddressOverflow, ram(&overflow).unwrap_err());
+    }
+}
@@ -0,0 +1,485 @@
+//! SVG and text previews of blueprints.
+//!
+//! Entities are drawn as their footprint with an arrow for the direction, wires as lines
+//! between their connection points. Hovering an entity shows its name, position and settings
+//! like the conditions of combinators. [`text`] shows the tiles of the entities as characters
+//! for terminals.
+
+use std::{collections::HashMap, fmt::Write};
+
+use serde::Serialize;
+
+use crate::{
+    abstract_model::{Blueprint, Entity, Side, Wire},
+    model::{Position, Signal},
+};
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct RenderOptions {
+    /// Pixels per tile.
+    pub tile_size: u32,
+
+    /// Draws the floor tiles, e.g. concrete.
+    pub tiles: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            tile_size: 32,
+            tiles: true,
+        }
+    }
+}
+
+/// Empty tiles around the entities.
+const MARGIN: i32 = 1;
+
+/// Wires of different colours between the same connectors are drawn side by side.
+const WIRE_OFFSET: f32 = 0.06;
+
+/// Renders the blueprint as a standalone SVG document.
+pub fn render(blueprint: &Blueprint, options: &RenderOptions) -> String {
+    let (left, top, right, bottom) = bounds(blueprint, options);
+    let (width, height) = (right - left + 2 * MARGIN, bottom - top + 2 * MARGIN);
+    let size = options.tile_size as i32;
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="{} {} {} {}">"#,
+        width * size,
+        height * size,
+        left - MARGIN,
+        top - MARGIN,
+        width,
+        height
+    );
+    let _ = writeln!(
+        svg,
+        r##"<rect x="{}" y="{}" width="{}" height="{}" fill="#2b2b2b"/>"##,
+        left - MARGIN,
+        top - MARGIN,
+        width,
+        height
+    );
+
+    if options.tiles {
+        for tile in &blueprint.tiles {
+            let _ = writeln!(
+                svg,
+                r##"<rect x="{}" y="{}" width="1" height="1" fill="#4a4a4a"><title>{}</title></rect>"##,
+                tile.position.x,
+                tile.position.y,
+                escape(&tile.name)
+            );
+        }
+    }
+
+    for entity in &blueprint.entities {
+        render_entity(&mut svg, entity);
+    }
+    render_wires(&mut svg, blueprint);
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Symbols of the entities in text previews, other entities are numbered with lowercase
+/// letters and digits.
+const SYMBOLS: [(&str, char); 8] = [
+    ("decider-combinator", 'D'),
+    ("arithmetic-combinator", 'A'),
+    ("constant-combinator", 'C'),
+    ("small-electric-pole", 'P'),
+    ("medium-electric-pole", 'M'),
+    ("big-electric-pole", 'B'),
+    ("substation", 'S'),
+    ("small-lamp", 'L'),
+];
+
+const OTHER_SYMBOLS: &str = "abcdefghijklmnopqrstuvwxyz0123456789";
+
+/// Shows floor tiles in text previews.
+const FLOOR: char = '#';
+
+const EMPTY: char = '.';
+
+/// Renders the tiles of the blueprint as a grid of characters, one per tile, followed by a
+/// legend. With `colour`, entities on red or green networks are coloured with ANSI escape
+/// codes, entities on both are yellow.
+pub fn text(blueprint: &Blueprint, colour: bool) -> String {
+    let mut symbols: Vec<(String, char, usize)> = Vec::new();
+    let mut grid = HashMap::new();
+    for tile in &blueprint.tiles {
+        grid.insert(
+            (
+                tile.position.x.floor() as i32,
+                tile.position.y.floor() as i32,
+            ),
+            (FLOOR, None),
+        );
+    }
+    for entity in &blueprint.entities {
+        let symbol = match symbols.iter_mut().find(|(name, ..)| name == entity.name()) {
+            Some((_, symbol, count)) => {
+                *count += 1;
+                *symbol
+            }
+            None => {
+                let others = symbols
+                    .iter()
+                    .filter(|(name, ..)| !SYMBOLS.iter().any(|(n, _)| n == name))
+                    .count();
+                let symbol = SYMBOLS
+                    .iter()
+                    .find(|(name, _)| *name == entity.name())
+                    .map(|&(_, symbol)| symbol)
+                    .unwrap_or_else(|| OTHER_SYMBOLS.chars().nth(others).unwrap_or('?'));
+                symbols.push((entity.name().into(), symbol, 1));
+                symbol
+            }
+        };
+
+        let wires = entity.wires();
+        let ansi = match (
+            wires.iter().any(|c| c.wire == Wire::Red),
+            wires.iter().any(|c| c.wire == Wire::Green),
+        ) {
+            _ if !colour => None,
+            (true, true) => Some(33),
+            (true, false) => Some(31),
+            (false, true) => Some(32),
+            (false, false) => None,
+        };
+        for tile in entity.tiles() {
+            grid.insert(tile, (symbol, ansi));
+        }
+    }
+
+    let (left, top, right, bottom) = bounds(blueprint, &RenderOptions::default());
+    let mut text = String::new();
+    for y in top..bottom {
+        for x in left..right {
+            match grid.get(&(x, y)) {
+                Some((symbol, Some(ansi))) => {
+                    let _ = write!(text, "\x1b[{}m{}\x1b[0m", ansi, symbol);
+                }
+                Some((symbol, None)) => text.push(*symbol),
+                None => text.push(EMPTY),
+            }
+        }
+        text.push('\n');
+    }
+
+    text.push('\n');
+    for (name, symbol, count) in &symbols {
+        let _ = writeln!(text, "{} {} ({})", symbol, name, count);
+    }
+    if !blueprint.tiles.is_empty() {
+        let _ = writeln!(text, "{} floor tile ({})", FLOOR, blueprint.tiles.len());
+    }
+    if colour {
+        text.push_str(
+            "\x1b[31mred\x1b[0m, \x1b[32mgreen\x1b[0m or \x1b[33myellow\x1b[0m: \
+             on red, green or both wire networks\n",
+        );
+    }
+    text
+}
+
+/// Tiles covered by the entities (and floor tiles) as (left, top, right, bottom), the right
+/// and bottom edges are exclusive.
+fn bounds(blueprint: &Blueprint, options: &RenderOptions) -> (i32, i32, i32, i32) {
+    let entity_tiles = blueprint.entities.iter().flat_map(Entity::tiles);
+    let floor_tiles = blueprint
+        .tiles
+        .iter()
+        .filter(|_| options.tiles)
+        .map(|t| (t.position.x.floor() as i32, t.position.y.floor() as i32));
+
+    entity_tiles
+        .chain(floor_tiles)
+        .fold(None, |bounds, (x, y)| match bounds {
+            None => Some((x, y, x + 1, y + 1)),
+            Some((l, t, r, b)) => Some((l.min(x), t.min(y), r.max(x + 1), b.max(y + 1))),
+        })
+        .unwrap_or((0, 0, 0, 0))
+}
+
+fn render_entity(svg: &mut String, entity: &Entity) {
+    let (width, height) = entity.footprint().unwrap_or((1, 1));
+    let (left, top) = entity.top_left_tile();
+    let fill = match entity {
+        Entity::DeciderCombinator { .. } => "#8a6d3b",
+        Entity::ArithmeticCombinator { .. } => "#3b6d8a",
+        Entity::ConstantCombinator { .. } => "#6d6d6d",
+        Entity::ElectricPole { .. } => "#7a5230",
+        Entity::Lamp { .. } => "#c8c86e",
+        Entity::Unknown(_) => "#5a3b6d",
+    };
+
+    let _ = writeln!(svg, "<g><title>{}</title>", escape(&tooltip(entity)));
+    let _ = writeln!(
+        svg,
+        r##"<rect x="{}" y="{}" width="{}" height="{}" rx="0.1" fill="{}" stroke="#1a1a1a" stroke-width="0.05"/>"##,
+        left as f32 + 0.05,
+        top as f32 + 0.05,
+        width as f32 - 0.1,
+        height as f32 - 0.1,
+        fill
+    );
+    if let Some(direction) = entity.direction() {
+        let position = entity.position();
+        let _ = writeln!(
+            svg,
+            r##"<path d="M 0 -0.3 L 0.2 0.1 L -0.2 0.1 Z" fill="#e0e0e0" transform="translate({} {}) rotate({})"/>"##,
+            position.x,
+            position.y,
+            direction.clone() as u8 as u32 * 45
+        );
+    }
+    svg.push_str("</g>\n");
+}
+
+/// Circuit wires and copper cables, each drawn once.
+fn render_wires(svg: &mut String, blueprint: &Blueprint) {
+    for entity in &blueprint.entities {
+        for c in entity.wires() {
+            let Some(to) = blueprint.entities.get(c.to.id) else {
+                continue;
+            };
+            if (entity.id(), c.from_side) > (c.to.id, c.to.side) {
+                continue;
+            }
+            let (colour, offset) = match c.wire {
+                Wire::Red => ("#d03030", WIRE_OFFSET),
+                Wire::Green => ("#30b030", -WIRE_OFFSET),
+            };
+            line(
+                svg,
+                &entity.connection_point(c.from_side),
+                &to.connection_point(c.to.side),
+                offset,
+                colour,
+            );
+        }
+
+        for to in entity.neighbours().into_iter().filter(|&n| n > entity.id()) {
+            if let Some(to) = blueprint.entities.get(to) {
+                line(
+                    svg,
+                    &entity.connection_point(Side::One),
+                    &to.connection_point(Side::One),
+                    0.0,
+                    "#c87533",
+                );
+            }
+        }
+    }
+}
+
+fn line(svg: &mut String, from: &Position, to: &Position, offset: f32, colour: &str) {
+    let _ = writeln!(
+        svg,
+        r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{}" stroke-width="0.06" stroke-linecap="round"/>"#,
+        from.x + offset,
+        from.y + offset,
+        to.x + offset,
+        to.y + offset,
+        colour
+    );
+}
+
+/// Name, position and settings of the entity, one per line.
+fn tooltip(entity: &Entity) -> String {
+    let position = entity.position();
+    let mut lines = vec![format!(
+        "{} at ({}, {})",
+        entity.name(),
+        position.x,
+        position.y
+    )];
+
+    match entity {
+        Entity::DeciderCombinator { condition: c, .. } => {
+            let output = match c.copy_count_from_input {
+                true => "input count",
+                false => "1",
+            };
+            lines.push(format!(
+                "if {} {} {} then {} = {}",
+                operand(&c.first_signal, None),
+                symbol(&c.comparator),
+                operand(&c.second_signal, c.constant),
+                operand(&c.output_signal, None),
+                output
+            ));
+        }
+        Entity::ArithmeticCombinator { condition: c, .. } => lines.push(format!(
+            "{} = {} {} {}",
+            operand(&c.output_signal, None),
+            operand(&c.first_signal, c.first_constant),
+            symbol(&c.operation),
+            operand(&c.second_signal, c.second_constant)
+        )),
+        Entity::ConstantCombinator {
+            is_on, condition, ..
+        } => {
+            if !is_on {
+                lines.push("switched off".into());
+            }
+            lines.extend(
+                condition
+                    .iter()
+                    .map(|f| format!("{} = {}", f.signal.name, f.count)),
+            );
+        }
+        Entity::Lamp {
+            condition,
+            use_colors,
+            ..
+        } => {
+            lines.push(match condition {
+                Some(c) => format!(
+                    "on if {} {} {}",
+                    operand(&c.first_signal, None),
+                    symbol(&c.comparator),
+                    operand(&c.second_signal, c.constant)
+                ),
+                None => "always on".into(),
+            });
+            if *use_colors {
+                lines.push("uses colours".into());
+            }
+        }
+        Entity::ElectricPole { .. } | Entity::Unknown(_) => {}
+    }
+
+    lines.join("\n")
+}
+
+/// Name of the signal or the constant.
+fn operand(signal: &Option<Signal>, constant: Option<i32>) -> String {
+    match (signal, constant) {
+        (Some(signal), _) => signal.name.clone(),
+        (None, Some(constant)) => constant.to_string(),
+        (None, None) => "?".into(),
+    }
+}
+
+/// Symbol of a comparator or operation as it is stored in blueprints.
+fn symbol<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(symbol)) => symbol,
+        _ => "?".into(),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{abstract_model::PoleType, model, primitives};
+
+    #[test]
+    fn entities_wires_and_tooltips_are_drawn() {
+        let template = primitives::memory_cell();
+        let blueprint = template.blueprint();
+        let svg = render(blueprint, &RenderOptions::default());
+
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(blueprint.entities.len(), svg.matches("<g><title>").count());
+        assert!(svg.contains("<path d="));
+        assert!(svg.contains("stroke=\"#30b030\""));
+        assert!(svg.contains("stroke=\"#d03030\""));
+        assert!(svg.contains("decider-combinator at ("));
+        assert!(svg.contains("then "));
+    }
+
+    #[test]
+    fn text_shows_tiles_and_legend() {
+        let mut blueprint = Blueprint::new(primitives::loader_cell().blueprint().entities.clone());
+        blueprint.entities.push(Entity::ElectricPole {
+            id: 2,
+            pole_type: PoleType::Medium,
+            position: Position { x: 4.5, y: 0.5 },
+            neighbours: Vec::new(),
+            connections: Vec::new(),
+            tags: None,
+        });
+        let mut unknown: model::Entity = blueprint.entities[2].clone().into();
+        unknown.name = "iron-chest".into();
+        unknown.entity_number = 4;
+        unknown.position.y += 1.0;
+        blueprint.entities.push(Entity::Unknown(unknown));
+
+        let preview = text(&blueprint, false);
+        let lines: Vec<&str> = preview.lines().collect();
+        assert_eq!(vec!["CDD.M", "....a", ""], lines[..3]);
+        assert!(lines.contains(&"D decider-combinator (1)"));
+        assert!(lines.contains(&"a iron-chest (1)"));
+
+        let constant = blueprint
+            .entities
+            .iter()
+            .position(|e| e.name() == "constant-combinator")
+            .unwrap();
+        blueprint.connect_wire(3, constant, Wire::Red).unwrap();
+        let coloured = text(&blueprint, true);
+        assert!(coloured.starts_with("\x1b[33mC\x1b[0m"));
+        assert!(coloured.contains("\x1b[31ma\x1b[0m"));
+        assert!(coloured.contains("\x1b[31mred\x1b[0m"));
+    }
+
+    #[test]
+    fn links_of_unknown_entities_are_drawn() {
+        let unknown = |id: usize, name: &str, x: f32, neighbours: Option<Vec<u32>>| {
+            Entity::Unknown(model::Entity {
+                entity_number: id as u32 + 1,
+                name: name.into(),
+                position: Position { x, y: 0.5 },
+                direction: None,
+                connections: None,
+                control_behavior: None,
+                neighbours,
+                tags: None,
+            })
+        };
+        let mut blueprint = Blueprint::new(vec![
+            unknown(0, "inserter", 0.5, None),
+            unknown(1, "iron-chest", 2.5, None),
+            unknown(2, "power-switch", 5.0, Some(vec![4])),
+            unknown(3, "power-switch", 8.0, Some(vec![3])),
+        ]);
+        blueprint.connect_wire(0, 1, Wire::Red).unwrap();
+
+        let svg = render(&blueprint, &RenderOptions::default());
+        assert_eq!(2, svg.matches("<line").count());
+        assert_eq!(1, svg.matches("stroke=\"#d03030\"").count());
+        assert_eq!(1, svg.matches("stroke=\"#c87533\"").count());
+    }
+
+    #[test]
+    fn empty_blueprints_and_escaping() {
+        let blueprint = Blueprint::default();
+        let options = RenderOptions {
+            tile_size: 10,
+            tiles: true,
+        };
+        assert!(
+            render(&blueprint, &options).contains(r#"width="20" height="20" viewBox="-1 -1 2 2""#)
+        );
+        assert_eq!(
+            "a &lt; b &amp;&amp; &quot;c&quot;",
+            escape("a < b && \"c\"")
+        );
+    }
+}
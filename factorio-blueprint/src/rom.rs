@@ -0,0 +1,390 @@
+//! Read only memory made of constant combinators, e.g. to stream a program into a computer.
+//!
+//! Every cell is a constant combinator which holds one or more words and a decider combinator
+//! which passes them on while the address signal on its input equals the address of the cell.
+//! The cells are stacked into columns, all of them share the red address network at
+//! [`Rom::address`] and the green output network at [`Rom::output`]. The address signal is
+//! passed on as well, so a cell outputs its address together with its words.
+
+use std::{collections::BTreeSet, error::Error, fmt};
+
+use crate::{
+    abstract_model::{utility::UtilityError, Blueprint, Connector, Entity, PoleType, Side, Wire},
+    compiler,
+    model::{ConstantCondition, Signal},
+    primitives::{self, LoaderCell},
+};
+
+/// Number of filter slots of a constant combinator.
+const CONSTANT_SLOTS: usize = 20;
+
+#[derive(Debug, PartialEq)]
+pub enum RomError {
+    InvalidWordSize(usize),
+    InvalidHeight,
+    InvalidStride,
+    NoDataSignal,
+    TooManySignals(usize),
+    DuplicateSignal(String),
+    ReservedSignal(String),
+    AddressOverflow,
+    Empty,
+    Layout(UtilityError),
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidWordSize(size) => {
+                write!(f, "words must have 1 to 4 bytes, not {}", size)
+            }
+            Self::InvalidHeight => write!(f, "columns must hold at least one cell"),
+            Self::InvalidStride => write!(f, "the address stride must not be 0"),
+            Self::NoDataSignal => write!(f, "no data signal given"),
+            Self::TooManySignals(count) => write!(
+                f,
+                "{} data signals do not fit into a constant combinator with {} slots",
+                count, CONSTANT_SLOTS
+            ),
+            Self::DuplicateSignal(name) => write!(f, "signal '{}' is used twice", name),
+            Self::ReservedSignal(name) => write!(f, "signal '{}' is reserved", name),
+            Self::AddressOverflow => write!(f, "addresses do not fit into a signal"),
+            Self::Empty => write!(f, "cannot generate a rom without data"),
+            Self::Layout(cause) => write!(f, "could not lay out the rom: {}", cause),
+        }
+    }
+}
+
+impl Error for RomError {}
+
+impl From<UtilityError> for RomError {
+    fn from(e: UtilityError) -> Self {
+        RomError::Layout(e)
+    }
+}
+
+pub type Result<T> = core::result::Result<T, RomError>;
+
+/// Byte order of the words in the data.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Endianness {
+    Big,
+    Little,
+}
+
+/// How the data is split into words and how the cells are addressed.
+#[derive(Clone, Debug)]
+pub struct RomOptions {
+    /// Bytes per word, 1 to 4. Words with less than 4 bytes are not sign extended.
+    pub word_size: usize,
+    pub endianness: Endianness,
+
+    /// Signal which selects the cell.
+    pub address: Signal,
+
+    /// Signals of the words of a cell. Consecutive words are packed into the slots of one
+    /// constant combinator, one word per signal.
+    pub data: Vec<Signal>,
+
+    /// Address of the first cell.
+    pub base: i32,
+
+    /// Difference between the addresses of two consecutive cells.
+    pub stride: i32,
+
+    /// Cells per column.
+    pub height: usize,
+}
+
+impl Default for RomOptions {
+    /// Big endian 32 bit words, one per cell on `signal-green`, addressed by `signal-W`
+    /// starting at 1.
+    fn default() -> Self {
+        RomOptions {
+            word_size: 4,
+            endianness: Endianness::Big,
+            address: Signal::virtual_signal("signal-W"),
+            data: vec![Signal::virtual_signal("signal-green")],
+            base: 1,
+            stride: 1,
+            height: 32,
+        }
+    }
+}
+
+/// Generated rom and its networks.
+#[derive(Debug)]
+pub struct Rom {
+    pub blueprint: Blueprint,
+
+    /// Red network on which the address is expected.
+    pub address: Connector,
+
+    /// Green network on which the selected cell puts its words and address.
+    pub output: Connector,
+
+    pub cells: usize,
+}
+
+/// Splits the data into words of `word_size` bytes, padding the last word with zeros.
+pub fn words(data: &[u8], word_size: usize, endianness: Endianness) -> Result<Vec<i32>> {
+    if !(1..=4).contains(&word_size) {
+        return Err(RomError::InvalidWordSize(word_size));
+    }
+
+    let words = data
+        .chunks(word_size)
+        .map(|chunk| {
+            let mut word = [0u8; 4];
+            word[..chunk.len()].copy_from_slice(chunk);
+            let word = &mut word[..word_size];
+            if endianness == Endianness::Little {
+                word.reverse();
+            }
+            word.iter().fold(0u32, |value, &b| value << 8 | b as u32) as i32
+        })
+        .collect();
+    Ok(words)
+}
+
+/// Generates a rom which holds `data`, see [`RomOptions`] for the layout of the data.
+pub fn rom(data: &[u8], options: &RomOptions) -> Result<Rom> {
+    check_options(options)?;
+    let words = words(data, options.word_size, options.endianness)?;
+    if words.is_empty() {
+        return Err(RomError::Empty);
+    }
+
+    let mut blueprint = Blueprint {
+        version: compiler::VERSION,
+        ..Blueprint::default()
+    };
+    let template = primitives::loader_cell();
+    let (width, _) = template.size();
+    let mut deciders: Vec<usize> = Vec::new();
+    let mut address = options.base;
+    for (i, chunk) in words.chunks(options.data.len()).enumerate() {
+        if i > 0 {
+            address = address
+                .checked_add(options.stride)
+                .ok_or(RomError::AddressOverflow)?;
+        }
+
+        // Columns of cells with a free column for poles between them
+        let (col, row) = (i / options.height, i % options.height);
+        let cell = template.instantiate(
+            &mut blueprint,
+            (col as u32 * (width + 1)) as f32,
+            row as f32,
+        );
+        if let Entity::ConstantCombinator { condition, .. } =
+            &mut blueprint.entities[cell.id(LoaderCell::Data)]
+        {
+            *condition = chunk
+                .iter()
+                .zip(&options.data)
+                .enumerate()
+                .filter(|(_, (&count, _))| count != 0)
+                .map(|(slot, (&count, signal))| ConstantCondition {
+                    count,
+                    index: slot as u8 + 1,
+                    signal: signal.clone(),
+                })
+                .collect();
+        }
+        let decider = cell.id(LoaderCell::Decider);
+        if let Entity::DeciderCombinator { condition, .. } = &mut blueprint.entities[decider] {
+            condition.first_signal = Some(options.address.clone());
+            condition.constant = Some(address);
+        }
+
+        // Chain the cells of a column, the columns are joined along the top row
+        let previous = match row {
+            0 => deciders.len().checked_sub(options.height),
+            _ => Some(deciders.len() - 1),
+        };
+        if let Some(previous) = previous {
+            connect_cells(&mut blueprint, deciders[previous], decider)?;
+        }
+        deciders.push(decider);
+    }
+
+    let combinators: Vec<usize> = (0..blueprint.entities.len()).collect();
+    blueprint.place_power_poles(&combinators, PoleType::Medium)?;
+
+    Ok(Rom {
+        blueprint,
+        address: Connector {
+            id: deciders[0],
+            side: Side::One,
+        },
+        output: Connector {
+            id: deciders[0],
+            side: Side::Two,
+        },
+        cells: deciders.len(),
+    })
+}
+
+fn check_options(options: &RomOptions) -> Result<()> {
+    if options.height == 0 {
+        return Err(RomError::InvalidHeight);
+    }
+    if options.stride == 0 {
+        return Err(RomError::InvalidStride);
+    }
+    if options.data.is_empty() {
+        return Err(RomError::NoDataSignal);
+    }
+    if options.data.len() > CONSTANT_SLOTS {
+        return Err(RomError::TooManySignals(options.data.len()));
+    }
+
+    let mut seen = BTreeSet::new();
+    for signal in std::iter::once(&options.address).chain(&options.data) {
+        if matches!(
+            signal.name.as_str(),
+            "signal-everything" | "signal-anything" | "signal-each"
+        ) {
+            return Err(RomError::ReservedSignal(signal.name.clone()));
+        }
+        if !seen.insert(signal) {
+            return Err(RomError::DuplicateSignal(signal.name.clone()));
+        }
+    }
+
+    Ok(())
+}
+
+/// Joins the address inputs (red) and the outputs (green) of two cells.
+fn connect_cells(blueprint: &mut Blueprint, id1: usize, id2: usize) -> Result<()> {
+    let connector = |id: usize, side: Side| Connector { id, side };
+    blueprint.connect_wire_with_side(
+        connector(id1, Side::One),
+        connector(id2, Side::One),
+        Wire::Red,
+    )?;
+    blueprint.connect_wire_with_side(
+        connector(id1, Side::Two),
+        connector(id2, Side::Two),
+        Wire::Green,
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::harness::Harness;
+
+    fn s(name: &str) -> Signal {
+        Signal::virtual_signal(name)
+    }
+
+    #[test]
+    fn words_respect_size_and_endianness() {
+        let data = [0x12, 0x34, 0x56, 0x78, 0x9a];
+        assert_eq!(
+            vec![0x1234, 0x5678, 0x9a00],
+            words(&data, 2, Endianness::Big).unwrap()
+        );
+        assert_eq!(
+            vec![0x3412, 0x7856, 0x9a],
+            words(&data, 2, Endianness::Little).unwrap()
+        );
+        assert_eq!(
+            vec![0x78563412, 0x9a],
+            words(&data, 4, Endianness::Little).unwrap()
+        );
+        assert_eq!(vec![-1], words(&[0xff; 4], 4, Endianness::Big).unwrap());
+        assert_eq!(
+            RomError::InvalidWordSize(5),
+            words(&data, 5, Endianness::Big).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn packed_cells_output_their_words() {
+        let data: Vec<u8> = (1..=21).collect();
+        let options = RomOptions {
+            word_size: 1,
+            address: s("signal-A"),
+            data: vec![s("signal-X"), s("signal-Y"), s("signal-Z")],
+            base: 100,
+            stride: 3,
+            height: 3,
+            ..RomOptions::default()
+        };
+        let rom = rom(&data, &options).unwrap();
+        assert_eq!(7, rom.cells);
+        assert!(rom.blueprint.check_wire_reach().is_empty());
+
+        let mut harness = Harness::new(&rom.blueprint);
+        harness
+            .port("address", rom.address.clone(), Wire::Red)
+            .unwrap();
+        harness
+            .port("out", rom.output.clone(), Wire::Green)
+            .unwrap();
+        for cell in 0..7 {
+            let address = 100 + 3 * cell;
+            harness
+                .drive("address", &[(s("signal-A"), address)])
+                .unwrap();
+            harness.step(2);
+            harness.expect("out", &s("signal-A"), address).unwrap();
+            for (slot, signal) in ["signal-X", "signal-Y", "signal-Z"].iter().enumerate() {
+                let word = 3 * cell + slot as i32 + 1;
+                harness.expect("out", &s(signal), word).unwrap();
+            }
+        }
+
+        harness.drive("address", &[(s("signal-A"), 101)]).unwrap();
+        harness.step(2);
+        assert!(harness.read("out").unwrap().is_empty());
+    }
+
+    #[test]
+    fn roms_with_zero_words_are_read_back() {
+        let rom = rom(&[0; 64], &RomOptions::default()).unwrap();
+        let string = crate::model_to_blueprint_string(rom.blueprint).unwrap();
+        let blueprint = crate::blueprint_string_to_model(&string).unwrap();
+
+        assert_eq!(
+            16,
+            blueprint
+                .entities
+                .iter()
+                .filter(|e| e.name() == "constant-combinator")
+                .count()
+        );
+        assert!(blueprint.entities.iter().all(|e| match e {
+            Entity::ConstantCombinator { condition, .. } => condition.is_empty(),
+            _ => true,
+        }));
+    }
+
+    #[test]
+    fn rom_checks_options() {
+        let options = |data: &[&str]| RomOptions {
+            data: data.iter().map(|name| s(name)).collect(),
+            ..RomOptions::default()
+        };
+        assert_eq!(
+            RomError::Empty,
+            rom(&[], &RomOptions::default()).unwrap_err()
+        );
+        assert_eq!(
+            RomError::DuplicateSignal("signal-W".into()),
+            rom(&[1], &options(&["signal-W"])).unwrap_err()
+        );
+        assert_eq!(
+            RomError::ReservedSignal("signal-each".into()),
+            rom(&[1], &options(&["signal-each"])).unwrap_err()
+        );
+        assert_eq!(
+            RomError::TooManySignals(21),
+            rom(&[1], &options(&["signal-A"; 21])).unwrap_err()
+        );
+    }
+}
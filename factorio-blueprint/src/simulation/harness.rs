@@ -0,0 +1,396 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
+    fmt,
+    io::{self, Write},
+};
+
+use crate::{
+    abstract_model::{network::NetworkId, Blueprint, Connector, Wire},
+    model::Signal,
+};
+
+use super::{Signals, Simulator};
+
+#[derive(Debug, PartialEq)]
+pub enum HarnessError {
+    UnknownPort(String),
+    NotConnected(String),
+    Mismatch {
+        port: String,
+        signal: String,
+        tick: u64,
+        expected: i32,
+        actual: i32,
+    },
+}
+
+impl fmt::Display for HarnessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnknownPort(name) => write!(f, "port '{}' does not exist", name),
+            Self::NotConnected(name) => {
+                write!(f, "port '{}' has no wire of the requested colour", name)
+            }
+            Self::Mismatch {
+                port,
+                signal,
+                tick,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "port '{}' signal '{}' at tick {}: expected {} but was {}",
+                port, signal, tick, expected, actual
+            ),
+        }
+    }
+}
+
+impl Error for HarnessError {}
+
+pub type Result<T> = core::result::Result<T, HarnessError>;
+
+/// Drives signals into named networks of a simulated blueprint, steps the simulation and
+/// checks the signals that come out. Optionally records every port per tick as a waveform.
+#[derive(Debug)]
+pub struct Harness {
+    simulator: Simulator,
+    ports: BTreeMap<String, NetworkId>,
+    trace: Option<Vec<(u64, BTreeMap<String, Signals>)>>,
+}
+
+impl Harness {
+    pub fn new(blueprint: &Blueprint) -> Self {
+        Harness {
+            simulator: Simulator::new(blueprint),
+            ports: BTreeMap::new(),
+            trace: None,
+        }
+    }
+
+    pub fn simulator(&self) -> &Simulator {
+        &self.simulator
+    }
+
+    pub fn simulator_mut(&mut self) -> &mut Simulator {
+        &mut self.simulator
+    }
+
+    /// Names the network of the given colour at a connector.
+    pub fn port(&mut self, name: &str, connector: Connector, wire: Wire) -> Result<()> {
+        let network = self
+            .simulator
+            .networks()
+            .id_of(&connector, wire)
+            .ok_or_else(|| HarnessError::NotConnected(name.into()))?;
+        self.ports.insert(name.into(), network);
+        Ok(())
+    }
+
+    fn network(&self, port: &str) -> Result<NetworkId> {
+        self.ports
+            .get(port)
+            .copied()
+            .ok_or_else(|| HarnessError::UnknownPort(port.into()))
+    }
+
+    /// Drives the given signals into a port until they are replaced or released.
+    pub fn drive(&mut self, port: &str, signals: &[(Signal, i32)]) -> Result<()> {
+        let network = self.network(port)?;
+        let mut driven = Signals::new();
+        for (signal, value) in signals {
+            super::add_signal(&mut driven, signal, *value);
+        }
+        self.simulator.drive(network, driven);
+        self.sample();
+        Ok(())
+    }
+
+    pub fn release(&mut self, port: &str) -> Result<()> {
+        let network = self.network(port)?;
+        self.simulator.release(network);
+        self.sample();
+        Ok(())
+    }
+
+    /// Advances the simulation by the given number of ticks.
+    pub fn step(&mut self, ticks: u64) {
+        for _ in 0..ticks {
+            self.simulator.step();
+            self.sample();
+        }
+    }
+
+    pub fn tick(&self) -> u64 {
+        self.simulator.tick()
+    }
+
+    pub fn read(&self, port: &str) -> Result<Signals> {
+        let network = self.network(port)?;
+        Ok(self.simulator.network(network).clone())
+    }
+
+    pub fn read_signal(&self, port: &str, signal: &Signal) -> Result<i32> {
+        Ok(self.read(port)?.get(signal).copied().unwrap_or(0))
+    }
+
+    /// Checks the value of a signal at a port during the current tick.
+    pub fn expect(&self, port: &str, signal: &Signal, expected: i32) -> Result<()> {
+        let actual = self.read_signal(port, signal)?;
+        if actual == expected {
+            Ok(())
+        } else {
+            Err(HarnessError::Mismatch {
+                port: port.into(),
+                signal: signal.name.clone(),
+                tick: self.tick(),
+                expected,
+                actual,
+            })
+        }
+    }
+
+    /// Starts recording all ports, beginning with the current tick.
+    pub fn record(&mut self) {
+        self.trace = Some(Vec::new());
+        self.sample();
+    }
+
+    fn sample(&mut self) {
+        if let Some(trace) = &mut self.trace {
+            let values = self
+                .ports
+                .iter()
+                .map(|(name, &network)| (name.clone(), self.simulator.network(network).clone()))
+                .collect();
+
+            // Driving a port changes the current tick, which replaces its earlier sample
+            if trace.last().is_some_and(|(tick, _)| *tick == self.simulator.tick()) {
+                trace.pop();
+            }
+            trace.push((self.simulator.tick(), values));
+        }
+    }
+
+    /// Writes the recorded trace in the value change dump format (e.g. for GTKWave).
+    /// Every port becomes a scope with one 32 bit variable per signal that occurred on it.
+    /// One time unit corresponds to one game tick.
+    pub fn write_vcd<W: Write>(&self, mut out: W) -> io::Result<()> {
+        let trace = match &self.trace {
+            Some(trace) => trace,
+            None => return Ok(()),
+        };
+
+        // Collect all signals that occur on each port
+        let mut variables: BTreeMap<&str, BTreeSet<&Signal>> = BTreeMap::new();
+        for name in self.ports.keys() {
+            variables.insert(name, BTreeSet::new());
+        }
+        for (_, ports) in trace {
+            for (name, signals) in ports {
+                variables.entry(name).or_default().extend(signals.keys());
+            }
+        }
+
+        let mut codes = BTreeMap::new();
+        writeln!(out, "$comment factorio-blueprint simulation $end")?;
+        writeln!(out, "$timescale 1 ns $end")?;
+        writeln!(out, "$scope module blueprint $end")?;
+        for (port, signals) in &variables {
+            writeln!(out, "$scope module {} $end", vcd_name(port))?;
+            for &signal in signals {
+                let code = vcd_code(codes.len());
+                writeln!(out, "$var integer 32 {} {} $end", code, vcd_name(&signal.name))?;
+                codes.insert((*port, signal), code);
+            }
+            writeln!(out, "$upscope $end")?;
+        }
+        writeln!(out, "$upscope $end")?;
+        writeln!(out, "$enddefinitions $end")?;
+
+        let mut last: BTreeMap<(&str, &Signal), i32> = BTreeMap::new();
+        for (i, (tick, ports)) in trace.iter().enumerate() {
+            writeln!(out, "#{}", tick)?;
+            for ((port, signal), code) in &codes {
+                let value = ports
+                    .get(*port)
+                    .and_then(|signals| signals.get(*signal))
+                    .copied()
+                    .unwrap_or(0);
+                if i == 0 || last.get(&(*port, *signal)) != Some(&value) {
+                    writeln!(out, "b{:b} {}", value as u32, code)?;
+                    last.insert((port, signal), value);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Short identifier of a variable, built from the printable ASCII characters.
+fn vcd_code(mut index: usize) -> String {
+    let mut code = String::new();
+    loop {
+        code.push((b'!' + (index % 94) as u8) as char);
+        index /= 94;
+        if index == 0 {
+            return code;
+        }
+        index -= 1;
+    }
+}
+
+fn vcd_name(name: &str) -> String {
+    name.chars().map(|c| if c.is_whitespace() { '_' } else { c }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        abstract_model::{Entity, Side},
+        model::{ArithmeticCondition, Direction, Operation, Position},
+    };
+
+    // Empty constant combinator -> arithmetic combinator (A * 2) -> arithmetic combinator
+    fn doubler() -> Harness {
+        let constant = Entity::ConstantCombinator {
+            id: 0,
+            position: Position { x: 0.5, y: 0.5 },
+            direction: Direction::East,
+            is_on: true,
+            connections: Vec::new(),
+            condition: Vec::new(),
+            tags: None,
+        };
+        let arithmetic = |id: usize, x: f32| Entity::ArithmeticCombinator {
+            id,
+            position: Position { x, y: 0.5 },
+            direction: Direction::East,
+            connections: Vec::new(),
+            condition: ArithmeticCondition {
+                operation: Operation::Mul,
+                first_constant: None,
+                second_constant: Some(2),
+                first_signal: Some(Signal::virtual_signal("signal-A")),
+                second_signal: None,
+                output_signal: Some(Signal::virtual_signal("signal-A")),
+            },
+            tags: None,
+        };
+        let (input, output) = (
+            Connector {
+                id: 1,
+                side: Side::One,
+            },
+            Connector {
+                id: 2,
+                side: Side::One,
+            },
+        );
+        let mut blueprint = Blueprint::new(vec![constant, arithmetic(1, 2.0), arithmetic(2, 4.0)]);
+        blueprint.connect_wire(0, 1, Wire::Red).unwrap();
+        let middle = Connector {
+            id: 1,
+            side: Side::Two,
+        };
+        blueprint
+            .connect_wire_with_side(middle, output.clone(), Wire::Green)
+            .unwrap();
+
+        let mut harness = Harness::new(&blueprint);
+        harness.port("in", input, Wire::Red).unwrap();
+        harness.port("out", output, Wire::Green).unwrap();
+        harness
+    }
+
+    #[test]
+    fn ports_must_exist_and_be_connected() {
+        let mut harness = doubler();
+        let a = Signal::virtual_signal("signal-A");
+        assert_eq!(
+            Err(HarnessError::UnknownPort("clock".into())),
+            harness.drive("clock", &[(a.clone(), 1)])
+        );
+        assert_eq!(
+            Err(HarnessError::UnknownPort("clock".into())),
+            harness.read_signal("clock", &a)
+        );
+        assert_eq!(
+            Err(HarnessError::NotConnected("green".into())),
+            harness.port(
+                "green",
+                Connector {
+                    id: 1,
+                    side: Side::One
+                },
+                Wire::Green
+            )
+        );
+
+        harness.drive("in", &[(a.clone(), 3)]).unwrap();
+        harness.step(1);
+        assert_eq!(Ok(()), harness.expect("out", &a, 6));
+        assert_eq!(
+            Err(HarnessError::Mismatch {
+                port: "out".into(),
+                signal: "signal-A".into(),
+                tick: 1,
+                expected: 3,
+                actual: 6,
+            }),
+            harness.expect("out", &a, 3)
+        );
+    }
+
+    #[test]
+    fn traces_are_written_as_value_change_dumps() {
+        let mut harness = doubler();
+        let a = Signal::virtual_signal("signal-A");
+        let mut vcd = Vec::new();
+        harness.write_vcd(&mut vcd).unwrap();
+        assert!(vcd.is_empty());
+
+        harness.record();
+        harness.drive("in", &[(a.clone(), 3)]).unwrap();
+        harness.step(2);
+        harness.release("in").unwrap();
+        harness.step(2);
+        harness.write_vcd(&mut vcd).unwrap();
+        assert_eq!(
+            "$comment factorio-blueprint simulation $end\n\
+             $timescale 1 ns $end\n\
+             $scope module blueprint $end\n\
+             $scope module in $end\n\
+             $var integer 32 ! signal-A $end\n\
+             $upscope $end\n\
+             $scope module out $end\n\
+             $var integer 32 \" signal-A $end\n\
+             $upscope $end\n\
+             $upscope $end\n\
+             $enddefinitions $end\n\
+             #0\n\
+             b11 !\n\
+             b0 \"\n\
+             #1\n\
+             b110 \"\n\
+             #2\n\
+             b0 !\n\
+             #3\n\
+             b0 \"\n\
+             #4\n",
+            String::from_utf8(vcd).unwrap()
+        );
+    }
+
+    #[test]
+    fn vcd_codes_are_unique() {
+        assert_eq!("!", vcd_code(0));
+        assert_eq!("~", vcd_code(93));
+        assert_eq!("!!", vcd_code(94));
+        assert_eq!("\"!", vcd_code(95));
+        assert_eq!("signal_A", vcd_name("signal A"));
+    }
+}
@@ -0,0 +1,556 @@
+pub mod harness;
+
+use std::collections::BTreeMap;
+
+use crate::{
+    abstract_model::{
+        network::{CircuitNetworks, NetworkId},
+        Blueprint, Connector, Entity, Side, Wire,
+    },
+    model::{ArithmeticCondition, Comparator, ConstantCondition, DeciderCondition, Operation, Signal},
+};
+
+/// Values of signals on a network or at an entity. Signals with a value of zero are not stored.
+pub type Signals = BTreeMap<Signal, i32>;
+
+const EVERYTHING: &str = "signal-everything";
+const ANYTHING: &str = "signal-anything";
+const EACH: &str = "signal-each";
+
+/// Combinator related part of an entity that is relevant for the simulation.
+#[derive(Clone, Debug)]
+enum Behaviour {
+    Decider(DeciderCondition),
+    Arithmetic(ArithmeticCondition),
+    Constant { signals: Signals, is_on: bool },
+    Passive,
+}
+
+#[derive(Clone, Debug)]
+struct SimulatedEntity {
+    behaviour: Behaviour,
+    inputs: Vec<NetworkId>,
+    outputs: Vec<NetworkId>,
+}
+
+/// Tick by tick simulation of the combinators in a blueprint, following the rules of the game:
+///
+/// - Every network carries the sum of the outputs of all entities connected to it.
+/// - Combinators read the sum of the red and green network at their input (side one)
+///   and write their result to the networks at their output (side two) one tick later.
+/// - Constant combinators output their signals on every network they are connected to.
+/// - All arithmetic wraps around on overflow like the 32 bit integers of the game.
+#[derive(Clone, Debug)]
+pub struct Simulator {
+    networks: CircuitNetworks,
+    entities: Vec<SimulatedEntity>,
+    outputs: Vec<Signals>,
+    values: Vec<Signals>,
+    external: Vec<Signals>,
+    tick: u64,
+}
+
+impl Simulator {
+    pub fn new(blueprint: &Blueprint) -> Self {
+        let networks = blueprint.circuit_networks();
+
+        let networks_at = |id: usize, sides: &[Side]| -> Vec<NetworkId> {
+            let mut ids = Vec::new();
+            for &side in sides {
+                for wire in [Wire::Red, Wire::Green] {
+                    if let Some(n) = networks.id_of(&Connector { id, side }, wire) {
+                        ids.push(n);
+                    }
+                }
+            }
+
+            // Both sides of a constant combinator can end up on the same network
+            ids.sort_unstable();
+            ids.dedup();
+            ids
+        };
+
+        let entities = blueprint
+            .entities
+            .iter()
+            .map(|e| {
+                let id = e.id();
+                match e {
+                    Entity::DeciderCombinator { condition, .. } => SimulatedEntity {
+                        behaviour: Behaviour::Decider(condition.clone()),
+                        inputs: networks_at(id, &[Side::One]),
+                        outputs: networks_at(id, &[Side::Two]),
+                    },
+                    Entity::ArithmeticCombinator { condition, .. } => SimulatedEntity {
+                        behaviour: Behaviour::Arithmetic(condition.clone()),
+                        inputs: networks_at(id, &[Side::One]),
+                        outputs: networks_at(id, &[Side::Two]),
+                    },
+                    Entity::ConstantCombinator { condition, is_on, .. } => SimulatedEntity {
+                        behaviour: Behaviour::Constant {
+                            signals: constant_signals(condition),
+                            is_on: *is_on,
+                        },
+                        inputs: Vec::new(),
+                        outputs: networks_at(id, &[Side::One, Side::Two]),
+                    },
+                    _ => SimulatedEntity {
+                        behaviour: Behaviour::Passive,
+                        inputs: Vec::new(),
+                        outputs: Vec::new(),
+                    },
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut simulator = Simulator {
+            values: vec![Signals::new(); networks.networks.len()],
+            external: vec![Signals::new(); networks.networks.len()],
+            outputs: vec![Signals::new(); entities.len()],
+            networks,
+            entities,
+            tick: 0,
+        };
+
+        // Constant combinators output their signals right away
+        for i in 0..simulator.entities.len() {
+            if let Behaviour::Constant { .. } = simulator.entities[i].behaviour {
+                simulator.outputs[i] = simulator.entities[i].evaluate(&Signals::new());
+            }
+        }
+        simulator.update_networks();
+
+        simulator
+    }
+
+    /// Advances the simulation by one tick.
+    pub fn step(&mut self) {
+        let outputs = self
+            .entities
+            .iter()
+            .map(|e| {
+                let mut input = Signals::new();
+                for &n in &e.inputs {
+                    add_signals(&mut input, &self.values[n]);
+                }
+                e.evaluate(&input)
+            })
+            .collect();
+
+        self.outputs = outputs;
+        self.update_networks();
+        self.tick += 1;
+    }
+
+    pub fn run(&mut self, ticks: u64) {
+        for _ in 0..ticks {
+            self.step();
+        }
+    }
+
+    fn update_networks(&mut self) {
+        for (values, external) in self.values.iter_mut().zip(&self.external) {
+            values.clone_from(external);
+        }
+
+        for (entity, output) in self.entities.iter().zip(&self.outputs) {
+            for &n in &entity.outputs {
+                add_signals(&mut self.values[n], output);
+            }
+        }
+    }
+
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub fn networks(&self) -> &CircuitNetworks {
+        &self.networks
+    }
+
+    /// Signals on the given network during the current tick.
+    pub fn network(&self, id: NetworkId) -> &Signals {
+        &self.values[id]
+    }
+
+    /// Signals on the wire of the given colour at a connector.
+    /// Returns no signals if no such wire is attached.
+    pub fn probe(&self, connector: &Connector, wire: Wire) -> Signals {
+        self.networks
+            .id_of(connector, wire)
+            .map(|n| self.values[n].clone())
+            .unwrap_or_default()
+    }
+
+    /// Sum of the red and green signals at a connector, as seen by a combinator input.
+    pub fn probe_both(&self, connector: &Connector) -> Signals {
+        let mut signals = self.probe(connector, Wire::Red);
+        add_signals(&mut signals, &self.probe(connector, Wire::Green));
+        signals
+    }
+
+    /// Value of a single signal on the wire of the given colour at a connector.
+    pub fn value(&self, connector: &Connector, wire: Wire, signal: &Signal) -> i32 {
+        self.networks
+            .id_of(connector, wire)
+            .and_then(|n| self.values[n].get(signal).copied())
+            .unwrap_or(0)
+    }
+
+    /// Adds the given signals to a network from outside of the blueprint, as if an additional
+    /// constant combinator was connected to it. Replaces previously driven signals and is
+    /// visible on the network immediately.
+    pub fn drive(&mut self, network: NetworkId, signals: Signals) {
+        self.external[network] = signals;
+        self.update_networks();
+    }
+
+    /// Stops driving signals into a network.
+    pub fn release(&mut self, network: NetworkId) {
+        self.drive(network, Signals::new());
+    }
+
+    /// Switches a constant combinator on or off. Takes effect on the next tick.
+    /// Returns false if the entity is not a constant combinator.
+    pub fn set_enabled(&mut self, id: usize, enabled: bool) -> bool {
+        match self.entities.get_mut(id).map(|e| &mut e.behaviour) {
+            Some(Behaviour::Constant { is_on, .. }) => {
+                *is_on = enabled;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Replaces the signals of a constant combinator. Takes effect on the next tick.
+    /// Returns false if the entity is not a constant combinator.
+    pub fn set_constant(&mut self, id: usize, condition: &[ConstantCondition]) -> bool {
+        match self.entities.get_mut(id).map(|e| &mut e.behaviour) {
+            Some(Behaviour::Constant { signals, .. }) => {
+                *signals = constant_signals(condition);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl SimulatedEntity {
+    fn evaluate(&self, input: &Signals) -> Signals {
+        match &self.behaviour {
+            Behaviour::Decider(condition) => decide(condition, input),
+            Behaviour::Arithmetic(condition) => calculate(condition, input),
+            Behaviour::Constant { signals, is_on: true } => signals.clone(),
+            Behaviour::Constant { is_on: false, .. } | Behaviour::Passive => Signals::new(),
+        }
+    }
+}
+
+fn constant_signals(condition: &[ConstantCondition]) -> Signals {
+    let mut signals = Signals::new();
+    for c in condition {
+        add_signal(&mut signals, &c.signal, c.count);
+    }
+    signals
+}
+
+pub fn add_signal(signals: &mut Signals, signal: &Signal, value: i32) {
+    let sum = signals.get(signal).copied().unwrap_or(0).wrapping_add(value);
+    if sum == 0 {
+        signals.remove(signal);
+    } else {
+        signals.insert(signal.clone(), sum);
+    }
+}
+
+pub fn add_signals(signals: &mut Signals, other: &Signals) {
+    for (signal, &value) in other {
+        add_signal(signals, signal, value);
+    }
+}
+
+fn is_special(signal: &Signal, name: &str) -> bool {
+    signal.name == name
+}
+
+/// Applies a decider combinator comparison.
+pub fn compare(comparator: Comparator, a: i32, b: i32) -> bool {
+    match comparator {
+        Comparator::Gt => a > b,
+        Comparator::Lt => a < b,
+        Comparator::Eq => a == b,
+        Comparator::Ge => a >= b,
+        Comparator::Le => a <= b,
+        Comparator::Neq => a != b,
+    }
+}
+
+fn decide(condition: &DeciderCondition, input: &Signals) -> Signals {
+    let mut output = Signals::new();
+    let (first, out) = match (&condition.first_signal, &condition.output_signal) {
+        (Some(first), Some(out)) => (first, out),
+        _ => return output,
+    };
+
+    let value_of = |s: &Signal| input.get(s).copied().unwrap_or(0);
+    let right = match &condition.second_signal {
+        Some(second) => value_of(second),
+        None => condition.constant.unwrap_or(0),
+    };
+    let passes = |value: i32| compare(condition.comparator, value, right);
+    let count = |s: &Signal| if condition.copy_count_from_input { value_of(s) } else { 1 };
+
+    if is_special(first, EACH) {
+        for signal in input.keys().filter(|s| passes(value_of(s))) {
+            if is_special(out, EACH) {
+                add_signal(&mut output, signal, count(signal));
+            } else if !is_special(out, EVERYTHING) && !is_special(out, ANYTHING) {
+                add_signal(&mut output, out, count(signal));
+            }
+        }
+        return output;
+    }
+
+    let fulfilled = if is_special(first, EVERYTHING) {
+        input.values().all(|&v| passes(v))
+    } else if is_special(first, ANYTHING) {
+        input.values().any(|&v| passes(v))
+    } else {
+        passes(value_of(first))
+    };
+
+    if fulfilled {
+        if is_special(out, EVERYTHING) {
+            for signal in input.keys() {
+                add_signal(&mut output, signal, count(signal));
+            }
+        } else if !is_special(out, EACH) && !is_special(out, ANYTHING) {
+            add_signal(&mut output, out, count(out));
+        }
+    }
+
+    output
+}
+
+fn calculate(condition: &ArithmeticCondition, input: &Signals) -> Signals {
+    let mut output = Signals::new();
+    let out = match &condition.output_signal {
+        Some(out) => out,
+        None => return output,
+    };
+
+    let value_of = |s: &Signal| input.get(s).copied().unwrap_or(0);
+    let operand = |signal: &Option<Signal>, constant: Option<i32>, each: i32| match signal {
+        Some(s) if is_special(s, EACH) => each,
+        Some(s) => value_of(s),
+        None => constant.unwrap_or(0),
+    };
+    let result = |each: i32| {
+        let left = operand(&condition.first_signal, condition.first_constant, each);
+        let right = operand(&condition.second_signal, condition.second_constant, each);
+        operate(condition.operation, left, right)
+    };
+
+    let first_is_each = condition.first_signal.as_ref().is_some_and(|s| is_special(s, EACH));
+    let second_is_each = condition.second_signal.as_ref().is_some_and(|s| is_special(s, EACH));
+    if first_is_each || second_is_each {
+        for (signal, &value) in input {
+            let target = if is_special(out, EACH) { signal } else { out };
+            add_signal(&mut output, target, result(value));
+        }
+    } else if !is_special(out, EACH) {
+        add_signal(&mut output, out, result(0));
+    }
+
+    output
+}
+
+/// Applies an arithmetic combinator operation with the integer semantics of the game:
+/// results wrap around, division and modulo by zero yield zero, negative exponents yield zero
+/// and shifts only use the lowest five bits of the shift amount.
+pub fn operate(operation: Operation, left: i32, right: i32) -> i32 {
+    match operation {
+        Operation::Add => left.wrapping_add(right),
+        Operation::Sub => left.wrapping_sub(right),
+        Operation::Mul => left.wrapping_mul(right),
+        Operation::Div => {
+            if right == 0 {
+                0
+            } else {
+                left.wrapping_div(right)
+            }
+        }
+        Operation::Mod => {
+            if right == 0 {
+                0
+            } else {
+                left.wrapping_rem(right)
+            }
+        }
+        Operation::Pow => {
+            if right < 0 {
+                0
+            } else {
+                left.wrapping_pow(right as u32)
+            }
+        }
+        Operation::Shl => left.wrapping_shl(right as u32),
+        Operation::Shr => left.wrapping_shr(right as u32),
+        Operation::And => left & right,
+        Operation::Or => left | right,
+        Operation::Xor => left ^ right,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{self, Direction, Position};
+
+    fn s(name: &str) -> Signal {
+        Signal::virtual_signal(name)
+    }
+
+    fn signals(values: &[(&str, i32)]) -> Signals {
+        let mut result = Signals::new();
+        for (name, value) in values {
+            add_signal(&mut result, &s(name), *value);
+        }
+        result
+    }
+
+    fn decider_condition(first: &str, comparator: Comparator, constant: i32, out: &str, copy: bool) -> DeciderCondition {
+        DeciderCondition {
+            comparator,
+            copy_count_from_input: copy,
+            constant: Some(constant),
+            first_signal: Some(s(first)),
+            second_signal: None,
+            output_signal: Some(s(out)),
+        }
+    }
+
+    #[test]
+    fn operations_wrap_like_the_game() {
+        assert_eq!(i32::MIN, operate(Operation::Add, i32::MAX, 1));
+        assert_eq!(i32::MAX, operate(Operation::Sub, i32::MIN, 1));
+        assert_eq!(-2, operate(Operation::Mul, i32::MAX, 2));
+        assert_eq!(0, operate(Operation::Div, 5, 0));
+        assert_eq!(i32::MIN, operate(Operation::Div, i32::MIN, -1));
+        assert_eq!(-2, operate(Operation::Div, -7, 3));
+        assert_eq!(-1, operate(Operation::Mod, -7, 3));
+        assert_eq!(0, operate(Operation::Mod, 7, 0));
+        assert_eq!(1024, operate(Operation::Pow, 2, 10));
+        assert_eq!(0, operate(Operation::Pow, 2, 32));
+        assert_eq!(0, operate(Operation::Pow, 2, -1));
+        assert_eq!(i32::MIN, operate(Operation::Shl, 1, 31));
+        assert_eq!(2, operate(Operation::Shl, 1, 33));
+        assert_eq!(-1, operate(Operation::Shr, -8, 3 + 32 * 4));
+        assert_eq!(-1, operate(Operation::Shr, i32::MIN, 31));
+        assert_eq!(0b0110, operate(Operation::Xor, 0b1010, 0b1100));
+    }
+
+    #[test]
+    fn decider_special_signals() {
+        let input = signals(&[("signal-A", 5), ("signal-B", -3), ("signal-C", 10)]);
+
+        let each = decide(&decider_condition(EACH, Comparator::Gt, 0, EACH, true), &input);
+        assert_eq!(signals(&[("signal-A", 5), ("signal-C", 10)]), each);
+
+        let each_sum = decide(&decider_condition(EACH, Comparator::Gt, 0, "signal-X", false), &input);
+        assert_eq!(signals(&[("signal-X", 2)]), each_sum);
+
+        let anything = decide(&decider_condition(ANYTHING, Comparator::Lt, 0, "signal-X", true), &input);
+        assert_eq!(Signals::new(), anything);
+
+        let everything = decide(&decider_condition(EVERYTHING, Comparator::Neq, 0, EVERYTHING, false), &input);
+        assert_eq!(signals(&[("signal-A", 1), ("signal-B", 1), ("signal-C", 1)]), everything);
+
+        let none = decide(&decider_condition(EVERYTHING, Comparator::Gt, 0, EVERYTHING, true), &input);
+        assert_eq!(Signals::new(), none);
+    }
+
+    #[test]
+    fn combinators_delay_by_one_tick() {
+        // Constant combinator -> arithmetic combinator (A * 2) -> decider combinator (A > 0)
+        let constant = Entity::ConstantCombinator {
+            id: 0,
+            position: Position { x: 0.5, y: 0.5 },
+            direction: Direction::East,
+            is_on: true,
+            connections: Vec::new(),
+            condition: vec![model::ConstantCondition {
+                count: 3,
+                index: 1,
+                signal: s("signal-A"),
+            }],
+            tags: None,
+        };
+        let arithmetic = Entity::ArithmeticCombinator {
+            id: 1,
+            position: Position { x: 2.0, y: 0.5 },
+            direction: Direction::East,
+            connections: Vec::new(),
+            condition: ArithmeticCondition {
+                operation: Operation::Mul,
+                first_constant: None,
+                second_constant: Some(2),
+                first_signal: Some(s("signal-A")),
+                second_signal: None,
+                output_signal: Some(s("signal-A")),
+            },
+            tags: None,
+        };
+        let decider = Entity::DeciderCombinator {
+            id: 2,
+            position: Position { x: 4.0, y: 0.5 },
+            direction: Direction::East,
+            connections: Vec::new(),
+            condition: decider_condition("signal-A", Comparator::Gt, 0, "signal-A", true),
+            tags: None,
+        };
+        let mut blueprint = Blueprint::new(vec![constant, arithmetic, decider]);
+        blueprint.connect_wire(0, 1, Wire::Red).unwrap();
+        blueprint
+            .connect_wire_with_side(
+                Connector { id: 1, side: Side::Two },
+                Connector { id: 2, side: Side::One },
+                Wire::Green,
+            )
+            .unwrap();
+        blueprint
+            .connect_wire_with_side(
+                Connector { id: 2, side: Side::Two },
+                Connector { id: 2, side: Side::One },
+                Wire::Red,
+            )
+            .unwrap();
+
+        let a = s("signal-A");
+        let (arithmetic_in, decider_in, decider_out) = (
+            Connector { id: 1, side: Side::One },
+            Connector { id: 2, side: Side::One },
+            Connector { id: 2, side: Side::Two },
+        );
+
+        let mut simulator = Simulator::new(&blueprint);
+        assert_eq!(3, simulator.value(&arithmetic_in, Wire::Red, &a));
+        assert_eq!(0, simulator.value(&decider_in, Wire::Green, &a));
+
+        simulator.step();
+        assert_eq!(6, simulator.value(&decider_in, Wire::Green, &a));
+        assert_eq!(0, simulator.value(&decider_out, Wire::Red, &a));
+
+        // The decider feeds its output back into its input, so the value keeps growing
+        simulator.step();
+        assert_eq!(6, simulator.value(&decider_out, Wire::Red, &a));
+        simulator.step();
+        assert_eq!(12, simulator.value(&decider_out, Wire::Red, &a));
+        assert_eq!(signals(&[("signal-A", 18)]), simulator.probe_both(&decider_in));
+
+        simulator.set_enabled(0, false);
+        simulator.run(2);
+        assert_eq!(5, simulator.tick());
+        assert_eq!(0, simulator.value(&decider_in, Wire::Green, &a));
+    }
+}
@@ -0,0 +1,254 @@
+//! Statistics of blueprints, e.g. to estimate the build cost of generated memory or to notice
+//! when a generator suddenly needs more entities.
+
+use std::{collections::BTreeMap, fmt};
+
+use serde::Serialize;
+
+use crate::{
+    abstract_model::{Blueprint, Entity, Wire},
+    diff::json,
+};
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct Stats {
+    /// Count of each entity by name.
+    pub entities: BTreeMap<String, usize>,
+
+    /// Items needed to build the blueprint: entities, floor tiles and circuit wires. Copper
+    /// cables between poles are connected for free.
+    pub materials: BTreeMap<String, usize>,
+
+    /// Size of the area covered by entities and floor tiles in tiles.
+    pub width: u32,
+    pub height: u32,
+
+    /// Circuit networks per colour.
+    pub networks: Networks,
+    pub wires: Wires,
+
+    /// Count of the comparators of decider combinators and the operations of arithmetic
+    /// combinators, e.g. `decider =` or `arithmetic +`.
+    pub operations: BTreeMap<String, usize>,
+}
+
+#[derive(Serialize, Clone, PartialEq, Debug, Default)]
+pub struct Networks {
+    pub red: usize,
+    pub green: usize,
+}
+
+#[derive(Serialize, Clone, PartialEq, Debug, Default)]
+pub struct Wires {
+    pub red: usize,
+    pub green: usize,
+    pub copper: usize,
+}
+
+pub fn stats(blueprint: &Blueprint) -> Stats {
+    let mut entities = BTreeMap::new();
+    let mut operations = BTreeMap::new();
+    let mut wires = Wires::default();
+    let mut tiles: Vec<(i32, i32)> = Vec::new();
+
+    for entity in &blueprint.entities {
+        *entities.entry(entity.name().to_string()).or_insert(0) += 1;
+        tiles.extend(entity.tiles());
+
+        let operation = match entity {
+            Entity::DeciderCombinator { condition, .. } => {
+                Some(("decider", json(&condition.comparator)))
+            }
+            Entity::ArithmeticCombinator { condition, .. } => {
+                Some(("arithmetic", json(&condition.operation)))
+            }
+            _ => None,
+        };
+        if let Some((kind, symbol)) = operation {
+            let symbol = symbol.as_str().unwrap_or("?").to_string();
+            *operations
+                .entry(format!("{} {}", kind, symbol))
+                .or_insert(0) += 1;
+        }
+
+        // Every wire is stored at both of its ends
+        for c in entity.wires() {
+            if (entity.id(), c.from_side) < (c.to.id, c.to.side) {
+                match c.wire {
+                    Wire::Red => wires.red += 1,
+                    Wire::Green => wires.green += 1,
+                }
+            }
+        }
+        match entity {
+            Entity::ElectricPole { id, neighbours, .. } => {
+                wires.copper += neighbours.iter().filter(|&n| n > id).count()
+            }
+            Entity::Unknown(e) => {
+                let number = e.entity_number;
+                wires.copper += e
+                    .neighbours
+                    .iter()
+                    .flatten()
+                    .filter(|&&n| n > number)
+                    .count()
+            }
+            _ => {}
+        }
+    }
+
+    let mut materials = entities.clone();
+    for tile in &blueprint.tiles {
+        *materials.entry(tile_item(&tile.name).into()).or_insert(0) += 1;
+        tiles.push((
+            tile.position.x.floor() as i32,
+            tile.position.y.floor() as i32,
+        ));
+    }
+    for (item, count) in [("red-wire", wires.red), ("green-wire", wires.green)] {
+        if count > 0 {
+            materials.insert(item.into(), count);
+        }
+    }
+
+    let networks = blueprint.circuit_networks();
+    let (width, height) = match (
+        tiles.iter().map(|t| t.0).min(),
+        tiles.iter().map(|t| t.0).max(),
+        tiles.iter().map(|t| t.1).min(),
+        tiles.iter().map(|t| t.1).max(),
+    ) {
+        (Some(left), Some(right), Some(top), Some(bottom)) => {
+            ((right - left + 1) as u32, (bottom - top + 1) as u32)
+        }
+        _ => (0, 0),
+    };
+
+    Stats {
+        entities,
+        materials,
+        width,
+        height,
+        networks: Networks {
+            red: networks.of_colour(Wire::Red).count(),
+            green: networks.of_colour(Wire::Green).count(),
+        },
+        wires,
+        operations,
+    }
+}
+
+/// Item which places the tile.
+fn tile_item(tile: &str) -> &str {
+    match tile {
+        "stone-path" => "stone-brick",
+        "hazard-concrete-left" | "hazard-concrete-right" => "hazard-concrete",
+        "refined-hazard-concrete-left" | "refined-hazard-concrete-right" => {
+            "refined-hazard-concrete"
+        }
+        _ => tile,
+    }
+}
+
+impl fmt::Display for Stats {
+    /// A table with one section per statistic.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let total: usize = self.entities.values().sum();
+        writeln!(
+            f,
+            "{:<28} {:>8}",
+            "size",
+            format!("{}x{}", self.width, self.height)
+        )?;
+        writeln!(f, "{:<28} {:>8}", "total entities", total)?;
+        let sections = [
+            ("entities", &self.entities),
+            ("materials", &self.materials),
+            ("operations", &self.operations),
+        ];
+        for (title, counts) in sections {
+            writeln!(f, "\n{}", title)?;
+            for (name, count) in counts {
+                writeln!(f, "  {:<26} {:>8}", name, count)?;
+            }
+        }
+
+        writeln!(
+            f,
+            "\n{:<28} {:>8} {:>8} {:>8}",
+            "", "red", "green", "copper"
+        )?;
+        writeln!(
+            f,
+            "{:<28} {:>8} {:>8} {:>8}",
+            "networks", self.networks.red, self.networks.green, "-"
+        )?;
+        writeln!(
+            f,
+            "{:<28} {:>8} {:>8} {:>8}",
+            "wires", self.wires.red, self.wires.green, self.wires.copper
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        abstract_model::PoleType,
+        model::{self, Position},
+        primitives,
+    };
+
+    #[test]
+    fn memory_cell_is_counted() {
+        let template = primitives::memory_cell();
+        let mut blueprint = Blueprint::new(template.blueprint().entities.clone());
+        let ids: Vec<usize> = (0..blueprint.entities.len()).collect();
+        blueprint.place_power_poles(&ids, PoleType::Small).unwrap();
+        let stats = stats(&blueprint);
+
+        assert_eq!(3, stats.entities["decider-combinator"]);
+        assert_eq!(1, stats.entities["arithmetic-combinator"]);
+        assert_eq!(1, stats.entities["medium-electric-pole"]);
+        assert_eq!(
+            (3, 5, 0),
+            (stats.wires.red, stats.wires.green, stats.wires.copper)
+        );
+        assert_eq!(Some(&3), stats.materials.get("red-wire"));
+        assert_eq!(Some(&5), stats.materials.get("green-wire"));
+        assert_eq!(3, stats.operations["decider ="]);
+        assert_eq!(1, stats.operations["arithmetic *"]);
+        assert_eq!((3, 2), (stats.networks.red, stats.networks.green));
+        assert_eq!((5, 2), (stats.width, stats.height));
+
+        let json = serde_json::to_value(&stats).unwrap();
+        assert_eq!(stats.wires.copper, json["wires"]["copper"]);
+        assert!(stats.to_string().contains("\nmaterials\n"));
+    }
+
+    #[test]
+    fn wires_of_unknown_entities_are_counted() {
+        let pole = |number: u32, x: f32, neighbour: u32| {
+            Entity::Unknown(model::Entity {
+                entity_number: number,
+                name: "modded-pole".into(),
+                position: Position { x, y: 0.5 },
+                direction: None,
+                connections: None,
+                control_behavior: None,
+                neighbours: Some(vec![neighbour]),
+                tags: None,
+            })
+        };
+        let mut blueprint = Blueprint::new(vec![pole(1, 0.5, 2), pole(2, 4.5, 1)]);
+        blueprint.connect_wire(0, 1, Wire::Green).unwrap();
+        let stats = stats(&blueprint);
+
+        assert_eq!(
+            (0, 1, 1),
+            (stats.wires.red, stats.wires.green, stats.wires.copper)
+        );
+        assert_eq!(1, stats.networks.green);
+    }
+}
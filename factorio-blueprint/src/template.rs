@@ -0,0 +1,392 @@
+//! Blueprints which are reused as building blocks. Instead of relying on the order of the
+//! entities in an exported blueprint, the parts that matter are bound to named anchors:
+//!
+//! ```
+//! use factorio_blueprint::{
+//!     abstract_model::{Blueprint, Wire},
+//!     primitives::{loader_cell, LoaderCell},
+//! };
+//!
+//! let cell = loader_cell();
+//! let mut blueprint = Blueprint::default();
+//! let first = cell.instantiate(&mut blueprint, 0.0, 0.0);
+//! let second = cell.instantiate(&mut blueprint, 0.0, 1.0);
+//! let (a, b) = (first.id(LoaderCell::Decider), second.id(LoaderCell::Decider));
+//! blueprint.connect_wire(a, b, Wire::Red).unwrap();
+//! ```
+
+use std::{collections::BTreeMap, error::Error, fmt};
+
+use crate::{
+    abstract_model::{prototype::Tile, Blueprint, Connector, Entity, Side},
+    blueprint_string_to_raw_model,
+    model::Position,
+    BlueprintError,
+};
+
+/// Finds exactly one entity of a template.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Selector {
+    /// Entity whose centre is at the position, relative to the top left corner of the template.
+    At(Position),
+
+    /// Entity which carries a tag with this key.
+    Tag(String),
+
+    /// Combinator whose condition starts with this signal, e.g. the decider that checks `signal-R`.
+    Reads(String),
+
+    /// The only entity with this name, e.g. `medium-electric-pole`.
+    Named(String),
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::At(p) => write!(f, "entity at ({}, {})", p.x, p.y),
+            Self::Tag(key) => write!(f, "entity tagged '{}'", key),
+            Self::Reads(signal) => write!(f, "combinator reading '{}'", signal),
+            Self::Named(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TemplateError {
+    Blueprint(BlueprintError),
+    NotFound(Selector),
+    Ambiguous(Selector, usize),
+    InvalidSide(Selector, Side),
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Blueprint(cause) => write!(f, "invalid template: {}", cause),
+            Self::NotFound(selector) => write!(f, "template has no {}", selector),
+            Self::Ambiguous(selector, count) => {
+                write!(f, "template has {} matches for {}", count, selector)
+            }
+            Self::InvalidSide(selector, side) => {
+                write!(f, "{} has no wire connection on side {:?}", selector, side)
+            }
+        }
+    }
+}
+
+impl Error for TemplateError {}
+
+impl From<BlueprintError> for TemplateError {
+    fn from(e: BlueprintError) -> Self {
+        TemplateError::Blueprint(e)
+    }
+}
+
+pub type Result<T> = core::result::Result<T, TemplateError>;
+
+/// Blueprint with anchors named by keys of type `K`, usually an enum.
+/// Its top left tile is moved to (0, 0).
+#[derive(Debug)]
+pub struct Template<K> {
+    blueprint: Blueprint,
+    anchors: BTreeMap<K, Connector>,
+}
+
+/// Copy of a template inside of another blueprint.
+#[derive(Clone, Debug)]
+pub struct Instance<K> {
+    /// Ids of the copied entities in the order of the template.
+    pub ids: Vec<usize>,
+    anchors: BTreeMap<K, Connector>,
+}
+
+impl<K: Copy + Ord + fmt::Debug> Template<K> {
+    pub fn new(mut blueprint: Blueprint) -> Self {
+        let tiles = covered_tiles(&blueprint);
+        let left = tiles.iter().map(|t| t.0).min().unwrap_or(0);
+        let top = tiles.iter().map(|t| t.1).min().unwrap_or(0);
+        for entity in &mut blueprint.entities {
+            let position = entity.position_mut();
+            position.x -= left as f32;
+            position.y -= top as f32;
+        }
+        for tile in &mut blueprint.tiles {
+            tile.position.x -= left as f32;
+            tile.position.y -= top as f32;
+        }
+
+        Template {
+            blueprint,
+            anchors: BTreeMap::new(),
+        }
+    }
+
+    /// Loads a template from a blueprint string.
+    pub fn from_blueprint_string(blueprint: &str) -> Result<Self> {
+        let raw = blueprint_string_to_raw_model(blueprint)?;
+        Ok(Self::new(Blueprint::try_from_raw(raw)?))
+    }
+
+    /// Binds `key` to the entity found by the selector (on side one, where wires go by default).
+    pub fn bind(self, key: K, selector: Selector) -> Result<Self> {
+        self.bind_connector(key, selector, Side::One)
+    }
+
+    /// Binds `key` to the given side of the entity found by the selector.
+    pub fn bind_connector(mut self, key: K, selector: Selector, side: Side) -> Result<Self> {
+        let id = self.find(&selector)?;
+        if self.blueprint.entities[id].connections().is_some()
+            && !self.blueprint.entities[id].can_connect(side)
+        {
+            return Err(TemplateError::InvalidSide(selector, side));
+        }
+        self.anchors.insert(key, Connector { id, side });
+        Ok(self)
+    }
+
+    /// Id of the only entity in the template that matches the selector.
+    pub fn find(&self, selector: &Selector) -> Result<usize> {
+        let matches: Vec<usize> = self
+            .blueprint
+            .entities
+            .iter()
+            .filter(|e| self.matches(e, selector))
+            .map(Entity::id)
+            .collect();
+        match matches[..] {
+            [id] => Ok(id),
+            [] => Err(TemplateError::NotFound(selector.clone())),
+            _ => Err(TemplateError::Ambiguous(selector.clone(), matches.len())),
+        }
+    }
+
+    fn matches(&self, entity: &Entity, selector: &Selector) -> bool {
+        match selector {
+            Selector::At(p) => {
+                let position = entity.position();
+                (position.x - p.x).abs() < 0.01 && (position.y - p.y).abs() < 0.01
+            }
+            Selector::Tag(key) => entity.tags().is_some_and(|tags| tags.contains_key(key)),
+            Selector::Reads(signal) => {
+                let first = match entity {
+                    Entity::DeciderCombinator { condition, .. } => &condition.first_signal,
+                    Entity::ArithmeticCombinator { condition, .. } => &condition.first_signal,
+                    _ => return false,
+                };
+                first.as_ref().is_some_and(|s| &s.name == signal)
+            }
+            Selector::Named(name) => entity.name() == name,
+        }
+    }
+
+    pub fn blueprint(&self) -> &Blueprint {
+        &self.blueprint
+    }
+
+    /// Id of the anchor inside of the template. Panics if `key` is not bound.
+    pub fn id(&self, key: K) -> usize {
+        self.connector(key).id
+    }
+
+    /// Connector of the anchor inside of the template. Panics if `key` is not bound.
+    pub fn connector(&self, key: K) -> Connector {
+        anchor(&self.anchors, key)
+    }
+
+    /// Size in tiles (width, height), including the floor tiles.
+    pub fn size(&self) -> (u32, u32) {
+        let tiles = covered_tiles(&self.blueprint);
+        let extent = |values: Vec<i32>| match (values.iter().min(), values.iter().max()) {
+            (Some(min), Some(max)) => (max - min + 1) as u32,
+            _ => 0,
+        };
+        (
+            extent(tiles.iter().map(|t| t.0).collect()),
+            extent(tiles.iter().map(|t| t.1).collect()),
+        )
+    }
+
+    /// Copies the template into `target` with its top left corner at (dx, dy).
+    pub fn instantiate(&self, target: &mut Blueprint, dx: f32, dy: f32) -> Instance<K> {
+        let copy = Blueprint {
+            entities: self.blueprint.entities.clone(),
+            tiles: self.blueprint.tiles.clone(),
+            version: self.blueprint.version,
+            icons: Vec::new(),
+        };
+        let ids = target.append(copy, dx, dy).new_ids();
+        let anchors = self
+            .anchors
+            .iter()
+            .map(|(&key, c)| {
+                let connector = Connector {
+                    id: ids[c.id],
+                    side: c.side,
+                };
+                (key, connector)
+            })
+            .collect();
+        Instance { ids, anchors }
+    }
+}
+
+/// Tiles occupied by entities or covered by floor tiles.
+fn covered_tiles(blueprint: &Blueprint) -> Vec<Tile> {
+    let floor = blueprint
+        .tiles
+        .iter()
+        .map(|t| (t.position.x.floor() as i32, t.position.y.floor() as i32));
+    blueprint
+        .occupied_tiles()
+        .into_iter()
+        .chain(floor)
+        .collect()
+}
+
+impl<K: Copy + Ord + fmt::Debug> Instance<K> {
+    /// Id of the anchor. Panics if `key` is not bound.
+    pub fn id(&self, key: K) -> usize {
+        self.connector(key).id
+    }
+
+    /// Connector of the anchor. Panics if `key` is not bound.
+    pub fn connector(&self, key: K) -> Connector {
+        anchor(&self.anchors, key)
+    }
+}
+
+fn anchor<K: Ord + fmt::Debug>(anchors: &BTreeMap<K, Connector>, key: K) -> Connector {
+    match anchors.get(&key) {
+        Some(connector) => connector.clone(),
+        None => panic!("anchor {:?} is not bound", key),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        model::{self, BlueprintContainer},
+        primitives, raw_model_to_blueprint_string,
+    };
+    use serde_json::{Map, Value};
+
+    #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+    enum Key {
+        Pole,
+        Read,
+        Output,
+    }
+
+    #[test]
+    fn anchors_do_not_depend_on_entity_order() {
+        // The memory cell exported with its entities in reverse order and a tagged pole
+        let mut entities = primitives::memory_cell().blueprint().entities.clone();
+        entities.reverse();
+        let last = entities.len() - 1;
+        for (id, entity) in entities.iter_mut().enumerate() {
+            entity.update_id(id);
+            for c in entity.connections_mut().into_iter().flatten() {
+                c.to.id = last - c.to.id;
+            }
+        }
+        let mut raw: BlueprintContainer = Blueprint::new(entities).into();
+        for entity in &mut raw.blueprint.entities {
+            if entity.name == "medium-electric-pole" {
+                let mut tags = Map::new();
+                tags.insert("bus".into(), Value::Bool(true));
+                entity.tags = Some(tags);
+            }
+        }
+        let string = raw_model_to_blueprint_string(&raw).unwrap();
+
+        let template = Template::from_blueprint_string(&string)
+            .and_then(|t| t.bind(Key::Pole, Selector::Tag("bus".into())))
+            .and_then(|t| t.bind(Key::Read, Selector::Reads("signal-R".into())))
+            .and_then(|t| {
+                t.bind_connector(Key::Output, Selector::Reads("signal-R".into()), Side::Two)
+            })
+            .unwrap();
+        assert_eq!((5, 2), template.size());
+        assert_eq!(
+            template.id(Key::Pole),
+            template
+                .find(&Selector::At(Position { x: 4.5, y: 0.5 }))
+                .unwrap()
+        );
+
+        let mut target = Blueprint::default();
+        template.instantiate(&mut target, 0.0, 0.0);
+        let instance = template.instantiate(&mut target, 10.0, 4.0);
+        assert_eq!(template.id(Key::Read) + 5, instance.id(Key::Read));
+        assert_eq!(Side::Two, instance.connector(Key::Output).side);
+        assert_eq!(
+            &Position { x: 14.5, y: 4.5 },
+            target.entities[instance.id(Key::Pole)].position()
+        );
+
+        // Tags are copied with the entities and written back to the raw model
+        let pole: model::Entity = target.entities[instance.id(Key::Pole)].clone().into();
+        assert_eq!(Some(&Value::Bool(true)), pole.tags.unwrap().get("bus"));
+    }
+
+    #[test]
+    fn selectors_must_match_once() {
+        let template = primitives::memory_cell();
+        let blueprint = Blueprint::new(template.blueprint().entities.clone());
+        let template: Template<Key> = Template::new(blueprint);
+        assert_eq!(
+            TemplateError::Ambiguous(Selector::Named("decider-combinator".into()), 3),
+            template
+                .find(&Selector::Named("decider-combinator".into()))
+                .unwrap_err()
+        );
+        assert_eq!(
+            TemplateError::NotFound(Selector::Tag("pole".into())),
+            template.find(&Selector::Tag("pole".into())).unwrap_err()
+        );
+        assert_eq!(
+            TemplateError::InvalidSide(Selector::Named("medium-electric-pole".into()), Side::Two),
+            template
+                .bind_connector(
+                    Key::Pole,
+                    Selector::Named("medium-electric-pole".into()),
+                    Side::Two
+                )
+                .unwrap_err()
+        );
+    }
+
+    #[test]
+    fn floor_tiles_are_part_of_the_template() {
+        let mut blueprint = Blueprint::new(primitives::memory_cell().blueprint().entities.clone());
+        blueprint.tiles.push(model::Tile {
+            name: "refined-concrete".into(),
+            position: Position { x: -2.0, y: -1.0 },
+        });
+        let template: Template<Key> = Template::new(blueprint);
+        assert_eq!((7, 3), template.size());
+        assert_eq!(
+            Position { x: 0.0, y: 0.0 },
+            template.blueprint().tiles[0].position
+        );
+
+        let mut target = Blueprint::default();
+        template.instantiate(&mut target, 10.0, 4.0);
+        assert_eq!(Position { x: 10.0, y: 4.0 }, target.tiles[0].position);
+    }
+
+    #[test]
+    fn invalid_blueprints_are_rejected() {
+        let blueprint = Blueprint::new(primitives::memory_cell().blueprint().entities.clone());
+        let mut raw: BlueprintContainer = blueprint.into();
+        let decider = &mut raw.blueprint.entities[0];
+        assert_eq!("decider-combinator", decider.name);
+        decider.control_behavior = None;
+        let string = raw_model_to_blueprint_string(&raw).unwrap();
+        assert_eq!(
+            TemplateError::Blueprint(BlueprintError::MissingCondition { entity: 1 }),
+            Template::<Key>::from_blueprint_string(&string).unwrap_err()
+        );
+    }
+}
@@ -0,0 +1,192 @@
+use std::fs;
+
+use clap::{App, Arg, ArgGroup};
+use factorio_blueprint::{
+    abstract_model::{Blueprint, PoleType},
+    display::{self, Bitmap, Colour, DisplayError, TileOptions},
+    model_to_blueprint_string,
+};
+
+#[derive(Debug)]
+enum Source {
+    Image(String),
+    Text(String),
+}
+
+#[derive(Debug)]
+enum Mode {
+    Lamps,
+    Tiles(TileOptions),
+}
+
+#[derive(Debug)]
+struct Arguments {
+    source: Source,
+    mode: Mode,
+    colour: Colour,
+    scale: usize,
+}
+
+fn parse_arguments() -> Option<Arguments> {
+    let matches = App::new("JP Factorio Generate Display")
+        .version("0.1.0")
+        .arg(Arg::new("image")
+            .long("image")
+            .takes_value(true)
+            .help("PNG or PBM image that is shown"))
+        .arg(Arg::new("text")
+            .long("text")
+            .takes_value(true)
+            .help("Text that is shown with the built-in 5x7 font"))
+        .group(ArgGroup::new("source")
+            .args(&["image", "text"])
+            .required(true))
+        .arg(Arg::new("mode")
+            .long("mode")
+            .possible_values(["lamps", "tiles"])
+            .default_value("lamps")
+            .help("Lamps driven by constant combinators or a pattern of floor tiles"))
+        .arg(Arg::new("colour")
+            .long("colour")
+            .default_value("white")
+            .help("Lamp colour of text and PBM images (red, green, blue, yellow, pink, cyan or white)"))
+        .arg(Arg::new("scale")
+            .long("scale")
+            .default_value("1")
+            .help("Lamps or tiles per pixel in each direction"))
+        .arg(Arg::new("tile")
+            .long("tile")
+            .default_value("refined-concrete")
+            .help("Tile of lit pixels in tile mode"))
+        .arg(Arg::new("background")
+            .long("background")
+            .takes_value(true)
+            .help("Tile of the other pixels in tile mode, e.g. landfill"))
+        .get_matches();
+
+    let source = match (matches.value_of("image"), matches.value_of("text")) {
+        (Some(file), _) => Source::Image(file.into()),
+        (_, Some(text)) => Source::Text(text.into()),
+        _ => return None,
+    };
+    let mode = match matches.value_of("mode") {
+        Some("tiles") => Mode::Tiles(TileOptions {
+            foreground: matches.value_of("tile")?.into(),
+            background: matches.value_of("background").map(String::from),
+        }),
+        _ => Mode::Lamps,
+    };
+
+    match (matches.value_of_t::<Colour>("colour"), matches.value_of_t::<usize>("scale")) {
+        (Ok(colour), Ok(scale)) if scale > 0 => Some(Arguments { source, mode, colour, scale }),
+        _ => None,
+    }
+}
+
+fn main() {
+    let args = match parse_arguments() {
+        Some(s) => s,
+        None => {
+            eprintln!("Invalid argument(s). Try --help for more information.");
+            return;
+        }
+    };
+
+    let bitmap = match &args.source {
+        Source::Text(text) => Ok(Bitmap::text(text, args.colour)),
+        Source::Image(path) => match fs::read(path) {
+            Ok(data) => read_image(&data, args.colour),
+            Err(e) => {
+                eprintln!("Cannot read {}: {}", path, e);
+                return;
+            }
+        },
+    };
+
+    match bitmap.and_then(|bitmap| generate_display(&bitmap.scaled(args.scale), &args.mode)) {
+        Ok(blueprint) => println!("{}", model_to_blueprint_string(blueprint).unwrap()),
+        Err(e) => eprintln!("{}", e),
+    }
+}
+
+fn generate_display(bitmap: &Bitmap, mode: &Mode) -> display::Result<Blueprint> {
+    match mode {
+        // Poles stay around the image, substations supply the deepest into it
+        Mode::Lamps => display::lamps(bitmap, PoleType::Substation),
+        Mode::Tiles(options) => display::tiles(bitmap, options),
+    }
+}
+
+/// Reads a PNG or PBM image, the format is detected by its signature.
+fn read_image(data: &[u8], colour: Colour) -> display::Result<Bitmap> {
+    if data.starts_with(b"\x89PNG") {
+        read_png(data).map_err(|e| DisplayError::InvalidImage(e.to_string()))?
+    } else {
+        Bitmap::from_pbm(data, colour)
+    }
+}
+
+fn read_png(data: &[u8]) -> Result<display::Result<Bitmap>, png::DecodingError> {
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)?;
+
+    let pixels = &buffer[..info.buffer_size()];
+    let rgba: Vec<u8> = match info.color_type {
+        png::ColorType::Rgba => pixels.to_vec(),
+        png::ColorType::Rgb => pixels.chunks(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect(),
+        png::ColorType::GrayscaleAlpha => pixels.chunks(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+        _ => pixels.iter().flat_map(|&p| [p, p, p, 255]).collect(),
+    };
+    Ok(Bitmap::from_rgba(info.width as usize, info.height as usize, &rgba))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use factorio_blueprint::abstract_model::{reach::ReachViolation, Entity};
+
+    fn png(width: u32, height: u32, color: png::ColorType, pixels: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut encoder = png::Encoder::new(&mut data, width, height);
+        encoder.set_color(color);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(pixels).unwrap();
+        writer.finish().unwrap();
+        data
+    }
+
+    #[test]
+    fn png_pixels_become_coloured_lamps() {
+        // Red, dark, transparent green and yellow
+        let pixels = [255, 0, 0, 255, 10, 10, 10, 255, 0, 255, 0, 0, 200, 200, 0, 255];
+        let data = png(2, 2, png::ColorType::Rgba, &pixels);
+        let bitmap = read_image(&data, Colour::White).unwrap();
+        assert_eq!(vec![(0, 0, Colour::Red), (1, 1, Colour::Yellow)], bitmap.lit().collect::<Vec<_>>());
+
+        let grey = png(2, 1, png::ColorType::Grayscale, &[0, 255]);
+        let bitmap = read_image(&grey, Colour::Red).unwrap();
+        assert_eq!(vec![(1, 0, Colour::White)], bitmap.lit().collect::<Vec<_>>());
+
+        assert!(matches!(read_image(b"\x89PNG broken", Colour::White), Err(DisplayError::InvalidImage(_))));
+    }
+
+    #[test]
+    fn text_in_both_modes() {
+        let bitmap = Bitmap::text("OK 42", Colour::Green).scaled(2);
+        let blueprint = generate_display(&bitmap, &Mode::Lamps).unwrap();
+        assert_eq!(Vec::<ReachViolation>::new(), blueprint.check_wire_reach());
+        let lamps = blueprint.entities.iter()
+            .filter(|e| matches!(e, Entity::Lamp { .. }))
+            .count();
+        assert_eq!(bitmap.lit().count(), lamps);
+
+        let options = TileOptions { background: Some("landfill".into()), ..TileOptions::default() };
+        let blueprint = generate_display(&bitmap, &Mode::Tiles(options)).unwrap();
+        assert_eq!(bitmap.width() * bitmap.height(), blueprint.tiles.len());
+        assert!(blueprint.entities.is_empty());
+    }
+}